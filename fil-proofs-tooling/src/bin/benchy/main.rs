@@ -3,6 +3,8 @@ use std::io::{stdin, stdout};
 use anyhow::Result;
 use clap::{value_t, App, Arg, SubCommand};
 
+use fil_proofs_tooling::shared::parse_bytes32;
+
 use crate::prodbench::ProdbenchInputs;
 
 mod hash_fns;
@@ -93,6 +95,12 @@ fn main() -> Result<()> {
                     Arg::with_name("extract")
                         .long("extract")
                         .help("Extract data after proving and verifying.")
+                )
+                .arg(
+                    Arg::with_name("replica-id")
+                        .long("replica-id")
+                        .help("The replica id to seal with, as 64 hex digits or base64 (random by default)")
+                        .takes_value(true),
                 );
 
     let window_post_cmd = SubCommand::with_name("window-post")
@@ -256,6 +264,7 @@ fn main() -> Result<()> {
                 no_bench: m.is_present("no-bench"),
                 no_tmp: m.is_present("no-tmp"),
                 partitions: value_t!(m, "partitions", usize)?,
+                replica_id: m.value_of("replica-id").map(parse_bytes32).transpose()?,
                 size: value_t!(m, "size", usize)?,
             })?;
         }
@@ -2,7 +2,7 @@ use std::fs::OpenOptions;
 use std::time::Duration;
 use std::{io, u32};
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use bellperson::util_cs::bench_cs::BenchCS;
 use bellperson::Circuit;
 use chrono::Utc;
@@ -56,6 +56,7 @@ struct Params {
     dump_proofs: bool,
     bench_only: bool,
     hasher: String,
+    replica_id: Option<[u8; 32]>,
 }
 
 impl From<Params> for Inputs {
@@ -97,6 +98,7 @@ where
             dump_proofs,
             bench_only,
             layer_challenges,
+            replica_id,
             ..
         } = &params;
 
@@ -120,7 +122,10 @@ where
             default_rows_to_discard(nodes, BINARY_ARITY),
         );
 
-        let replica_id = H::Domain::random(rng);
+        let replica_id = match replica_id {
+            Some(bytes) => H::Domain::try_from_bytes(bytes).context("invalid replica id")?,
+            None => H::Domain::random(rng),
+        };
         let arbitrary_porep_id = [11; 32];
         let sp = stacked::SetupParams {
             nodes,
@@ -486,6 +491,7 @@ pub struct RunOpts {
     pub no_bench: bool,
     pub no_tmp: bool,
     pub partitions: usize,
+    pub replica_id: Option<[u8; 32]>,
     pub size: usize,
 }
 
@@ -503,6 +509,7 @@ pub fn run(opts: RunOpts) -> anyhow::Result<()> {
         circuit: opts.circuit,
         extract: opts.extract,
         hasher: opts.hasher,
+        replica_id: opts.replica_id,
         samples: 5,
     };
 
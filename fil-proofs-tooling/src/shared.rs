@@ -1,5 +1,6 @@
 use std::io::{BufWriter, Seek, SeekFrom, Write};
 
+use anyhow::{ensure, Context, Result};
 use log::info;
 use rand::RngCore;
 use rayon::prelude::*;
@@ -21,6 +22,29 @@ pub const PROVER_ID: [u8; 32] = [9; 32];
 pub const RANDOMNESS: [u8; 32] = [44; 32];
 pub const TICKET_BYTES: [u8; 32] = [1; 32];
 
+/// Parses a 32 byte value such as a prover or replica id, given as 64 hex digits (optionally
+/// prefixed by `0x`) or as base64.
+pub fn parse_bytes32(input: &str) -> Result<[u8; 32]> {
+    let input = input.trim();
+    let hex_digits = input.trim_start_matches("0x");
+    let bytes = if hex_digits.len() == 64 && hex_digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(hex_digits).with_context(|| format!("invalid hex value {:?}", input))?
+    } else {
+        base64::decode(input)
+            .with_context(|| format!("{:?} is neither 64 hex digits nor base64", input))?
+    };
+    ensure!(
+        bytes.len() == 32,
+        "expected 32 bytes, {:?} decodes to {} bytes",
+        input,
+        bytes.len()
+    );
+
+    let mut value = [0u8; 32];
+    value.copy_from_slice(&bytes);
+    Ok(value)
+}
+
 pub struct PreCommitReplicaOutput<Tree: 'static + MerkleTreeTrait> {
     pub piece_info: Vec<PieceInfo>,
     pub private_replica_info: PrivateReplicaInfo<Tree>,
@@ -240,3 +264,35 @@ pub fn create_replicas<Tree: 'static + MerkleTreeTrait>(
 
     (porep_config, Some((out, seal_pre_commit_outputs)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bytes32() {
+        let mut expected = [0u8; 32];
+        for (i, byte) in expected.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let hex = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        assert_eq!(parse_bytes32(hex).unwrap(), expected);
+        assert_eq!(parse_bytes32(&format!("0x{}", hex)).unwrap(), expected);
+        assert_eq!(
+            parse_bytes32("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap(),
+            expected
+        );
+
+        assert!(
+            parse_bytes32(&hex[2..]).is_err(),
+            "accepted 31 bytes of hex"
+        );
+        assert!(
+            parse_bytes32(&format!("{}00", hex)).is_err(),
+            "accepted 33 bytes of hex"
+        );
+        assert!(parse_bytes32("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHg==").is_err());
+        assert!(parse_bytes32("not a value").is_err());
+    }
+}
@@ -216,6 +216,48 @@ pub fn to_padded_bytes(unpadded_bytes: usize) -> usize {
     FR32_PADDING_MAP.transform_byte_offset(unpadded_bytes, true)
 }
 
+/// Streams `source` into `target`, inserting two zero bits after every 254 bits of raw data,
+/// so every 127 raw bytes become 128 padded bytes. Returns the number of padded bytes written.
+pub fn write_padded<R, W: ?Sized>(source: R, target: &mut W) -> io::Result<u64>
+where
+    R: Read,
+    W: Write,
+{
+    let mut reader = crate::fr32_reader::Fr32Reader::new(source);
+    io::copy(&mut reader, target)
+}
+
+/// Inverse of `write_padded`: streams the padded `source` into `target`, dropping the padding
+/// bits, until `unpadded_len` raw bytes were written. Returns the number of raw bytes written.
+pub fn read_unpadded<R, W: ?Sized>(
+    source: R,
+    target: &mut W,
+    unpadded_len: usize,
+) -> io::Result<usize>
+where
+    R: Read,
+    W: Write,
+{
+    let padded_len = to_padded_bytes(unpadded_len) as u64;
+    let mut writer = crate::fr32_writer::Fr32Writer::new(&mut *target);
+    let copied = io::copy(&mut source.take(padded_len), &mut writer)?;
+    if copied < padded_len {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "padded source holds {} bytes, {} are needed for {} raw bytes",
+                copied, padded_len, unpadded_len
+            ),
+        ));
+    }
+
+    // The last padded byte may hold fewer than 8 bits of the requested data, the bits
+    // left in the writer belong to whatever follows, so they are dropped instead of checked.
+    writer.flush()?;
+
+    Ok(unpadded_len)
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // BitByte represents a size expressed in bytes extended
 // with bit precision, that is, not rounded.
@@ -1022,6 +1064,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_write_padded_read_unpadded() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        // Lengths around element (31.75 bytes) and group (127 bytes) boundaries.
+        for len in &[
+            0, 1, 31, 32, 33, 63, 64, 126, 127, 128, 254, 255, 1016, 1017,
+        ] {
+            let data: Vec<u8> = (0..*len).map(|_| rng.gen()).collect();
+
+            let mut padded = Vec::new();
+            let padded_len = write_padded(io::Cursor::new(&data), &mut padded).unwrap();
+            assert_eq!(padded_len as usize, padded.len());
+            assert_eq!(padded.len(), to_padded_bytes(*len));
+            assert_eq!(
+                padded.clone().into_boxed_slice(),
+                bit_vec_padding(data.clone())
+            );
+
+            let mut unpadded = Vec::new();
+            let written = read_unpadded(io::Cursor::new(&padded), &mut unpadded, *len).unwrap();
+            assert_eq!(written, *len);
+            assert_eq!(data, unpadded);
+        }
+
+        // Reading a prefix of a longer padded stream stops right after the requested data.
+        let data: Vec<u8> = (0..1017).map(|_| rng.gen()).collect();
+        let mut padded = Vec::new();
+        write_padded(io::Cursor::new(&data), &mut padded).unwrap();
+        for len in &[1, 31, 32, 33, 127, 128, 1000] {
+            let mut unpadded = Vec::new();
+            let written = read_unpadded(io::Cursor::new(&padded), &mut unpadded, *len).unwrap();
+            assert_eq!(written, *len);
+            assert_eq!(&data[..*len], &unpadded[..]);
+        }
+
+        let mut padded = Vec::new();
+        write_padded(io::Cursor::new(vec![255u8; 127]), &mut padded).unwrap();
+        assert_eq!(padded.len(), 128);
+
+        // Asking for more raw data than the source holds fails.
+        assert!(read_unpadded(io::Cursor::new(&padded), &mut Vec::new(), 128).is_err());
+    }
+
+    #[test]
+    fn test_write_padded_read_unpadded_sector() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let sector_size = crate::constants::SECTOR_SIZE_8_MIB as usize;
+        let len = to_unpadded_bytes(sector_size as u64) as usize;
+        let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+
+        let mut padded = Vec::new();
+        write_padded(io::Cursor::new(&data), &mut padded).unwrap();
+        assert_eq!(padded.len(), sector_size);
+
+        let mut unpadded = Vec::new();
+        read_unpadded(io::Cursor::new(&padded), &mut unpadded, len).unwrap();
+        assert_eq!(data, unpadded);
+    }
+
     // TODO: Add a test that drops the last part of an element and tries to recover
     // the rest of the data (may already be present in some form in the above tests).
 }
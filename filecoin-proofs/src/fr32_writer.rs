@@ -0,0 +1,216 @@
+use std::io;
+
+/// Bytes in a padded element.
+const TARGET_BYTES: u64 = 32;
+/// Data bits in the last byte of a padded element, the remaining two are padding.
+const LAST_BYTE_DATA_BITS: u32 = 6;
+
+/// The inverse of `Fr32Reader`: padded bytes written to it are forwarded to `target` with the
+/// two padding bits of every 32 byte element dropped.
+///
+/// Bits which do not make up a full byte yet are kept until the next `write`. Once all padded
+/// data was written, `finish` checks that what is left over is the zero fill `Fr32Reader` emits
+/// after the last data bit.
+#[derive(Debug)]
+pub struct Fr32Writer<W> {
+    /// The target of the unpadded data.
+    target: W,
+    /// How many padded bytes were written so far.
+    source_offset: u64,
+    /// Data bits not yet written to the target, from the least significant bit.
+    bits: u16,
+    /// How many bits of `bits` are valid, always less than 8 between writes.
+    bits_len: u32,
+    /// Unpadded bytes of the current `write`, kept around to avoid reallocating.
+    buffer: Vec<u8>,
+}
+
+impl<W: io::Write> Fr32Writer<W> {
+    pub fn new(target: W) -> Self {
+        Fr32Writer {
+            target,
+            source_offset: 0,
+            bits: 0,
+            bits_len: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Flushes the target and returns it, failing if the leftover bits are not all zero,
+    /// which means the padded data was truncated.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.bits != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} trailing bits of padded data do not make up a full byte",
+                    self.bits_len
+                ),
+            ));
+        }
+
+        self.target.flush()?;
+        Ok(self.target)
+    }
+}
+
+impl<W: io::Write> io::Write for Fr32Writer<W> {
+    fn write(&mut self, source: &[u8]) -> io::Result<usize> {
+        self.buffer.clear();
+        self.buffer.reserve(source.len());
+
+        // Unpad into local copies of the state, committed only once the target took the
+        // unpadded bytes, so that a failed write does not consume `source`.
+        let mut source_offset = self.source_offset;
+        let mut bits = self.bits;
+        let mut bits_len = self.bits_len;
+        for &byte in source {
+            let (byte, len) = if source_offset % TARGET_BYTES == TARGET_BYTES - 1 {
+                (byte & 0b0011_1111, LAST_BYTE_DATA_BITS)
+            } else {
+                (byte, 8)
+            };
+            source_offset += 1;
+
+            bits |= u16::from(byte) << bits_len;
+            bits_len += len;
+            if bits_len >= 8 {
+                self.buffer.push(bits as u8);
+                bits >>= 8;
+                bits_len -= 8;
+            }
+        }
+
+        self.target.write_all(&self.buffer)?;
+
+        self.source_offset = source_offset;
+        self.bits = bits;
+        self.bits_len = bits_len;
+
+        Ok(source.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.target.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+
+    use pretty_assertions::assert_eq;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::fr32::write_unpadded;
+    use crate::fr32_reader::Fr32Reader;
+
+    fn pad(data: &[u8]) -> Vec<u8> {
+        let mut padded = Vec::new();
+        Fr32Reader::new(io::Cursor::new(data))
+            .read_to_end(&mut padded)
+            .unwrap();
+        padded
+    }
+
+    #[test]
+    fn test_round_trip_unaligned() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        for &len in &[1, 2, 31, 32, 33, 126, 127, 128, 254, 255, 1000, 127 * 3 + 5] {
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let padded = pad(&data);
+
+            // Write in chunks which do not line up with bytes of data nor with elements.
+            for &chunk_size in &[1, 7, 31, 33, 128, padded.len()] {
+                let mut writer = Fr32Writer::new(Vec::new());
+                for chunk in padded.chunks(chunk_size) {
+                    writer.write_all(chunk).unwrap();
+                }
+                let unpadded = writer.finish().unwrap();
+
+                assert_eq!(
+                    data, unpadded,
+                    "round trip of {} bytes in chunks of {} failed",
+                    len, chunk_size
+                );
+            }
+
+            let mut expected = Vec::new();
+            write_unpadded(&padded, &mut expected, 0, len).unwrap();
+            assert_eq!(expected, data);
+        }
+    }
+
+    #[test]
+    fn test_streamed_round_trip() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let data: Vec<u8> = (0..127 * 100 + 17).map(|_| rng.gen()).collect();
+
+        let mut reader = Fr32Reader::new(io::Cursor::new(&data));
+        let mut writer = Fr32Writer::new(Vec::new());
+        io::copy(&mut reader, &mut writer).unwrap();
+
+        assert_eq!(writer.finish().unwrap(), data);
+    }
+
+    #[test]
+    fn test_trailing_bits() {
+        let data = vec![255u8; 32];
+        let padded = pad(&data);
+
+        // The last padded byte holds the last two data bits, which start the second element,
+        // followed by zero fill.
+        let mut writer = Fr32Writer::new(Vec::new());
+        writer.write_all(&padded[..padded.len() - 1]).unwrap();
+        writer.write_all(&[0b0000_0011]).unwrap();
+        assert_eq!(writer.finish().unwrap(), data);
+
+        let mut writer = Fr32Writer::new(Vec::new());
+        writer.write_all(&padded[..32]).unwrap();
+        writer.write_all(&[0b0100_0011]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    /// Fails every other write, starting with the first one.
+    struct Flaky {
+        written: Vec<u8>,
+        fail: bool,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.fail = !self.fail;
+            if self.fail {
+                return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failed_write_is_retried() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let data: Vec<u8> = (0..127 * 2 + 3).map(|_| rng.gen()).collect();
+        let padded = pad(&data);
+
+        let mut writer = Fr32Writer::new(Flaky {
+            written: Vec::new(),
+            fail: false,
+        });
+        for chunk in padded.chunks(33) {
+            assert!(writer.write(chunk).is_err());
+            assert_eq!(writer.write(chunk).unwrap(), chunk.len());
+        }
+
+        assert_eq!(writer.finish().unwrap().written, data);
+    }
+}
@@ -7,6 +7,7 @@ mod commitment_reader;
 pub mod constants;
 pub mod fr32;
 pub mod fr32_reader;
+pub mod fr32_writer;
 pub mod param;
 pub mod parameters;
 pub mod pieces;
@@ -0,0 +1,29 @@
+#![no_main]
+use ff::PrimeField;
+use libfuzzer_sys::fuzz_target;
+use paired::bls12_381::Fr;
+use storage_proofs_core::fr32::{
+    bytes_into_fr, bytes_into_fr_repr_safe, bytes_into_frs, fr_into_bytes, frs_into_bytes,
+    trim_bytes_to_fr_safe,
+};
+
+fuzz_target!(|data: &[u8]| {
+    // Any 32 bytes that decode are the canonical encoding of their field element.
+    if let Ok(fr) = bytes_into_fr(data) {
+        assert_eq!(fr_into_bytes(&fr), data);
+    }
+
+    if let Ok(frs) = bytes_into_frs(data) {
+        assert_eq!(frs_into_bytes(&frs), data);
+    }
+
+    // Trimming always yields a field element, the same one `bytes_into_fr_repr_safe` reads.
+    if let Ok(trimmed) = trim_bytes_to_fr_safe(data) {
+        let fr = bytes_into_fr(&trimmed).expect("trimmed bytes are not a field element");
+        assert_eq!(fr_into_bytes(&fr), trimmed);
+
+        let safe =
+            Fr::from_repr(bytes_into_fr_repr_safe(data)).expect("safe repr is not a field element");
+        assert_eq!(safe, fr);
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use storage_proofs_core::crypto::sloth;
+use storage_proofs_core::fr32::{bytes_into_fr, fr_into_bytes, trim_bytes_to_fr_safe};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() != 64 {
+        return;
+    }
+
+    let key = bytes_into_fr(&trim_bytes_to_fr_safe(&data[..32]).unwrap()).unwrap();
+    let value = bytes_into_fr(&trim_bytes_to_fr_safe(&data[32..]).unwrap()).unwrap();
+
+    let encoded = sloth::encode(&key, &value);
+    assert_eq!(sloth::decode(&key, &encoded), value);
+    assert_eq!(sloth::encode(&key, &sloth::decode(&key, &value)), value);
+
+    // Decoding bytes read from a replica node is as safe as encoding them.
+    if let Ok(ciphertext) = bytes_into_fr(&data[32..]) {
+        let decoded = sloth::decode(&key, &ciphertext);
+        assert_eq!(fr_into_bytes(&sloth::encode(&key, &decoded)), &data[32..]);
+    }
+});
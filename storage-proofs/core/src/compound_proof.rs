@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use anyhow::{ensure, Context};
 use bellperson::{groth16, Circuit};
 use log::info;
@@ -26,6 +28,20 @@ pub struct PublicParams<'a, S: ProofScheme<'a>> {
     pub priority: bool,
 }
 
+/// Where the time of `CompoundProof::prove_instrumented` was spent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProveTimings {
+    /// Generating and sanity checking the vanilla proofs, which hold the witnesses.
+    pub vanilla_proving: Duration,
+    /// Assigning the vanilla proofs to circuits.
+    pub witness_assignment: Duration,
+    /// Synthesizing the circuits and creating the groth proofs. bellperson synthesizes inside
+    /// `create_random_proof_batch`, so synthesis and the multiexps cannot be timed apart.
+    pub groth_proving: Duration,
+    /// The whole of `prove_instrumented`.
+    pub total: Duration,
+}
+
 /// CircuitComponent exists so parent components can pass private inputs to their subcomponents
 /// when calling CompoundProof::circuit directly. In general, there are no internal private inputs,
 /// and a default value will be passed. CompoundProof::circuit implementations should exhibit
@@ -70,6 +86,17 @@ where
         priv_in: &S::PrivateInputs,
         groth_params: &'b groth16::MappedParameters<Bls12>,
     ) -> Result<MultiProof<'b>> {
+        Self::prove_instrumented(pub_params, pub_in, priv_in, groth_params).map(|(proof, _)| proof)
+    }
+
+    /// Same as `prove`, also returning where the time was spent.
+    fn prove_instrumented<'b>(
+        pub_params: &PublicParams<'a, S>,
+        pub_in: &S::PublicInputs,
+        priv_in: &S::PrivateInputs,
+        groth_params: &'b groth16::MappedParameters<Bls12>,
+    ) -> Result<(MultiProof<'b>, ProveTimings)> {
+        let start = Instant::now();
         let partition_count = Self::partition_count(pub_params);
 
         // This will always run at least once, since there cannot be zero partitions.
@@ -88,18 +115,26 @@ where
         let sanity_check =
             S::verify_all_partitions(&pub_params.vanilla_params, &pub_in, &vanilla_proofs)?;
         ensure!(sanity_check, "sanity check failed");
+        let vanilla_proving = start.elapsed();
 
         info!("snark_proof:start");
-        let groth_proofs = Self::circuit_proofs(
-            pub_in,
-            vanilla_proofs,
-            &pub_params.vanilla_params,
-            groth_params,
-            pub_params.priority,
-        )?;
+        let assignment_start = Instant::now();
+        let circuits = Self::proof_circuits(pub_in, vanilla_proofs, &pub_params.vanilla_params)?;
+        let witness_assignment = assignment_start.elapsed();
+
+        let groth_start = Instant::now();
+        let groth_proofs = Self::groth_proofs(circuits, groth_params, pub_params.priority)?;
+        let groth_proving = groth_start.elapsed();
         info!("snark_proof:finish");
 
-        Ok(MultiProof::new(groth_proofs, &groth_params.vk))
+        let timings = ProveTimings {
+            vanilla_proving,
+            witness_assignment,
+            groth_proving,
+            total: start.elapsed(),
+        };
+
+        Ok((MultiProof::new(groth_proofs, &groth_params.vk), timings))
     }
 
     // verify is equivalent to ProofScheme::verify.
@@ -207,13 +242,23 @@ where
         groth_params: &groth16::MappedParameters<Bls12>,
         priority: bool,
     ) -> Result<Vec<groth16::Proof<Bls12>>> {
-        let mut rng = OsRng;
+        let circuits = Self::proof_circuits(pub_in, vanilla_proofs, pub_params)?;
+        Self::groth_proofs(circuits, groth_params, priority)
+    }
+
+    /// Creates the circuit of every partition from its vanilla proof, the first half of
+    /// `circuit_proofs`.
+    fn proof_circuits(
+        pub_in: &S::PublicInputs,
+        vanilla_proofs: Vec<S::Proof>,
+        pub_params: &S::PublicParams,
+    ) -> Result<Vec<C>> {
         ensure!(
             !vanilla_proofs.is_empty(),
             "cannot create a circuit proof over missing vanilla proofs"
         );
 
-        let circuits = vanilla_proofs
+        vanilla_proofs
             .into_par_iter()
             .enumerate()
             .map(|(k, vanilla_proof)| {
@@ -225,7 +270,17 @@ where
                     Some(k),
                 )
             })
-            .collect::<Result<Vec<_>>>()?;
+            .collect()
+    }
+
+    /// Synthesizes `circuits` and creates their groth proofs, the second half of
+    /// `circuit_proofs`.
+    fn groth_proofs(
+        circuits: Vec<C>,
+        groth_params: &groth16::MappedParameters<Bls12>,
+        priority: bool,
+    ) -> Result<Vec<groth16::Proof<Bls12>>> {
+        let mut rng = OsRng;
 
         let groth_proofs = if priority {
             groth16::create_random_proof_batch_in_priority(circuits, groth_params, &mut rng)?
@@ -1,42 +1,129 @@
-use ff::Field;
-use paired::bls12_381::Fr;
+use ff::PrimeField;
+use paired::bls12_381::{Fr, FrRepr};
+
+// The modulus from `bls12_381::Fr`.
+// The definition of MODULUS and comment defining r come from paired/src/bls_12_381/fr.rs.
+// r = 52435875175126190479447740508185965837690552500527637822603658699938581184513
+const MODULUS: [u64; 4] = [
+    0xffffffff00000001,
+    0x53bda402fffe5bfe,
+    0x3339d80809a1d805,
+    0x73eda753299d7d48,
+];
 
 /// Sloth based encoding.
+///
+/// Runs in constant time with respect to `key` and `plaintext`.
 #[inline]
 pub fn encode(key: &Fr, plaintext: &Fr) -> Fr {
-    let mut ciphertext = *plaintext;
-
-    ciphertext.add_assign(key); // c + k
-    ciphertext
+    // c + k
+    from_limbs(add_mod(
+        &plaintext.into_raw_repr().0,
+        &key.into_raw_repr().0,
+    ))
 }
 
 /// Sloth based decoding.
+///
+/// Runs in constant time with respect to `key` and `ciphertext`.
 #[inline]
 pub fn decode(key: &Fr, ciphertext: &Fr) -> Fr {
-    let mut plaintext = *ciphertext;
+    // c - k
+    from_limbs(sub_mod(
+        &ciphertext.into_raw_repr().0,
+        &key.into_raw_repr().0,
+    ))
+}
+
+/// Encodes then decodes `value` with `key`, returning whether `value` was recovered.
+pub fn roundtrip(key: &Fr, value: &Fr) -> bool {
+    decode(key, &encode(key, value)) == *value
+}
+
+#[inline]
+fn from_limbs(limbs: [u64; 4]) -> Fr {
+    Fr::from_raw_repr(FrRepr(limbs)).expect("reduced value is always in the field")
+}
+
+/// Computes `a + b` for `a, b < r` over Montgomery limbs, without branching on their values.
+#[inline]
+fn add_mod(a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
+    // `r < 2^255`, so the sum never overflows 256 bits.
+    let mut sum = [0u64; 4];
+    let mut carry = 0;
+    for (s, (a, b)) in sum.iter_mut().zip(a.iter().zip(b.iter())) {
+        let (v, c) = adc(*a, *b, carry);
+        *s = v;
+        carry = c;
+    }
 
-    plaintext.sub_assign(key); // c - k
+    let mut reduced = [0u64; 4];
+    let mut borrow = 0;
+    for (r, (s, m)) in reduced.iter_mut().zip(sum.iter().zip(MODULUS.iter())) {
+        let (v, b) = sbb(*s, *m, borrow);
+        *r = v;
+        borrow = b;
+    }
 
-    plaintext
+    // Keep `sum` if subtracting the modulus borrowed, i.e. `sum < r`.
+    select(borrow, &sum, &reduced)
+}
+
+/// Computes `a - b` for `a, b < r` over Montgomery limbs, without branching on their values.
+#[inline]
+fn sub_mod(a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
+    let mut diff = [0u64; 4];
+    let mut borrow = 0;
+    for (d, (a, b)) in diff.iter_mut().zip(a.iter().zip(b.iter())) {
+        let (v, b) = sbb(*a, *b, borrow);
+        *d = v;
+        borrow = b;
+    }
+
+    // Add the modulus back iff the subtraction wrapped.
+    let mask = 0u64.wrapping_sub(borrow);
+    let mut res = [0u64; 4];
+    let mut carry = 0;
+    for (r, (d, m)) in res.iter_mut().zip(diff.iter().zip(MODULUS.iter())) {
+        let (v, c) = adc(*d, *m & mask, carry);
+        *r = v;
+        carry = c;
+    }
+
+    res
+}
+
+/// Returns `a` if `choice == 1` and `b` if `choice == 0`.
+#[inline]
+fn select(choice: u64, a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
+    let mask = 0u64.wrapping_sub(choice);
+    let mut res = [0u64; 4];
+    for (r, (a, b)) in res.iter_mut().zip(a.iter().zip(b.iter())) {
+        *r = (*a & mask) | (*b & !mask);
+    }
+    res
+}
+
+#[inline]
+fn adc(a: u64, b: u64, carry: u64) -> (u64, u64) {
+    let t = u128::from(a) + u128::from(b) + u128::from(carry);
+    (t as u64, (t >> 64) as u64)
+}
+
+#[inline]
+fn sbb(a: u64, b: u64, borrow: u64) -> (u64, u64) {
+    let t = u128::from(a)
+        .wrapping_sub(u128::from(b))
+        .wrapping_sub(u128::from(borrow));
+    (t as u64, (t >> 127) as u64)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ff::PrimeField;
-    use paired::bls12_381::{Fr, FrRepr};
+    use ff::Field;
     use proptest::{prop_compose, proptest};
 
-    // the modulus from `bls12_381::Fr`
-    // The definition of MODULUS and comment defining r come from paired/src/bls_12_381/fr.rs.
-    // r = 52435875175126190479447740508185965837690552500527637822603658699938581184513
-    const MODULUS: [u64; 4] = [
-        0xffffffff00000001,
-        0x53bda402fffe5bfe,
-        0x3339d80809a1d805,
-        0x73eda753299d7d48,
-    ];
-
     #[test]
     fn sloth_bls_12() {
         let key = Fr::from_str("11111111").unwrap();
@@ -67,6 +154,48 @@ mod tests {
         fn sloth_bls_roundtrip(key in arb_fr(), plaintext in arb_fr()) {
             let ciphertext = encode(&key, &plaintext);
             assert_eq!(decode(&key, &ciphertext), plaintext);
+            assert!(roundtrip(&key, &plaintext));
+        }
+
+        #[test]
+        fn sloth_bls_matches_field_arithmetic(key in arb_fr(), value in arb_fr()) {
+            let mut sum = value;
+            sum.add_assign(&key);
+            assert_eq!(encode(&key, &value), sum);
+
+            let mut diff = value;
+            diff.sub_assign(&key);
+            assert_eq!(decode(&key, &value), diff);
+        }
+    }
+
+    #[test]
+    fn sloth_zero_key_is_identity() {
+        let mut max = Fr::zero();
+        max.sub_assign(&Fr::one());
+
+        for value in &[Fr::zero(), Fr::one(), max] {
+            assert_eq!(encode(&Fr::zero(), value), *value);
+            assert_eq!(decode(&Fr::zero(), value), *value);
+            assert!(roundtrip(&Fr::zero(), value));
+        }
+    }
+
+    #[test]
+    fn sloth_bls_edge_cases() {
+        let mut max = Fr::zero();
+        max.sub_assign(&Fr::one());
+
+        for key in &[Fr::zero(), Fr::one(), max] {
+            for value in &[Fr::zero(), Fr::one(), max] {
+                let mut sum = *value;
+                sum.add_assign(key);
+                assert_eq!(encode(key, value), sum);
+
+                let mut diff = *value;
+                diff.sub_assign(key);
+                assert_eq!(decode(key, value), diff);
+            }
         }
     }
 }
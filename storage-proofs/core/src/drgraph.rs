@@ -5,6 +5,7 @@ use anyhow::ensure;
 use generic_array::typenum;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use crate::crypto::{derive_porep_domain_seed, DRSAMPLE_DST};
@@ -45,6 +46,63 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
     /// reasons, so that the vector can be allocated outside this call.
     fn parents(&self, node: usize, parents: &mut [u32]) -> Result<()>;
 
+    /// Returns the parents of `node` as indices, like `parents`, except that nodes without
+    /// parents return an empty vector. Every other node has `degree` parents.
+    ///
+    /// This allocates, use `parents_into` or `parents` on hot paths.
+    fn parent_nodes(&self, node: usize) -> Result<Vec<usize>> {
+        let mut parents = Vec::with_capacity(self.degree());
+        self.parents_into(node, &mut parents)?;
+
+        Ok(parents)
+    }
+
+    /// Replaces the contents of `buf` with the parents of `node`, as returned by
+    /// `parent_nodes`. Once `buf` holds `degree` elements, this does not allocate, so a single
+    /// buffer can be reused to look up the parents of every node.
+    fn parents_into(&self, node: usize, buf: &mut Vec<usize>) -> Result<()> {
+        // Enough for the graphs of this crate, larger degrees fall back to the heap.
+        const STACK_DEGREE: usize = 64;
+
+        let degree = self.degree();
+        let mut stack = [0u32; STACK_DEGREE];
+        let mut heap;
+        let parents = if degree <= STACK_DEGREE {
+            &mut stack[..degree]
+        } else {
+            heap = vec![0; degree];
+            &mut heap[..]
+        };
+        self.parents(node, parents)?;
+
+        buf.clear();
+        if parents.first().map(|p| *p as usize) != Some(node) {
+            buf.extend(parents.iter().map(|p| *p as usize));
+        }
+
+        Ok(())
+    }
+
+    /// Iterates over every node in order, together with its parents as returned by
+    /// `parent_nodes`. Meant for analysing a graph, e.g. its parent distribution, not for
+    /// encoding.
+    fn all_parents(&self) -> Box<dyn Iterator<Item = Result<(usize, Vec<usize>)>> + '_> {
+        Box::new((0..self.size()).map(move |node| Ok((node, self.parent_nodes(node)?))))
+    }
+
+    /// Returns true if all parents of every node precede it, which is what allows encoding
+    /// the nodes in order. Graphs with parents in other layers, such as the expansion parents
+    /// of stacked graphs, do not satisfy this.
+    ///
+    /// This computes the parents of every node, it is meant as a guard for new graph
+    /// constructions rather than for production code paths.
+    fn is_acyclic(&self) -> bool {
+        self.all_parents().all(|entry| match entry {
+            Ok((node, parents)) => parents.iter().all(|parent| *parent < node),
+            Err(_) => false,
+        })
+    }
+
     /// Returns the size of the graph (number of nodes).
     fn size(&self) -> usize;
 
@@ -59,6 +117,14 @@ pub trait Graph<H: Hasher>: ::std::fmt::Debug + Clone + PartialEq + Eq {
     ) -> Result<Self>;
     fn seed(&self) -> [u8; 28];
 
+    /// Returns true if `other` was created with the same parameters and seed, which determine
+    /// the parents of every node. Use this to check a persisted graph against the expected one.
+    fn verify_matches(&self, other: &Self) -> bool {
+        self.size() == other.size()
+            && self.degree() == other.degree()
+            && self.seed() == other.seed()
+    }
+
     /// Creates the encoding key.
     /// The algorithm for that is `Sha256(id | encodedParentNode1 | encodedParentNode1 | ...)`.
     fn create_key(
@@ -76,7 +142,7 @@ pub fn graph_height<U: typenum::Unsigned>(number_of_leafs: usize) -> usize {
 }
 
 /// Bucket sampling algorithm.
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+#[derive(Clone, Debug, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub struct BucketGraph<H: Hasher> {
     nodes: usize,
     base_degree: usize,
@@ -260,6 +326,33 @@ mod tests {
         mm
     }
 
+    #[test]
+    fn graph_serialization_roundtrip() {
+        let graph = BucketGraph::<PedersenHasher>::new(2048, BASE_DEGREE, 0, [123; 32]).unwrap();
+
+        let serialized = serde_json::to_string(&graph).expect("failed to serialize graph");
+        let deserialized: BucketGraph<PedersenHasher> =
+            serde_json::from_str(&serialized).expect("failed to deserialize graph");
+        assert!(graph.verify_matches(&deserialized));
+
+        let mut parents = vec![0; graph.degree()];
+        let mut deserialized_parents = vec![0; deserialized.degree()];
+        for node in 0..graph.size() {
+            graph.parents(node, &mut parents).unwrap();
+            deserialized
+                .parents(node, &mut deserialized_parents)
+                .unwrap();
+            assert_eq!(
+                parents, deserialized_parents,
+                "parents differ for node {}",
+                node
+            );
+        }
+
+        let other = BucketGraph::<PedersenHasher>::new(2048, BASE_DEGREE, 0, [124; 32]).unwrap();
+        assert!(!graph.verify_matches(&other));
+    }
+
     fn graph_bucket<H: Hasher>() {
         let degree = BASE_DEGREE;
         let porep_id = [123; 32];
@@ -298,6 +391,86 @@ mod tests {
         }
     }
 
+    #[test]
+    fn graph_bucket_parent_nodes_form_a_dag() {
+        let degree = BASE_DEGREE;
+
+        for &size in &[4, 16, 256, 2048] {
+            let g = BucketGraph::<PedersenHasher>::new(size, degree, 0, [123; 32]).unwrap();
+            let same = BucketGraph::<PedersenHasher>::new(size, degree, 0, [123; 32]).unwrap();
+
+            assert!(
+                g.parent_nodes(0).unwrap().is_empty(),
+                "first node has parents"
+            );
+
+            for node in 1..size {
+                let parents = g.parent_nodes(node).unwrap();
+                assert_eq!(parents.len(), degree, "wrong parent count for {}", node);
+                assert!(
+                    parents.iter().all(|p| *p < node),
+                    "node {} has parents {:?} which do not precede it",
+                    node,
+                    parents
+                );
+                assert_eq!(parents, g.parent_nodes(node).unwrap());
+                assert_eq!(parents, same.parent_nodes(node).unwrap());
+
+                let mut raw = vec![0; degree];
+                g.parents(node, &mut raw).unwrap();
+                let raw: Vec<usize> = raw.into_iter().map(|p| p as usize).collect();
+                assert_eq!(parents, raw);
+            }
+
+            assert!(g.is_acyclic());
+        }
+    }
+
+    #[test]
+    fn graph_parents_into_reuses_buffer() {
+        let g = BucketGraph::<PedersenHasher>::new(2048, BASE_DEGREE, 0, [123; 32]).unwrap();
+
+        let mut buf = Vec::with_capacity(g.degree());
+        let ptr = buf.as_ptr();
+        let mut raw = vec![0; g.degree()];
+        for node in 0..g.size() {
+            g.parents_into(node, &mut buf).unwrap();
+            assert_eq!(buf, g.parent_nodes(node).unwrap());
+            assert_eq!(
+                buf.as_ptr(),
+                ptr,
+                "reallocated the buffer for node {}",
+                node
+            );
+
+            if node > 0 {
+                g.parents(node, &mut raw).unwrap();
+                let raw: Vec<usize> = raw.iter().map(|p| *p as usize).collect();
+                assert_eq!(buf, raw);
+            }
+        }
+
+        // Whatever the buffer held before is replaced.
+        let mut buf = vec![usize::max_value(); 3 * g.degree()];
+        g.parents_into(0, &mut buf).unwrap();
+        assert!(buf.is_empty());
+        g.parents_into(7, &mut buf).unwrap();
+        assert_eq!(buf, g.parent_nodes(7).unwrap());
+    }
+
+    #[test]
+    fn graph_all_parents_matches_parent_nodes() {
+        let g = BucketGraph::<PedersenHasher>::new(2048, BASE_DEGREE, 0, [123; 32]).unwrap();
+
+        let all: Vec<(usize, Vec<usize>)> = g.all_parents().map(Result::unwrap).collect();
+        assert_eq!(all.len(), g.size());
+
+        for (i, (node, parents)) in all.into_iter().enumerate() {
+            assert_eq!(node, i);
+            assert_eq!(parents, g.parent_nodes(i).unwrap());
+        }
+    }
+
     #[test]
     fn graph_bucket_sha256() {
         graph_bucket::<Sha256Hasher>();
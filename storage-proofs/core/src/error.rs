@@ -25,6 +25,8 @@ pub enum Error {
     MalformedInput,
     #[error("malformed merkle tree")]
     MalformedMerkleTree,
+    #[error("merkle path of depth {} does not match the expected depth {}", _0, _1)]
+    InvalidMerklePathDepth(usize, usize),
     #[error("invalid input size")]
     InvalidInputSize,
     #[error("merkle tree generation error: {}", _0)]
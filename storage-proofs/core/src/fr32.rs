@@ -65,9 +65,35 @@ pub fn bytes_into_fr_repr_safe(r: &[u8]) -> FrRepr {
 }
 
 // Takes an Fr and returns a vector of exactly 32 bytes guaranteed to contain a valid Fr.
+// The bytes are the canonical little-endian encoding, so `bytes_into_fr` recovers the same Fr.
 pub fn fr_into_bytes(fr: &Fr) -> Fr32Vec {
     let mut out = Vec::with_capacity(32);
     fr.into_repr().write_le(&mut out).unwrap();
+    debug_assert_eq!(out.len(), 32);
+    debug_assert!(
+        bytes_into_fr(&out).ok().as_ref() == Some(fr),
+        "non-canonical Fr encoding"
+    );
+    out
+}
+
+// Like `bytes_into_fr`, but for the big-endian encoding of `fr_into_bytes_be`. This is only for
+// interop with tools expecting big-endian values, everything in this crate is little-endian.
+pub fn bytes_into_fr_be(bytes: &[u8]) -> Result<Fr> {
+    ensure!(bytes.len() == 32, Error::BadFrBytes);
+
+    let mut fr_repr = <<Fr as PrimeField>::Repr as Default>::default();
+    fr_repr.read_be(bytes).context(Error::BadFrBytes)?;
+
+    Fr::from_repr(fr_repr).map_err(|_| Error::BadFrBytes.into())
+}
+
+// Takes an Fr and returns its canonical big-endian encoding, the bytes of `fr_into_bytes` in
+// reverse order.
+pub fn fr_into_bytes_be(fr: &Fr) -> Fr32Vec {
+    let mut out = Vec::with_capacity(32);
+    fr.into_repr().write_be(&mut out).unwrap();
+    debug_assert_eq!(out.len(), 32);
     out
 }
 
@@ -108,6 +134,10 @@ pub fn u64_into_fr(n: u64) -> Fr {
 mod tests {
     use super::*;
 
+    use ff::Field;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
     fn bytes_fr_test(bytes: Fr32Ary, expect_success: bool) {
         let b = &bytes[..];
         let fr_result = bytes_into_fr(&b);
@@ -163,6 +193,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fr_into_bytes_round_trip() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        for _ in 0..100 {
+            let fr = Fr::random(rng);
+            let bytes = fr_into_bytes(&fr);
+            assert_eq!(bytes.len(), 32);
+            assert_eq!(bytes_into_fr(&bytes).expect("failed to decode"), fr);
+        }
+    }
+
+    #[test]
+    fn test_bytes_into_fr_rejects_modulus() {
+        let mut modulus = Vec::with_capacity(32);
+        Fr::char().write_le(&mut modulus).unwrap();
+        assert!(
+            bytes_into_fr(&modulus).is_err(),
+            "the modulus must not wrap to zero"
+        );
+
+        let mut above_modulus = modulus.clone();
+        above_modulus[0] += 1;
+        assert!(bytes_into_fr(&above_modulus).is_err());
+
+        let mut below_modulus = modulus;
+        below_modulus[0] -= 1;
+        let max = bytes_into_fr(&below_modulus).expect("modulus - 1 is in the field");
+        let mut expected = Fr::zero();
+        expected.sub_assign(&Fr::one());
+        assert_eq!(max, expected);
+    }
+
+    #[test]
+    fn test_fr_into_bytes_be() {
+        let fr = u64_into_fr(0x0102_0304);
+        let mut le = vec![0u8; 32];
+        le[..4].copy_from_slice(&[4, 3, 2, 1]);
+        let mut be = vec![0u8; 32];
+        be[28..].copy_from_slice(&[1, 2, 3, 4]);
+
+        assert_eq!(fr_into_bytes(&fr), le);
+        assert_eq!(fr_into_bytes_be(&fr), be);
+        assert_eq!(bytes_into_fr(&le).unwrap(), fr);
+        assert_eq!(bytes_into_fr_be(&be).unwrap(), fr);
+        // Read in the wrong order, the value is different.
+        assert_ne!(bytes_into_fr(&be).unwrap(), fr);
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        for _ in 0..100 {
+            let fr = Fr::random(rng);
+            let be = fr_into_bytes_be(&fr);
+            assert_eq!(bytes_into_fr_be(&be).expect("failed to decode"), fr);
+
+            let mut reversed = fr_into_bytes(&fr);
+            reversed.reverse();
+            assert_eq!(be, reversed);
+        }
+
+        let mut modulus = Vec::with_capacity(32);
+        Fr::char().write_be(&mut modulus).unwrap();
+        assert!(bytes_into_fr_be(&modulus).is_err());
+        assert!(bytes_into_fr_be(&be[..31]).is_err());
+    }
+
     fn bytes_into_frs_into_bytes_test(bytes: &Fr32) {
         let frs = bytes_into_frs(bytes).expect("Failed to convert bytes into a `Vec<Fr>`");
         assert!(frs.len() == 3);
@@ -34,3 +34,73 @@ where
 
     Ok(alloc_num)
 }
+
+/// Like `bellperson::gadgets::multipack::pack_into_inputs`, but packs `bits` into public
+/// inputs of `width` bits each, rather than of `E::Fr::CAPACITY` bits.
+pub fn pack_into_inputs_with_width<E, CS>(
+    mut cs: CS,
+    bits: &[Boolean],
+    width: usize,
+) -> Result<(), SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+{
+    assert!(
+        width > 0 && width <= E::Fr::CAPACITY as usize,
+        "invalid packing width {}",
+        width
+    );
+
+    for (i, bits) in bits.chunks(width).enumerate() {
+        let mut num = Num::<E>::zero();
+        let mut coeff = E::Fr::one();
+        for bit in bits {
+            num = num.add_bool_with_coeff(CS::one(), bit, coeff);
+
+            coeff.double();
+        }
+
+        let input = cs.alloc_input(
+            || format!("input {}", i),
+            || {
+                num.get_value()
+                    .ok_or_else(|| SynthesisError::AssignmentMissing)
+            },
+        )?;
+
+        // num * 1 = input
+        cs.enforce(
+            || format!("packing constraint {}", i),
+            |_| num.lc(E::Fr::one()),
+            |lc| lc + CS::one(),
+            |lc| lc + input,
+        );
+    }
+
+    Ok(())
+}
+
+/// Computes the public inputs allocated by `pack_into_inputs_with_width`.
+pub fn compute_multipacking_with_width<E: ScalarEngine>(bits: &[bool], width: usize) -> Vec<E::Fr> {
+    assert!(
+        width > 0 && width <= E::Fr::CAPACITY as usize,
+        "invalid packing width {}",
+        width
+    );
+
+    bits.chunks(width)
+        .map(|bits| {
+            let mut num = E::Fr::zero();
+            let mut coeff = E::Fr::one();
+            for bit in bits {
+                if *bit {
+                    num.add_assign(&coeff);
+                }
+
+                coeff.double();
+            }
+            num
+        })
+        .collect()
+}
@@ -4,6 +4,7 @@ use anyhow::ensure;
 use bellperson::gadgets::boolean::{AllocatedBit, Boolean};
 use bellperson::gadgets::{multipack, num};
 use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use ff::PrimeField;
 use generic_array::typenum::Unsigned;
 use paired::bls12_381::{Bls12, Fr};
 
@@ -11,6 +12,7 @@ use crate::compound_proof::{CircuitComponent, CompoundProof};
 use crate::error::Result;
 use crate::gadgets::constraint;
 use crate::gadgets::insertion::insert;
+use crate::gadgets::multipack::pack_into_inputs_with_width;
 use crate::gadgets::variables::Root;
 use crate::hasher::{HashFunction, Hasher, PoseidonArity};
 use crate::merkle::{base_path_length, MerkleProofTrait, MerkleTreeTrait};
@@ -127,9 +129,19 @@ struct PathElement<H: Hasher, Arity: 'static + PoseidonArity> {
 
 impl<H: Hasher, Arity: 'static + PoseidonArity> SubPath<H, Arity> {
     fn synthesize<CS: ConstraintSystem<Bls12>>(
+        self,
+        cs: CS,
+        cur: num::AllocatedNum<Bls12>,
+    ) -> Result<(num::AllocatedNum<Bls12>, Vec<Boolean>), SynthesisError> {
+        self.synthesize_at(cs, cur, 0)
+    }
+
+    /// Ascends the path from `cur`, which is a node at `height` levels above the leaves.
+    fn synthesize_at<CS: ConstraintSystem<Bls12>>(
         self,
         mut cs: CS,
         mut cur: num::AllocatedNum<Bls12>,
+        height: usize,
     ) -> Result<(num::AllocatedNum<Bls12>, Vec<Boolean>), SynthesisError> {
         let arity = Arity::to_usize();
 
@@ -144,10 +156,15 @@ impl<H: Hasher, Arity: 'static + PoseidonArity> SubPath<H, Arity> {
 
         let mut auth_path_bits = Vec::with_capacity(self.path.len());
 
-        for (i, path_element) in self.path.into_iter().enumerate() {
+        for (i, path_element) in (height..).zip(self.path.into_iter()) {
             let path_hashes = path_element.hashes;
             let optional_index = path_element.index; // Optional because of Bellman blank-circuit construction mechanics.
 
+            // The depth is taken from the path, but every level must hold all siblings of the node.
+            if path_hashes.len() != arity - 1 {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+
             let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
 
             let mut index_bits = Vec::with_capacity(index_bit_count);
@@ -335,6 +352,18 @@ impl<'a, Tree: MerkleTreeTrait> Circuit<Bls12> for PoRCircuit<Tree> {
     ///
     /// Note: All public inputs must be provided as `E::Fr`.
     fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        self.synthesize_root(cs, Fr::CAPACITY as usize).map(|_| ())
+    }
+}
+
+impl<'a, Tree: MerkleTreeTrait> PoRCircuit<Tree> {
+    /// Synthesizes the circuit, returning the root computed from the authentication path and
+    /// the auth path bits. The bits are packed into public inputs of `input_bits` bits each.
+    fn synthesize_root<CS: ConstraintSystem<Bls12>>(
+        self,
+        cs: &mut CS,
+        input_bits: usize,
+    ) -> Result<(num::AllocatedNum<Bls12>, Vec<Boolean>), SynthesisError> {
         let value = self.value;
         let auth_path = self.auth_path;
         let root = self.root;
@@ -387,7 +416,7 @@ impl<'a, Tree: MerkleTreeTrait> Circuit<Bls12> for PoRCircuit<Tree> {
             auth_path_bits.extend(sub_auth_path_bits);
             auth_path_bits.extend(top_auth_path_bits);
 
-            multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+            pack_into_inputs_with_width(cs.namespace(|| "path"), &auth_path_bits, input_bits)?;
             {
                 // Validate that the root of the merkle tree that we calculated is the same as the input.
                 let rt = root.allocated(cs.namespace(|| "root_value"))?;
@@ -399,20 +428,57 @@ impl<'a, Tree: MerkleTreeTrait> Circuit<Bls12> for PoRCircuit<Tree> {
                 }
             }
 
-            Ok(())
+            Ok((computed_root, auth_path_bits))
         }
     }
-}
 
-impl<'a, Tree: MerkleTreeTrait> PoRCircuit<Tree> {
+    /// Synthesizes a proof of retrievability of `value` against `root`.
+    ///
+    /// Returns the root computed from the authentication path, which callers can use to
+    /// constrain several proofs to the same tree.
     #[allow(clippy::type_complexity)]
     pub fn synthesize<CS>(
+        cs: CS,
+        value: Root<Bls12>,
+        auth_path: AuthPath<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        root: Root<Bls12>,
+        private: bool,
+    ) -> Result<num::AllocatedNum<Bls12>, SynthesisError>
+    where
+        CS: ConstraintSystem<Bls12>,
+    {
+        Self::synthesize_with_input_bits(cs, value, auth_path, root, private, Fr::CAPACITY as usize)
+    }
+
+    /// Like `synthesize`, but packs the auth path bits into public inputs of `input_bits` bits
+    /// each, see `compute_multipacking_with_width`.
+    #[allow(clippy::type_complexity)]
+    pub fn synthesize_with_input_bits<CS>(
+        mut cs: CS,
+        value: Root<Bls12>,
+        auth_path: AuthPath<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        root: Root<Bls12>,
+        private: bool,
+        input_bits: usize,
+    ) -> Result<num::AllocatedNum<Bls12>, SynthesisError>
+    where
+        CS: ConstraintSystem<Bls12>,
+    {
+        Self::synthesize_with_path_bits(cs, value, auth_path, root, private, input_bits)
+            .map(|(computed_root, _)| computed_root)
+    }
+
+    /// Like `synthesize_with_input_bits`, but also returns the auth path bits, least
+    /// significant first, so that callers can bind the challenged index to other values.
+    #[allow(clippy::type_complexity)]
+    pub fn synthesize_with_path_bits<CS>(
         mut cs: CS,
         value: Root<Bls12>,
         auth_path: AuthPath<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
         root: Root<Bls12>,
         private: bool,
-    ) -> Result<(), SynthesisError>
+        input_bits: usize,
+    ) -> Result<(num::AllocatedNum<Bls12>, Vec<Boolean>), SynthesisError>
     where
         CS: ConstraintSystem<Bls12>,
     {
@@ -424,7 +490,144 @@ impl<'a, Tree: MerkleTreeTrait> PoRCircuit<Tree> {
             _tree: PhantomData,
         };
 
-        por.synthesize(&mut cs)
+        por.synthesize_root(&mut cs, input_bits)
+    }
+
+    /// Like `synthesize`, but for trees built with `create_base_merkle_tree_with_leaf_domain`:
+    /// if `leaf_hash_domain` is set, `value` is first hashed with it, as a constant, to get
+    /// the leaf.
+    #[allow(clippy::type_complexity)]
+    pub fn synthesize_with_leaf_domain<CS>(
+        mut cs: CS,
+        value: Root<Bls12>,
+        auth_path: AuthPath<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        root: Root<Bls12>,
+        private: bool,
+        leaf_hash_domain: Option<Fr>,
+    ) -> Result<num::AllocatedNum<Bls12>, SynthesisError>
+    where
+        CS: ConstraintSystem<Bls12>,
+    {
+        let value = match leaf_hash_domain {
+            Some(domain) => {
+                let value_num = value.allocated(cs.namespace(|| "unhashed_value"))?;
+                let domain_num =
+                    num::AllocatedNum::alloc(cs.namespace(|| "leaf_hash_domain"), || Ok(domain))?;
+                cs.enforce(
+                    || "leaf_hash_domain is constant",
+                    |lc| lc + domain_num.get_variable(),
+                    |lc| lc + CS::one(),
+                    |lc| lc + (domain, CS::one()),
+                );
+
+                let leaf = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                    cs.namespace(|| "hash_leaf"),
+                    &domain_num,
+                    &value_num,
+                )?;
+                Root::Var(leaf)
+            }
+            None => value,
+        };
+
+        Self::synthesize(cs, value, auth_path, root, private)
+    }
+
+    /// Synthesizes proofs of retrievability of `values` in a tree without sub or top trees,
+    /// where all values are leaves of the same subtree: their paths share the top
+    /// `shared_levels` levels. The shared levels are only ascended once, from the subtree root
+    /// that all remaining paths are constrained to reach, which saves the hashes of
+    /// `shared_levels` levels for every value but the first. The shared levels of the other
+    /// paths are ignored.
+    ///
+    /// Like `synthesize`, the auth path bits of every value are exposed as public inputs, and
+    /// the root is exposed unless `private` is set. `shared_levels` shapes the circuit, so it
+    /// must not depend on the witness.
+    #[allow(clippy::type_complexity)]
+    pub fn synthesize_shared<CS>(
+        mut cs: CS,
+        values: Vec<Root<Bls12>>,
+        auth_paths: Vec<
+            AuthPath<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+        >,
+        shared_levels: usize,
+        root: Root<Bls12>,
+        private: bool,
+    ) -> Result<num::AllocatedNum<Bls12>, SynthesisError>
+    where
+        CS: ConstraintSystem<Bls12>,
+    {
+        assert_eq!(
+            Tree::SubTreeArity::to_usize(),
+            0,
+            "sub trees are not supported"
+        );
+        assert_eq!(
+            Tree::TopTreeArity::to_usize(),
+            0,
+            "top trees are not supported"
+        );
+        assert!(!values.is_empty(), "no values to prove");
+        assert_eq!(values.len(), auth_paths.len());
+
+        let mut leaves = values.into_iter().zip(auth_paths.into_iter()).enumerate();
+        let mut subtree_paths = Vec::new();
+
+        // The first path ascends all the way to the root.
+        let (_, (value, auth_path)) = leaves.next().expect("no values to prove");
+        let mut base = auth_path.base.path;
+        if shared_levels > base.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let subtree_height = base.len() - shared_levels;
+        let shared = SubPath::<Tree::Hasher, Tree::Arity> {
+            path: base.split_off(subtree_height),
+        };
+
+        let subtree_root = {
+            let mut cs = cs.namespace(|| "value_0");
+            let value_num = value.allocated(cs.namespace(|| "value"))?;
+            let (subtree_root, bits) = SubPath::<Tree::Hasher, Tree::Arity> { path: base }
+                .synthesize(&mut cs, value_num)?;
+            subtree_paths.push(bits);
+            subtree_root
+        };
+
+        let (computed_root, shared_bits) = shared.synthesize_at(
+            cs.namespace(|| "shared"),
+            subtree_root.clone(),
+            subtree_height,
+        )?;
+
+        for (i, (value, auth_path)) in leaves {
+            let mut cs = cs.namespace(|| format!("value_{}", i));
+
+            let mut base = auth_path.base.path;
+            if base.len() != subtree_height + shared_levels {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            base.truncate(subtree_height);
+
+            let value_num = value.allocated(cs.namespace(|| "value"))?;
+            let (cur, bits) = SubPath::<Tree::Hasher, Tree::Arity> { path: base }
+                .synthesize(&mut cs, value_num)?;
+            constraint::equal(&mut cs, || "enforce subtree root", &cur, &subtree_root);
+            subtree_paths.push(bits);
+        }
+
+        for (i, mut bits) in subtree_paths.into_iter().enumerate() {
+            bits.extend_from_slice(&shared_bits);
+            multipack::pack_into_inputs(cs.namespace(|| format!("path_{}", i)), &bits)?;
+        }
+
+        let rt = root.allocated(cs.namespace(|| "root_value"))?;
+        constraint::equal(&mut cs, || "enforce root is correct", &computed_root, &rt);
+
+        if !private {
+            rt.inputize(cs.namespace(|| "root"))?;
+        }
+
+        Ok(computed_root)
     }
 }
 
@@ -437,22 +640,23 @@ mod tests {
     use generic_array::typenum;
     use merkletree::store::VecStore;
     use pretty_assertions::assert_eq;
-    use rand::SeedableRng;
+    use rand::{Rng, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
     use crate::compound_proof;
-    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes, u64_into_fr};
     use crate::hasher::{
         Blake2sHasher, Domain, Hasher, PedersenHasher, PoseidonHasher, Sha256Hasher,
     };
     use crate::merkle::{
-        create_base_merkle_tree, generate_tree, get_base_tree_count, MerkleProofTrait,
+        create_base_merkle_tree, create_base_merkle_tree_with_leaf_domain, generate_tree,
+        get_base_tree_count, hash_leaf_with_domain, MerkleProof, MerkleProofTrait,
         MerkleTreeWrapper, ResTree,
     };
     use crate::por;
     use crate::proof::NoRequirements;
     use crate::proof::ProofScheme;
-    use crate::util::data_at_node;
+    use crate::util::{data_at_node, NODE_SIZE};
     use bellperson::util_cs::metric_cs::MetricCS;
     use bellperson::util_cs::test_cs::TestConstraintSystem;
 
@@ -525,6 +729,433 @@ mod tests {
         assert!(cs.verify(&inputs));
     }
 
+    fn por_returned_roots<Tree: 'static + MerkleTreeTrait>(same_tree: bool) -> bool {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 64 * get_base_tree_count::<Tree>();
+        let (_, tree_a) = generate_tree::<Tree, _>(rng, leaves, None);
+        let (_, tree_b) = generate_tree::<Tree, _>(rng, leaves, None);
+        let other_tree = if same_tree { &tree_a } else { &tree_b };
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let mut roots = Vec::new();
+        for (i, (tree, challenge)) in [(&tree_a, 3), (other_tree, 7)].iter().enumerate() {
+            let proof = tree.gen_proof(*challenge).unwrap();
+            let root = PoRCircuit::<ResTree<Tree>>::synthesize(
+                cs.namespace(|| format!("por_{}", i)),
+                Root::Val(Some(proof.leaf().into())),
+                proof.as_options().into(),
+                Root::Val(Some(tree.root().into())),
+                true,
+            )
+            .expect("circuit synthesis failed");
+            roots.push(root);
+        }
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        constraint::equal(&mut cs, || "same root", &roots[0], &roots[1]);
+        cs.is_satisfied()
+    }
+
+    #[test]
+    fn por_returned_roots_bind_proofs_to_one_tree() {
+        type Tree = TestTree<PoseidonHasher, typenum::U4>;
+
+        assert!(por_returned_roots::<Tree>(true));
+        assert!(!por_returned_roots::<Tree>(false));
+    }
+
+    #[test]
+    fn por_circuit_leaf_hash_domain() {
+        type Tree = TestTree<PoseidonHasher, typenum::U4>;
+        type D = <PoseidonHasher as Hasher>::Domain;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 64;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let data_domain: D = u64_into_fr(1).into();
+        let replica_domain: D = u64_into_fr(2).into();
+
+        // Both trees commit to the same data, under different domains.
+        let data_tree = create_base_merkle_tree_with_leaf_domain::<Tree>(
+            None,
+            leaves,
+            &data,
+            Some(data_domain),
+        )
+        .unwrap();
+        let replica_tree = create_base_merkle_tree_with_leaf_domain::<Tree>(
+            None,
+            leaves,
+            &data,
+            Some(replica_domain),
+        )
+        .unwrap();
+        assert_ne!(data_tree.root(), replica_tree.root());
+
+        let value: D = bytes_into_fr(data_at_node(&data, 5).unwrap())
+            .unwrap()
+            .into();
+        let path = por::gen_path(&data_tree, 5).expect("failed to generate path");
+        assert_eq!(
+            path.leaf(),
+            hash_leaf_with_domain::<PoseidonHasher>(&data_domain, &value)
+        );
+        assert!(por::verify_path(&data_tree.root(), &path.leaf(), &path));
+        assert!(!por::verify_path(&replica_tree.root(), &path.leaf(), &path));
+
+        let satisfied = |domain: D, root: D| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Tree>::synthesize_with_leaf_domain(
+                cs.namespace(|| "por"),
+                Root::Val(Some(value.into())),
+                path.as_options().into(),
+                Root::Val(Some(root.into())),
+                true,
+                Some(domain.into()),
+            )
+            .expect("circuit synthesis failed");
+            cs.is_satisfied()
+        };
+
+        assert!(satisfied(data_domain, data_tree.root()));
+        assert!(!satisfied(replica_domain, replica_tree.root()));
+        assert!(!satisfied(data_domain, replica_tree.root()));
+    }
+
+    fn por_verify_path_agrees_with_circuit<Tree: 'static + MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 64 * get_base_tree_count::<Tree>();
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, leaves, data.as_slice()).unwrap();
+        let root = tree.root();
+
+        let path = por::gen_path(&tree, 5).expect("failed to generate path");
+        let leaf = path.leaf();
+        assert!(por::verify_path(&root, &leaf, &path));
+
+        let wrong_leaf = <Tree::Hasher as Hasher>::Domain::random(rng);
+        assert!(!por::verify_path(&root, &wrong_leaf, &path));
+
+        let public_inputs = por::PublicInputs {
+            challenge: path.path_index(),
+            commitment: Some(root),
+        };
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params: por::SetupParams {
+                leaves,
+                private: false,
+            },
+            partitions: None,
+            priority: false,
+        };
+        let public_params = PoRCompound::<Tree>::setup(&setup_params).expect("setup failed");
+        let private_inputs = por::PrivateInputs::<Tree>::new(leaf, &tree);
+
+        let (circuit, inputs) =
+            PoRCompound::<Tree>::circuit_for_test(&public_params, &public_inputs, &private_inputs)
+                .unwrap();
+
+        let mut cs = TestConstraintSystem::new();
+        circuit.synthesize(&mut cs).expect("failed to synthesize");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert!(cs.verify(&inputs), "failed to verify inputs");
+    }
+
+    #[test]
+    fn por_verify_path_agrees_with_circuit_pedersen_base_2() {
+        por_verify_path_agrees_with_circuit::<TestTree<PedersenHasher, typenum::U2>>();
+    }
+
+    #[test]
+    fn por_verify_path_agrees_with_circuit_poseidon_base_4() {
+        por_verify_path_agrees_with_circuit::<TestTree<PoseidonHasher, typenum::U4>>();
+    }
+
+    fn por_circuit_for_depth(depth: usize) {
+        type Tree = TestTree<PedersenHasher, typenum::U2>;
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 1 << depth;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, leaves, data.as_slice()).unwrap();
+
+        for challenge in 0..leaves {
+            let proof = tree.gen_proof(challenge).unwrap();
+            let path = proof.as_options();
+            assert_eq!(path.len(), depth);
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let root = PoRCircuit::<Tree>::synthesize(
+                cs.namespace(|| "por"),
+                Root::Val(Some(proof.leaf().into())),
+                path.into(),
+                Root::Val(Some(tree.root().into())),
+                false,
+            )
+            .expect("failed to synthesize");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(root.get_value(), Some(tree.root().into()));
+
+            let expected_inputs = PoRCompound::<Tree>::generate_public_inputs(
+                &por::PublicInputs {
+                    challenge,
+                    commitment: Some(tree.root()),
+                },
+                &por::PublicParams {
+                    leaves,
+                    private: false,
+                },
+                None,
+            )
+            .unwrap();
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+        }
+    }
+
+    #[test]
+    fn por_circuit_depth_3() {
+        por_circuit_for_depth(3);
+    }
+
+    #[test]
+    fn por_circuit_depth_1() {
+        por_circuit_for_depth(1);
+    }
+
+    /// Leaves holding `leaf_size` bytes of data each, zero padded to a domain element.
+    fn por_circuit_for_leaf_size(leaf_size: usize) {
+        type Tree = TestTree<PedersenHasher, typenum::U2>;
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 16;
+        let raw: Vec<u8> = (0..leaves * leaf_size).map(|_| rng.gen()).collect();
+        let data: Vec<u8> = raw
+            .chunks(leaf_size)
+            .flat_map(|chunk| {
+                let mut leaf = vec![0u8; NODE_SIZE];
+                leaf[..leaf_size].copy_from_slice(chunk);
+                leaf
+            })
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, leaves, data.as_slice()).unwrap();
+
+        for challenge in 0..leaves {
+            let proof = tree.gen_proof(challenge).unwrap();
+            assert_eq!(
+                &AsRef::<[u8]>::as_ref(&proof.leaf())[..leaf_size],
+                &raw[challenge * leaf_size..(challenge + 1) * leaf_size]
+            );
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Tree>::synthesize(
+                cs.namespace(|| "por"),
+                Root::Val(Some(proof.leaf().into())),
+                proof.as_options().into(),
+                Root::Val(Some(tree.root().into())),
+                false,
+            )
+            .expect("failed to synthesize");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+        }
+    }
+
+    #[test]
+    fn por_circuit_leaf_size_16() {
+        por_circuit_for_leaf_size(16);
+    }
+
+    #[test]
+    fn por_circuit_leaf_size_31() {
+        por_circuit_for_leaf_size(31);
+    }
+
+    #[test]
+    fn por_circuit_shared_subtree() {
+        type Tree = TestTree<PedersenHasher, typenum::U2>;
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 16;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, leaves, data.as_slice()).unwrap();
+
+        // Leaves 4..8 form a subtree of height 2, their paths share the top 2 of 4 levels.
+        let challenges = [4, 5, 6, 7];
+        let proofs: Vec<_> = challenges
+            .iter()
+            .map(|challenge| tree.gen_proof(*challenge).unwrap())
+            .collect();
+        let values = || {
+            proofs
+                .iter()
+                .map(|proof| Root::Val(Some(proof.leaf().into())))
+                .collect::<Vec<_>>()
+        };
+        let paths = || {
+            proofs
+                .iter()
+                .map(|proof| proof.as_options().into())
+                .collect::<Vec<AuthPath<PedersenHasher, typenum::U2, typenum::U0, typenum::U0>>>()
+        };
+
+        let independent = {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let root = Root::Val(Some(tree.root().into()))
+                .allocated(cs.namespace(|| "root"))
+                .unwrap();
+            for (i, (value, path)) in values().into_iter().zip(paths()).enumerate() {
+                PoRCircuit::<Tree>::synthesize(
+                    cs.namespace(|| format!("por_{}", i)),
+                    value,
+                    path,
+                    Root::Var(root.clone()),
+                    true,
+                )
+                .expect("failed to synthesize");
+            }
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            cs
+        };
+
+        let shared = |shared_levels| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let root = PoRCircuit::<Tree>::synthesize_shared(
+                cs.namespace(|| "por"),
+                values(),
+                paths(),
+                shared_levels,
+                Root::Val(Some(tree.root().into())),
+                true,
+            )
+            .expect("failed to synthesize");
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(root.get_value(), Some(tree.root().into()));
+            cs
+        };
+
+        let unshared = shared(0);
+        assert_eq!(unshared.num_constraints(), independent.num_constraints());
+
+        let shared = shared(2);
+        assert_eq!(shared.get_inputs().len(), independent.get_inputs().len());
+        for ((input, _), (expected, _)) in shared.get_inputs().iter().zip(independent.get_inputs())
+        {
+            assert_eq!(*input, expected);
+        }
+        assert!(shared.num_constraints() < independent.num_constraints());
+
+        // A leaf outside of the subtree cannot share its top levels.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let outside = tree.gen_proof(8).unwrap();
+        let mut values = values();
+        let mut paths = paths();
+        values[3] = Root::Val(Some(outside.leaf().into()));
+        paths[3] = outside.as_options().into();
+        PoRCircuit::<Tree>::synthesize_shared(
+            cs.namespace(|| "por"),
+            values,
+            paths,
+            2,
+            Root::Val(Some(tree.root().into())),
+            true,
+        )
+        .expect("failed to synthesize");
+        assert!(!cs.is_satisfied(), "proved a leaf outside of the subtree");
+    }
+
+    #[test]
+    fn por_circuit_accepts_deserialized_proof() {
+        type Tree = TestTree<PoseidonHasher, typenum::U4>;
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 64;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, leaves, data.as_slice()).unwrap();
+
+        let proof = tree.gen_proof(42).unwrap();
+        let decoded = MerkleProof::<PoseidonHasher, typenum::U4>::from_bytes(&proof.to_bytes())
+            .expect("failed to deserialize proof");
+        assert_eq!(decoded.as_options(), proof.as_options());
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Tree>::synthesize(
+            cs.namespace(|| "por"),
+            Root::Val(Some(decoded.leaf().into())),
+            decoded.as_options().into(),
+            Root::Val(Some(decoded.root().into())),
+            false,
+        )
+        .expect("failed to synthesize");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        let inputs = PoRCompound::<Tree>::generate_public_inputs(
+            &por::PublicInputs {
+                challenge: 42,
+                commitment: Some(tree.root()),
+            },
+            &por::PublicParams {
+                leaves,
+                private: false,
+            },
+            None,
+        )
+        .unwrap();
+        assert!(cs.verify(&inputs), "failed to verify inputs");
+    }
+
+    #[test]
+    fn por_circuit_rejects_malformed_path() {
+        type Tree = TestTree<PedersenHasher, typenum::U2>;
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let leaves = 8;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let tree = create_base_merkle_tree::<Tree>(None, leaves, data.as_slice()).unwrap();
+        let proof = tree.gen_proof(3).unwrap();
+
+        let malformed = |path: Vec<(Vec<Option<Fr>>, Option<usize>)>| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Tree>::synthesize(
+                cs.namespace(|| "por"),
+                Root::Val(Some(proof.leaf().into())),
+                path.into(),
+                Root::Val(Some(tree.root().into())),
+                false,
+            )
+            .is_err()
+        };
+
+        let mut extra_sibling = proof.as_options();
+        extra_sibling[1].0.push(Some(Fr::random(rng)));
+        assert!(
+            malformed(extra_sibling),
+            "accepted a level with two siblings"
+        );
+
+        let mut missing_sibling = proof.as_options();
+        missing_sibling[2].0.clear();
+        assert!(
+            malformed(missing_sibling),
+            "accepted a level without a sibling"
+        );
+    }
+
     #[test]
     fn test_por_circuit_pedersen_base_2() {
         test_por_circuit::<TestTree<PedersenHasher, typenum::U2>>(3, 8_247);
@@ -0,0 +1,249 @@
+use std::hash::Hasher as StdHasher;
+
+use bellperson::gadgets::{boolean, num};
+use bellperson::{ConstraintSystem, SynthesisError};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use merkletree::hash::{Algorithm as LightAlgorithm, Hashable};
+use paired::bls12_381::{Bls12, Fr};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::sloth;
+use crate::error::Result;
+use crate::fr32::bytes_into_fr_repr_safe;
+use crate::hasher::{HashFunction, Hasher, PoseidonArity, PoseidonDomain};
+
+/// A hasher which "hashes" field elements by adding them up, for testing the wiring of
+/// circuits without paying for the constraints of a real hash. The sum of the children of a
+/// merkle tree node is a single linear constraint.
+///
+/// There is nothing hard to invert about it, never use it outside of tests.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityHasher {}
+
+impl Hasher for IdentityHasher {
+    type Domain = PoseidonDomain;
+    type Function = IdentityFunction;
+
+    fn name() -> String {
+        "identity_hasher".into()
+    }
+
+    #[inline]
+    fn sloth_encode(key: &Self::Domain, ciphertext: &Self::Domain) -> Result<Self::Domain> {
+        let key = Fr::from_repr(key.0)?;
+        let ciphertext = Fr::from_repr(ciphertext.0)?;
+        Ok(sloth::encode(&key, &ciphertext).into())
+    }
+
+    #[inline]
+    fn sloth_decode(key: &Self::Domain, ciphertext: &Self::Domain) -> Result<Self::Domain> {
+        let key = Fr::from_repr(key.0)?;
+        let ciphertext = Fr::from_repr(ciphertext.0)?;
+        Ok(sloth::decode(&key, &ciphertext).into())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IdentityFunction(Fr);
+
+impl Default for IdentityFunction {
+    fn default() -> IdentityFunction {
+        IdentityFunction(Fr::zero())
+    }
+}
+
+impl Hashable<IdentityFunction> for PoseidonDomain {
+    fn hash(&self, state: &mut IdentityFunction) {
+        let mut bytes = Vec::with_capacity(32);
+        self.0
+            .write_le(&mut bytes)
+            .expect("Failed to write `FrRepr`");
+        state.write(&bytes);
+    }
+}
+
+/// The sum of `data`, read as 32 byte field elements with their top bits cleared.
+fn sum_bytes(data: &[u8]) -> Fr {
+    data.chunks(32).fold(Fr::zero(), |mut acc, chunk| {
+        let mut padded = [0u8; 32];
+        padded[..chunk.len()].copy_from_slice(chunk);
+        let repr = bytes_into_fr_repr_safe(&padded);
+        acc.add_assign(&Fr::from_repr(repr).expect("safe repr is in the field"));
+        acc
+    })
+}
+
+fn sum_domains(parts: &[PoseidonDomain]) -> PoseidonDomain {
+    parts
+        .iter()
+        .fold(Fr::zero(), |mut acc, part| {
+            acc.add_assign(&(*part).into());
+            acc
+        })
+        .into()
+}
+
+/// Allocates the sum of `nums`, constrained by a single linear constraint.
+fn sum_circuit<CS: ConstraintSystem<Bls12>>(
+    mut cs: CS,
+    nums: &[num::AllocatedNum<Bls12>],
+) -> std::result::Result<num::AllocatedNum<Bls12>, SynthesisError> {
+    let sum = num::AllocatedNum::alloc(cs.namespace(|| "sum"), || {
+        nums.iter().try_fold(Fr::zero(), |mut acc, num| {
+            acc.add_assign(&num.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(acc)
+        })
+    })?;
+
+    // (nums[0] + .. + nums[n - 1]) * 1 = sum
+    cs.enforce(
+        || "sum constraint",
+        |lc| nums.iter().fold(lc, |lc, num| lc + num.get_variable()),
+        |lc| lc + CS::one(),
+        |lc| lc + sum.get_variable(),
+    );
+
+    Ok(sum)
+}
+
+impl StdHasher for IdentityFunction {
+    #[inline]
+    fn write(&mut self, msg: &[u8]) {
+        self.0.add_assign(&sum_bytes(msg));
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        unimplemented!()
+    }
+}
+
+impl HashFunction<PoseidonDomain> for IdentityFunction {
+    fn hash(data: &[u8]) -> PoseidonDomain {
+        sum_bytes(data).into()
+    }
+
+    fn hash2(a: &PoseidonDomain, b: &PoseidonDomain) -> PoseidonDomain {
+        sum_domains(&[*a, *b])
+    }
+
+    fn hash_md(input: &[PoseidonDomain]) -> PoseidonDomain {
+        assert!(input.len() > 1, "hash_md needs more than one element.");
+        sum_domains(input)
+    }
+
+    fn hash_leaf_circuit<CS: ConstraintSystem<Bls12>>(
+        cs: CS,
+        left: &num::AllocatedNum<Bls12>,
+        right: &num::AllocatedNum<Bls12>,
+        _height: usize,
+    ) -> std::result::Result<num::AllocatedNum<Bls12>, SynthesisError> {
+        sum_circuit(cs, &[left.clone(), right.clone()])
+    }
+
+    fn hash_multi_leaf_circuit<Arity: 'static + PoseidonArity, CS: ConstraintSystem<Bls12>>(
+        cs: CS,
+        leaves: &[num::AllocatedNum<Bls12>],
+        _height: usize,
+    ) -> std::result::Result<num::AllocatedNum<Bls12>, SynthesisError> {
+        sum_circuit(cs, leaves)
+    }
+
+    fn hash_md_circuit<CS: ConstraintSystem<Bls12>>(
+        cs: &mut CS,
+        elements: &[num::AllocatedNum<Bls12>],
+    ) -> std::result::Result<num::AllocatedNum<Bls12>, SynthesisError> {
+        sum_circuit(cs.namespace(|| "hash md"), elements)
+    }
+
+    fn hash_circuit<CS: ConstraintSystem<Bls12>>(
+        _cs: CS,
+        _bits: &[boolean::Boolean],
+    ) -> std::result::Result<num::AllocatedNum<Bls12>, SynthesisError> {
+        unimplemented!();
+    }
+
+    fn hash2_circuit<CS>(
+        cs: CS,
+        a: &num::AllocatedNum<Bls12>,
+        b: &num::AllocatedNum<Bls12>,
+    ) -> std::result::Result<num::AllocatedNum<Bls12>, SynthesisError>
+    where
+        CS: ConstraintSystem<Bls12>,
+    {
+        sum_circuit(cs, &[a.clone(), b.clone()])
+    }
+}
+
+impl LightAlgorithm<PoseidonDomain> for IdentityFunction {
+    #[inline]
+    fn hash(&mut self) -> PoseidonDomain {
+        self.0.into()
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = Fr::zero();
+    }
+
+    fn leaf(&mut self, leaf: PoseidonDomain) -> PoseidonDomain {
+        leaf
+    }
+
+    fn node(
+        &mut self,
+        left: PoseidonDomain,
+        right: PoseidonDomain,
+        _height: usize,
+    ) -> PoseidonDomain {
+        sum_domains(&[left, right])
+    }
+
+    fn multi_node(&mut self, parts: &[PoseidonDomain], _height: usize) -> PoseidonDomain {
+        sum_domains(parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::util_cs::test_cs::TestConstraintSystem;
+    use generic_array::typenum;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::hasher::Domain;
+    use crate::merkle::MerkleTree;
+
+    #[test]
+    fn identity_tree_root_is_the_sum_of_the_leaves() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let leaves: Vec<PoseidonDomain> = (0..8).map(|_| PoseidonDomain::random(rng)).collect();
+
+        let tree = MerkleTree::<IdentityHasher, typenum::U2>::new(leaves.iter().copied()).unwrap();
+        assert_eq!(tree.root(), sum_domains(&leaves));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let nums: Vec<_> = leaves
+            .iter()
+            .enumerate()
+            .map(|(i, leaf)| {
+                num::AllocatedNum::alloc(cs.namespace(|| format!("leaf {}", i)), || {
+                    Ok((*leaf).into())
+                })
+                .unwrap()
+            })
+            .collect();
+        let sum = IdentityFunction::hash_multi_leaf_circuit::<typenum::U8, _>(
+            cs.namespace(|| "sum"),
+            &nums,
+            0,
+        )
+        .unwrap();
+
+        assert!(cs.is_satisfied());
+        assert_eq!(cs.num_constraints(), 1);
+        assert_eq!(sum.get_value(), Some(tree.root().into()));
+    }
+}
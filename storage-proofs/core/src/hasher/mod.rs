@@ -1,4 +1,5 @@
 pub mod blake2s;
+pub mod identity;
 pub mod pedersen;
 pub mod poseidon;
 pub mod sha256;
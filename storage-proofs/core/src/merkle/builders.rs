@@ -12,7 +12,7 @@ use merkletree::store::{ExternalReader, ReplicaConfig, Store, StoreConfig};
 use rayon::prelude::*;
 
 use crate::error::*;
-use crate::hasher::{Domain, Hasher, PoseidonArity};
+use crate::hasher::{Domain, HashFunction, Hasher, PoseidonArity};
 use crate::util::{data_at_node, default_rows_to_discard, NODE_SIZE};
 
 use super::*;
@@ -171,6 +171,29 @@ pub fn create_base_merkle_tree<Tree: MerkleTreeTrait>(
     config: Option<StoreConfig>,
     size: usize,
     data: &[u8],
+) -> Result<Tree> {
+    create_base_merkle_tree_with_leaf_domain::<Tree>(config, size, data, None)
+}
+
+/// Hashes `leaf` under the domain tag `leaf_hash_domain`, as done for every leaf of a tree
+/// built with `create_base_merkle_tree_with_leaf_domain`.
+pub fn hash_leaf_with_domain<H: Hasher>(
+    leaf_hash_domain: &H::Domain,
+    leaf: &H::Domain,
+) -> H::Domain {
+    <H::Function as HashFunction<H::Domain>>::hash2(leaf_hash_domain, leaf)
+}
+
+/// Like `create_base_merkle_tree`, but if `leaf_hash_domain` is set, every node of `data` is
+/// hashed with it (see `hash_leaf_with_domain`) before being inserted as a leaf.
+///
+/// Trees built under different domains have different roots for the same data, so a proof
+/// for a leaf of one does not verify against the root of the other.
+pub fn create_base_merkle_tree_with_leaf_domain<Tree: MerkleTreeTrait>(
+    config: Option<StoreConfig>,
+    size: usize,
+    data: &[u8],
+    leaf_hash_domain: Option<<Tree::Hasher as Hasher>::Domain>,
 ) -> Result<Tree> {
     ensure!(
         data.len() == (NODE_SIZE * size) as usize,
@@ -197,8 +220,12 @@ pub fn create_base_merkle_tree<Tree: MerkleTreeTrait>(
         // that isn't caught by the FPS API.
         // Unfortunately, it's not clear how to perform this error-handling in the parallel
         // iterator case.
-        <Tree::Hasher as Hasher>::Domain::try_from_bytes(d)
-            .expect("failed to convert node data to domain element")
+        let leaf = <Tree::Hasher as Hasher>::Domain::try_from_bytes(d)
+            .expect("failed to convert node data to domain element");
+        match leaf_hash_domain {
+            Some(ref domain) => hash_leaf_with_domain::<Tree::Hasher>(domain, &leaf),
+            None => leaf,
+        }
     };
 
     let tree = match config {
@@ -518,3 +545,48 @@ where
         generate_base_tree::<R, Tree>(rng, nodes, temp_path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::hasher::{Blake2sHasher, PedersenHasher, PoseidonHasher, Sha256Hasher};
+
+    fn base_merkle_tree_matches_leaves<H: 'static + Hasher>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let leaves: Vec<H::Domain> = (0..nodes).map(|_| H::Domain::random(rng)).collect();
+        let data: Vec<u8> = leaves.iter().flat_map(|leaf| leaf.into_bytes()).collect();
+
+        let from_data = create_base_merkle_tree::<BinaryMerkleTree<H>>(None, nodes, &data)
+            .expect("failed to build tree from data");
+        let from_leaves =
+            BinaryMerkleTree::<H>::new(leaves).expect("failed to build tree from leaves");
+
+        assert_eq!(from_data.root(), from_leaves.root());
+    }
+
+    #[test]
+    fn base_merkle_tree_matches_leaves_pedersen() {
+        base_merkle_tree_matches_leaves::<PedersenHasher>();
+    }
+
+    #[test]
+    fn base_merkle_tree_matches_leaves_poseidon() {
+        base_merkle_tree_matches_leaves::<PoseidonHasher>();
+    }
+
+    #[test]
+    fn base_merkle_tree_matches_leaves_sha256() {
+        base_merkle_tree_matches_leaves::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn base_merkle_tree_matches_leaves_blake2s() {
+        base_merkle_tree_matches_leaves::<Blake2sHasher>();
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{ensure, Context, Result};
+use generic_array::typenum::U2;
+use merkletree::hash::Algorithm;
+
+use crate::hasher::Hasher;
+use crate::merkle::MerkleProof;
+
+/// A binary merkle tree built by appending leaves one at a time.
+///
+/// Only the frontier (the roots of the complete subtrees seen so far, at most one per height)
+/// is kept in memory, plus the nodes needed for the paths of the leaves passed to `retaining`.
+/// Once a power of two number of leaves has been pushed, the root matches the one of a
+/// `BinaryMerkleTree` built over the same leaves.
+#[derive(Debug, Clone)]
+pub struct IncrementalMerkleTree<H: Hasher> {
+    /// `frontier[h]` is the root of the pending complete subtree of height `h`, if any.
+    frontier: Vec<Option<H::Domain>>,
+    leaves: usize,
+    retained: HashSet<usize>,
+    retained_leaves: HashMap<usize, H::Domain>,
+    /// Siblings on the paths of the retained leaves, keyed by `(height, index at that height)`.
+    siblings: HashMap<(usize, usize), H::Domain>,
+}
+
+impl<H: Hasher> Default for IncrementalMerkleTree<H> {
+    fn default() -> Self {
+        Self::retaining(Vec::new())
+    }
+}
+
+impl<H: Hasher> IncrementalMerkleTree<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty tree which keeps what is needed to generate proofs for the leaves at
+    /// `indices`.
+    pub fn retaining(indices: impl IntoIterator<Item = usize>) -> Self {
+        IncrementalMerkleTree {
+            frontier: Vec::new(),
+            leaves: 0,
+            retained: indices.into_iter().collect(),
+            retained_leaves: HashMap::new(),
+            siblings: HashMap::new(),
+        }
+    }
+
+    /// The number of leaves pushed so far.
+    pub fn len(&self) -> usize {
+        self.leaves
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+
+    /// Appends `leaf`, merging every complete subtree it closes.
+    pub fn push(&mut self, leaf: H::Domain) {
+        let mut index = self.leaves;
+        if self.retained.contains(&index) {
+            self.retained_leaves.insert(index, leaf);
+        }
+
+        let mut hasher = H::Function::default();
+        let mut node = leaf;
+        let mut height = 0;
+        self.record(height, index, node);
+
+        while let Some(left) = self.frontier.get_mut(height).and_then(Option::take) {
+            hasher.reset();
+            node = hasher.multi_node(&[left, node], height);
+            height += 1;
+            index >>= 1;
+            self.record(height, index, node);
+        }
+
+        if self.frontier.len() <= height {
+            self.frontier.resize(height + 1, None);
+        }
+        self.frontier[height] = Some(node);
+        self.leaves += 1;
+    }
+
+    /// Returns the root of the tree, which must hold a power of two (and at least two) leaves.
+    pub fn finalize(&self) -> Result<H::Domain> {
+        ensure!(
+            self.leaves > 1 && self.leaves.is_power_of_two(),
+            "cannot finalize an incremental merkle tree of {} leaves",
+            self.leaves
+        );
+
+        // A power of two number of leaves leaves a single complete subtree on the frontier.
+        let root = self.frontier[self.frontier.len() - 1].expect("missing incremental root");
+        Ok(root)
+    }
+
+    /// Generates the inclusion proof of the leaf at `index`, which must have been retained.
+    pub fn gen_proof(&self, index: usize) -> Result<MerkleProof<H, U2>> {
+        let root = self.finalize()?;
+        ensure!(
+            self.retained.contains(&index),
+            "leaf {} was not retained",
+            index
+        );
+        let leaf = *self
+            .retained_leaves
+            .get(&index)
+            .with_context(|| format!("leaf {} was not pushed", index))?;
+
+        let path = (0..self.frontier.len() - 1)
+            .map(|height| {
+                let sibling = self.siblings[&(height, (index >> height) ^ 1)];
+                (vec![sibling], (index >> height) & 1)
+            })
+            .collect();
+
+        Ok(MerkleProof::from_base_path(path, root, leaf))
+    }
+
+    /// Keeps the node at `(height, index)` if it is a sibling on a retained path.
+    fn record(&mut self, height: usize, index: usize, node: H::Domain) {
+        if self
+            .retained
+            .iter()
+            .any(|leaf| (leaf >> height) ^ 1 == index)
+        {
+            self.siblings.insert((height, index), node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::hasher::{Domain, PedersenHasher, PoseidonHasher, Sha256Hasher};
+    use crate::merkle::{generate_tree, BinaryMerkleTree, MerkleProofTrait, MerkleTreeTrait};
+    use crate::util::NODE_SIZE;
+
+    fn incremental_root_matches_tree<H: 'static + Hasher>(nodes: usize) {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let (data, tree) = generate_tree::<BinaryMerkleTree<H>, _>(rng, nodes, None);
+
+        let retained: Vec<usize> = (0..8).map(|_| rng.gen_range(0, nodes)).collect();
+        let mut incremental = IncrementalMerkleTree::<H>::retaining(retained.clone());
+        for chunk in data.chunks(NODE_SIZE) {
+            incremental.push(H::Domain::try_from_bytes(chunk).unwrap());
+        }
+
+        assert_eq!(incremental.len(), nodes);
+        assert_eq!(incremental.finalize().unwrap(), tree.root());
+
+        for i in retained {
+            let proof = incremental.gen_proof(i).unwrap();
+            assert!(proof.verify(), "invalid incremental proof");
+            assert!(proof.validate(i), "incremental proof for the wrong leaf");
+            assert_eq!(proof.path(), tree.gen_proof(i).unwrap().path());
+        }
+
+        let not_retained = (0..nodes).find(|i| !incremental.retained.contains(i));
+        assert!(incremental.gen_proof(not_retained.unwrap()).is_err());
+    }
+
+    #[test]
+    fn incremental_root_matches_tree_pedersen() {
+        incremental_root_matches_tree::<PedersenHasher>(1024);
+    }
+
+    #[test]
+    fn incremental_root_matches_tree_poseidon() {
+        incremental_root_matches_tree::<PoseidonHasher>(4096);
+    }
+
+    #[test]
+    fn incremental_root_matches_tree_sha256() {
+        incremental_root_matches_tree::<Sha256Hasher>(4096);
+    }
+
+    #[test]
+    fn incremental_finalize_requires_power_of_two() {
+        let mut tree = IncrementalMerkleTree::<PoseidonHasher>::new();
+        assert!(tree.finalize().is_err());
+        for len in 1..=8usize {
+            tree.push(Default::default());
+            assert_eq!(tree.finalize().is_ok(), len > 1 && len.is_power_of_two());
+        }
+    }
+}
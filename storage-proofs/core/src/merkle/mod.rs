@@ -5,10 +5,12 @@ use generic_array::typenum::{U0, U2, U4, U8};
 use crate::hasher::Hasher;
 
 mod builders;
+mod incremental;
 mod proof;
 mod tree;
 
 pub use builders::*;
+pub use incremental::*;
 pub use proof::*;
 pub use tree::*;
 
@@ -2,7 +2,7 @@
 
 use std::marker::PhantomData;
 
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Result};
 use generic_array::typenum::{Unsigned, U0};
 use merkletree::hash::Algorithm;
 use merkletree::proof;
@@ -10,7 +10,9 @@ use paired::bls12_381::Fr;
 use serde::{Deserialize, Serialize};
 
 use crate::drgraph::graph_height;
-use crate::hasher::{Hasher, PoseidonArity};
+use crate::error::Error;
+use crate::hasher::{Domain, Hasher, PoseidonArity};
+use crate::util::NODE_SIZE;
 
 /// Trait to abstract over the concept of Merkle Proof.
 pub trait MerkleProofTrait:
@@ -26,6 +28,8 @@ pub trait MerkleProofTrait:
         p: proof::Proof<<Self::Hasher as Hasher>::Domain, Self::Arity>,
     ) -> Result<Self>;
 
+    /// Siblings are converted from their domain, so the encoding does not depend on the byte
+    /// size of the data committed to in the leaves.
     fn as_options(&self) -> Vec<(Vec<Option<Fr>>, Option<usize>)> {
         self.path()
             .iter()
@@ -38,6 +42,21 @@ pub trait MerkleProofTrait:
             .collect::<Vec<_>>()
     }
 
+    /// Like `as_options`, but fails with `Error::InvalidMerklePathDepth` unless the path has
+    /// `expected_depth` elements, so a proof for another tree is caught before synthesis.
+    fn as_circuit_options(
+        &self,
+        expected_depth: usize,
+    ) -> Result<Vec<(Vec<Option<Fr>>, Option<usize>)>> {
+        let options = self.as_options();
+        ensure!(
+            options.len() == expected_depth,
+            Error::InvalidMerklePathDepth(options.len(), expected_depth)
+        );
+
+        Ok(options)
+    }
+
     fn into_options_with_leaf(self) -> (Option<Fr>, Vec<(Vec<Option<Fr>>, Option<usize>)>) {
         let leaf = self.leaf();
         let path = self.path();
@@ -169,7 +188,7 @@ macro_rules! forward_method {
     };
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct InclusionPath<H: Hasher, Arity: PoseidonArity> {
     #[serde(bound(
         serialize = "H::Domain: Serialize",
@@ -217,9 +236,60 @@ impl<H: Hasher, Arity: PoseidonArity> InclusionPath<H, Arity> {
             .rev()
             .fold(0, |acc, p| (acc * Arity::to_usize()) + p.index)
     }
+
+    /// Appends the number of levels, then the index and sibling hashes of each level.
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.path.len() as u32).to_le_bytes());
+        for element in &self.path {
+            out.push(element.index as u8);
+            for hash in &element.hashes {
+                out.extend_from_slice(&hash.into_bytes());
+            }
+        }
+    }
+
+    /// Reads a path written by `write_bytes` from the front of `bytes`.
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        let mut len = [0u8; 4];
+        len.copy_from_slice(take_bytes(bytes, 4)?);
+        let len = u32::from_le_bytes(len) as usize;
+
+        let arity = Arity::to_usize();
+        let mut path = Vec::with_capacity(len);
+        for _ in 0..len {
+            let index = take_bytes(bytes, 1)?[0] as usize;
+            ensure!(
+                index < arity,
+                "invalid path index {} for arity {}",
+                index,
+                arity
+            );
+
+            let hashes = (0..arity - 1)
+                .map(|_| H::Domain::try_from_bytes(take_bytes(bytes, NODE_SIZE)?))
+                .collect::<Result<_>>()?;
+
+            path.push(PathElement {
+                hashes,
+                index,
+                _arity: Default::default(),
+            });
+        }
+
+        Ok(path.into())
+    }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+/// Splits the first `n` bytes off `bytes`.
+fn take_bytes<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    ensure!(bytes.len() >= n, "unexpected end of merkle proof bytes");
+
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PathElement<H: Hasher, Arity: PoseidonArity> {
     #[serde(bound(
         serialize = "H::Domain: Serialize",
@@ -232,7 +302,7 @@ pub struct PathElement<H: Hasher, Arity: PoseidonArity> {
 }
 
 /// Representation of a merkle proof.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MerkleProof<
     H: Hasher,
     BaseArity: PoseidonArity,
@@ -316,7 +386,7 @@ impl<
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 enum ProofData<
     H: Hasher,
     BaseArity: PoseidonArity,
@@ -340,7 +410,7 @@ enum ProofData<
     Top(TopProof<H, BaseArity, SubTreeArity, TopTreeArity>),
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct SingleProof<H: Hasher, Arity: PoseidonArity> {
     /// Root of the merkle tree.
     #[serde(bound(
@@ -368,7 +438,7 @@ impl<H: Hasher, Arity: PoseidonArity> SingleProof<H, Arity> {
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct SubProof<H: Hasher, BaseArity: PoseidonArity, SubTreeArity: PoseidonArity> {
     #[serde(bound(
         serialize = "H::Domain: Serialize",
@@ -412,7 +482,7 @@ impl<H: Hasher, BaseArity: PoseidonArity, SubTreeArity: PoseidonArity>
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct TopProof<
     H: Hasher,
     BaseArity: PoseidonArity,
@@ -492,6 +562,112 @@ impl<
             data: ProofData::Single(SingleProof::new(path.into(), root, leaf)),
         }
     }
+
+    /// Builds a proof for a single (base only) tree from its path, given as the sibling hashes
+    /// and the index of the node at each level, as returned by `path`.
+    pub fn from_base_path(
+        path: Vec<(Vec<H::Domain>, usize)>,
+        root: H::Domain,
+        leaf: H::Domain,
+    ) -> Self {
+        let path: Vec<_> = path
+            .into_iter()
+            .map(|(hashes, index)| PathElement {
+                hashes,
+                index,
+                _arity: Default::default(),
+            })
+            .collect();
+        MerkleProof {
+            data: ProofData::Single(SingleProof::new(path.into(), root, leaf)),
+        }
+    }
+
+    /// Serializes the proof compactly, as a tag for its shape, the leaf and the root, followed
+    /// by the index and sibling hashes of every level of each of its paths.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let (tag, leaf, root) = match self.data {
+            ProofData::Single(ref proof) => (0, proof.leaf, proof.root),
+            ProofData::Sub(ref proof) => (1, proof.leaf, proof.root),
+            ProofData::Top(ref proof) => (2, proof.leaf, proof.root),
+        };
+        out.push(tag);
+        out.extend_from_slice(&leaf.into_bytes());
+        out.extend_from_slice(&root.into_bytes());
+
+        match self.data {
+            ProofData::Single(ref proof) => proof.path.write_bytes(&mut out),
+            ProofData::Sub(ref proof) => {
+                proof.base_proof.write_bytes(&mut out);
+                proof.sub_proof.write_bytes(&mut out);
+            }
+            ProofData::Top(ref proof) => {
+                proof.base_proof.write_bytes(&mut out);
+                proof.sub_proof.write_bytes(&mut out);
+                proof.top_proof.write_bytes(&mut out);
+            }
+        }
+
+        out
+    }
+
+    /// Deserializes a proof written by `to_bytes`, which must have the shape of this type: a
+    /// single tree, or a single level of sub and top trees over the base trees.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        let bytes = &mut bytes;
+
+        let tag = take_bytes(bytes, 1)?[0];
+        let expected_tag = if TopTreeArity::to_usize() > 0 {
+            2
+        } else if SubTreeArity::to_usize() > 0 {
+            1
+        } else {
+            0
+        };
+        ensure!(
+            tag == expected_tag,
+            "merkle proof tag {} does not match the tree shape, expected {}",
+            tag,
+            expected_tag
+        );
+        let leaf = H::Domain::try_from_bytes(take_bytes(bytes, NODE_SIZE)?)?;
+        let root = H::Domain::try_from_bytes(take_bytes(bytes, NODE_SIZE)?)?;
+
+        let data = match tag {
+            0 => {
+                let path = InclusionPath::read_bytes(bytes)?;
+                ProofData::Single(SingleProof::new(path, root, leaf))
+            }
+            1 => {
+                let base_proof = InclusionPath::read_bytes(bytes)?;
+                let sub_proof = InclusionPath::read_bytes(bytes)?;
+                ensure!(
+                    sub_proof.len() == 1,
+                    "sub tree path must have a single level"
+                );
+                ProofData::Sub(SubProof::new(base_proof, sub_proof, root, leaf))
+            }
+            2 => {
+                let base_proof = InclusionPath::read_bytes(bytes)?;
+                let sub_proof = InclusionPath::read_bytes(bytes)?;
+                let top_proof = InclusionPath::read_bytes(bytes)?;
+                ensure!(
+                    sub_proof.len() == 1,
+                    "sub tree path must have a single level"
+                );
+                ensure!(
+                    top_proof.len() == 1,
+                    "top tree path must have a single level"
+                );
+                ProofData::Top(TopProof::new(base_proof, sub_proof, top_proof, root, leaf))
+            }
+            _ => bail!("invalid merkle proof tag {}", tag),
+        };
+        ensure!(bytes.is_empty(), "trailing bytes after merkle proof");
+
+        Ok(MerkleProof { data })
+    }
 }
 
 /// Converts a merkle_light proof to a SingleProof
@@ -719,7 +895,10 @@ mod tests {
 
     use generic_array::typenum;
 
-    use crate::hasher::{Blake2sHasher, Domain, PedersenHasher, PoseidonHasher, Sha256Hasher};
+    use crate::error::Error;
+    use crate::hasher::{
+        Blake2sHasher, Domain, PedersenHasher, PoseidonArity, PoseidonHasher, Sha256Hasher,
+    };
     use crate::merkle::{generate_tree, MerkleProofTrait};
 
     fn merklepath<Tree: 'static + MerkleTreeTrait>() {
@@ -745,6 +924,107 @@ mod tests {
         }
     }
 
+    fn merkleproof_bytes_roundtrip<
+        H: 'static + Hasher,
+        U: 'static + PoseidonArity,
+        V: 'static + PoseidonArity,
+        W: 'static + PoseidonArity,
+    >() {
+        let nodes = 64 * get_base_tree_count::<DiskTree<H, U, V, W>>();
+
+        let mut rng = rand::thread_rng();
+        let (_, tree) = generate_tree::<DiskTree<H, U, V, W>, _>(&mut rng, nodes, None);
+
+        for i in 0..nodes {
+            let proof = tree.gen_proof(i).unwrap();
+            let bytes = proof.to_bytes();
+            let decoded = MerkleProof::<H, U, V, W>::from_bytes(&bytes).unwrap();
+
+            assert!(decoded.verify(), "failed to verify decoded proof");
+            assert!(decoded.validate(i), "decoded proof is for another node");
+            assert_eq!(decoded.as_options(), proof.as_options());
+            assert_eq!(decoded.leaf(), proof.leaf());
+            assert_eq!(decoded.root(), proof.root());
+            assert_eq!(decoded.to_bytes(), bytes);
+
+            assert!(MerkleProof::<H, U, V, W>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn merkleproof_from_bytes_checks_shape() {
+        use super::{ProofData, SubProof};
+        use typenum::{U0, U2, U8};
+
+        let mut rng = rand::thread_rng();
+        let (_, tree) =
+            generate_tree::<DiskTree<PoseidonHasher, U8, U0, U0>, _>(&mut rng, 64, None);
+        let bytes = tree.gen_proof(3).unwrap().to_bytes();
+        assert!(MerkleProof::<PoseidonHasher, U8>::from_bytes(&bytes).is_ok());
+        assert!(MerkleProof::<PoseidonHasher, U8, U2>::from_bytes(&bytes).is_err());
+        assert!(MerkleProof::<PoseidonHasher, U8, U2, U2>::from_bytes(&bytes).is_err());
+
+        type SubTree = DiskTree<PoseidonHasher, U8, U2, U0>;
+        let nodes = 64 * get_base_tree_count::<SubTree>();
+        let (_, tree) = generate_tree::<SubTree, _>(&mut rng, nodes, None);
+        let proof = tree.gen_proof(3).unwrap();
+        assert!(MerkleProof::<PoseidonHasher, U8>::from_bytes(&proof.to_bytes()).is_err());
+
+        // Sub tree paths have a single level.
+        let sub = match proof.data {
+            ProofData::Sub(ref sub) => sub,
+            _ => panic!("not a sub tree proof"),
+        };
+        let mut levels: Vec<_> = sub.sub_proof.iter().cloned().collect();
+        levels.extend(levels.clone());
+        let deeper = MerkleProof::<PoseidonHasher, U8, U2> {
+            data: ProofData::Sub(SubProof::new(
+                sub.base_proof.clone(),
+                levels.into(),
+                sub.root,
+                sub.leaf,
+            )),
+        };
+        assert!(MerkleProof::<PoseidonHasher, U8, U2>::from_bytes(&deeper.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn as_circuit_options_checks_depth() {
+        type Tree = DiskTree<PoseidonHasher, typenum::U8, typenum::U2, typenum::U0>;
+        let nodes = 64 * get_base_tree_count::<Tree>();
+
+        let mut rng = rand::thread_rng();
+        let (_, tree) = generate_tree::<Tree, _>(&mut rng, nodes, None);
+        let proof = tree.gen_proof(3).unwrap();
+        let depth = proof.expected_len(nodes);
+
+        assert_eq!(proof.as_circuit_options(depth).unwrap(), proof.as_options());
+        for wrong_depth in &[depth - 1, depth + 1] {
+            let err = proof.as_circuit_options(*wrong_depth).unwrap_err();
+            match err.downcast_ref::<Error>() {
+                Some(Error::InvalidMerklePathDepth(actual, expected)) => {
+                    assert_eq!((*actual, *expected), (depth, *wrong_depth));
+                }
+                _ => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn merkleproof_bytes_roundtrip_pedersen_2() {
+        merkleproof_bytes_roundtrip::<PedersenHasher, typenum::U2, typenum::U0, typenum::U0>();
+    }
+
+    #[test]
+    fn merkleproof_bytes_roundtrip_poseidon_8_2() {
+        merkleproof_bytes_roundtrip::<PoseidonHasher, typenum::U8, typenum::U2, typenum::U0>();
+    }
+
+    #[test]
+    fn merkleproof_bytes_roundtrip_poseidon_8_4_2() {
+        merkleproof_bytes_roundtrip::<PoseidonHasher, typenum::U8, typenum::U4, typenum::U2>();
+    }
+
     #[test]
     fn merklepath_pedersen_2() {
         merklepath::<
@@ -146,6 +146,21 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for PoR<Tree> {
     }
 }
 
+/// Generates the merkle path of the leaf at `index` in `tree`, without a `ConstraintSystem`.
+pub fn gen_path<Tree: MerkleTreeTrait>(tree: &Tree, index: usize) -> Result<Tree::Proof> {
+    tree.gen_proof(index)
+}
+
+/// Checks that `path` leads from `leaf` to `root`. The leaf index is the one encoded in `path`
+/// (see `MerkleProofTrait::path_index`), which is also how `PoRCircuit` reads it.
+pub fn verify_path<Proof: MerkleProofTrait>(
+    root: &<Proof::Hasher as Hasher>::Domain,
+    leaf: &<Proof::Hasher as Hasher>::Domain,
+    path: &Proof,
+) -> bool {
+    &path.root() == root && path.validate_data(*leaf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,5 +1,6 @@
 use std::time::Instant;
 
+use anyhow::ensure;
 use log::info;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
@@ -26,6 +27,28 @@ pub trait ProofScheme<'a> {
         _: &Self::PrivateInputs,
     ) -> Result<Self::Proof>;
 
+    /// Proves only partition `k` out of `partition_count`, so that partitions can be proven
+    /// independently. By default, proves the partition's public inputs (see `with_partition`).
+    fn prove_partition(
+        pub_params: &Self::PublicParams,
+        pub_in: &Self::PublicInputs,
+        priv_in: &Self::PrivateInputs,
+        k: usize,
+        partition_count: usize,
+    ) -> Result<Self::Proof> {
+        ensure!(
+            k < partition_count,
+            "partition {} out of range for {} partitions",
+            k,
+            partition_count
+        );
+
+        let partition_pub_in = Self::with_partition((*pub_in).clone(), Some(k));
+        Self::prove(pub_params, &partition_pub_in, priv_in)
+    }
+
+    /// Proves all `partition_count` partitions, in partition order, by calling
+    /// `prove_partition` for each of them.
     fn prove_all_partitions(
         pub_params: &Self::PublicParams,
         pub_in: &Self::PublicInputs,
@@ -42,8 +65,7 @@ pub trait ProofScheme<'a> {
                 info!("generating groth proof {}.", k);
                 let start = Instant::now();
 
-                let partition_pub_in = Self::with_partition((*pub_in).clone(), Some(k));
-                let proof = Self::prove(pub_params, &partition_pub_in, priv_in);
+                let proof = Self::prove_partition(pub_params, pub_in, priv_in, k, partition_count);
 
                 let proof_time = start.elapsed();
                 info!("groth_proof_time: {:?}", proof_time);
@@ -70,6 +92,7 @@ pub trait ProofScheme<'a> {
         unimplemented!();
     }
 
+    /// Verifies proofs of all partitions, as returned by `prove_all_partitions`.
     fn verify_all_partitions(
         pub_params: &Self::PublicParams,
         pub_in: &Self::PublicInputs,
@@ -1,5 +1,7 @@
+use bellperson::util_cs::test_cs::TestConstraintSystem;
 use memmap::MmapMut;
 use memmap::MmapOptions;
+use paired::Engine;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
@@ -20,6 +22,55 @@ pub fn setup_replica(data: &[u8], replica_path: &Path) -> MmapMut {
     }
 }
 
+/// The first unsatisfied constraint of a `TestConstraintSystem`, with the values of the
+/// variables it involves.
+#[derive(Debug, Clone)]
+pub struct UnsatisfiedConstraint<E: Engine> {
+    /// Full path of the constraint.
+    pub path: String,
+    /// Namespace the constraint was enforced in.
+    pub namespace: String,
+    /// Paths and values of the variables in the constraint, in order of appearance.
+    pub variables: Vec<(String, E::Fr)>,
+}
+
+/// Returns the first unsatisfied constraint of `cs`, or `None` if all are satisfied.
+pub fn unsatisfied_constraint<E: Engine>(
+    cs: &mut TestConstraintSystem<E>,
+) -> Option<UnsatisfiedConstraint<E>> {
+    let path = cs.which_is_unsatisfied()?.to_string();
+    let namespace = match path.rfind('/') {
+        Some(i) => path[..i].to_string(),
+        None => String::new(),
+    };
+
+    // Each constraint is printed on a line of its own, as its path followed by the linear
+    // combinations, which quote the paths of their variables in backticks.
+    let printed = cs.pretty_print();
+    let prefix = format!("{}: ", path);
+    let line = printed
+        .lines()
+        .find(|line| line.starts_with(&prefix))
+        .unwrap_or_default();
+
+    let mut names: Vec<&str> = Vec::new();
+    for name in line.split('`').skip(1).step_by(2) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let variables = names
+        .into_iter()
+        .map(|name| (name.to_string(), cs.get(name)))
+        .collect();
+
+    Some(UnsatisfiedConstraint {
+        path,
+        namespace,
+        variables,
+    })
+}
+
 #[macro_export]
 macro_rules! table_tests {
     ($property_test_func:ident {
@@ -34,3 +85,45 @@ macro_rules! table_tests {
         )+
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::ConstraintSystem;
+    use ff::Field;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn unsatisfied_constraint_points_at_namespace() {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let mut two = Fr::one();
+        two.double();
+
+        {
+            let mut cs = cs.namespace(|| "outer");
+            let mut cs = cs.namespace(|| "inner");
+            let a = cs.alloc(|| "a", || Ok(two)).unwrap();
+            let b = cs.alloc(|| "b", || Ok(two)).unwrap();
+            let c = cs.alloc(|| "c", || Ok(two)).unwrap();
+            cs.enforce(|| "mul", |lc| lc + a, |lc| lc + b, |lc| lc + c);
+        }
+        assert!(unsatisfied_constraint(&mut cs).is_none());
+
+        let mut three = two;
+        three.add_assign(&Fr::one());
+        cs.set("outer/inner/c", three);
+
+        let unsatisfied = unsatisfied_constraint(&mut cs).expect("constraint is satisfied");
+        assert_eq!(unsatisfied.path, "outer/inner/mul");
+        assert_eq!(unsatisfied.namespace, "outer/inner");
+        assert_eq!(
+            unsatisfied.variables,
+            vec![
+                ("outer/inner/a".to_string(), two),
+                ("outer/inner/b".to_string(), two),
+                ("outer/inner/c".to_string(), three),
+            ]
+        );
+    }
+}
@@ -14,6 +14,12 @@ pub fn data_at_node_offset(v: usize) -> usize {
     v * NODE_SIZE
 }
 
+/// Returns the node containing the byte at `offset`, the inverse of `data_at_node_offset`.
+/// Offsets inside a node map to that node, no bounds are checked against any data.
+pub fn node_at_offset(offset: usize) -> usize {
+    offset / NODE_SIZE
+}
+
 /// Returns the byte slice representing one node (of uniform size, NODE_SIZE) at position v in data.
 pub fn data_at_node(data: &[u8], v: usize) -> error::Result<&[u8]> {
     let offset = data_at_node_offset(v);
@@ -187,6 +193,30 @@ mod tests {
     use rand::{Rng, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
+    #[test]
+    fn test_node_offset_roundtrip() {
+        for node in 0..1000 {
+            let offset = data_at_node_offset(node);
+            assert_eq!(node_at_offset(offset), node);
+            assert_eq!(node_at_offset(offset + NODE_SIZE - 1), node);
+            assert_eq!(node_at_offset(offset + NODE_SIZE), node + 1);
+        }
+    }
+
+    #[test]
+    fn test_data_at_node_boundaries() {
+        let nodes = 4;
+        let data: Vec<u8> = (0..nodes * NODE_SIZE).map(|i| i as u8).collect();
+
+        assert_eq!(data_at_node(&data, 0).unwrap(), &data[..NODE_SIZE]);
+        assert_eq!(
+            data_at_node(&data, nodes - 1).unwrap(),
+            &data[data_at_node_offset(nodes - 1)..]
+        );
+        assert!(data_at_node(&data, nodes).is_err());
+        assert!(data_at_node(&data[..data.len() - 1], nodes - 1).is_err());
+    }
+
     #[test]
     fn test_bytes_into_boolean_vec() {
         let mut cs = TestConstraintSystem::<Bls12>::new();
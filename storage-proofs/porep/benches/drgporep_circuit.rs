@@ -0,0 +1,78 @@
+use bellperson::util_cs::bench_cs::BenchCS;
+use bellperson::Circuit;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, ParameterizedBenchmark};
+use ff::{Field, PrimeField};
+use generic_array::typenum;
+use paired::bls12_381::{Bls12, Fr};
+use rand::{rngs::ThreadRng, thread_rng};
+use storage_proofs_core::{
+    drgraph::{graph_height, BASE_DEGREE},
+    gadgets::variables::Root,
+    hasher::pedersen::PedersenHasher,
+};
+use storage_proofs_porep::drg::DrgPoRepCircuit;
+
+/// A circuit for a single challenge in a 1GiB sector, with random values.
+fn drgporep_circuit<'a>(verify_commitments: bool) -> DrgPoRepCircuit<'a, PedersenHasher> {
+    let rng = &mut thread_rng();
+
+    let nodes = (1 << 30) / 32;
+    let degree = BASE_DEGREE;
+    let tree_depth = graph_height::<typenum::U2>(nodes);
+    let path = |rng: &mut ThreadRng| vec![(vec![Some(Fr::random(rng))], Some(0)); tree_depth];
+
+    DrgPoRepCircuit {
+        replica_nodes: vec![Some(Fr::random(rng))],
+        replica_nodes_paths: vec![path(rng)],
+        replica_root: Root::Val(Some(Fr::random(rng))),
+        replica_parents: vec![vec![Some(Fr::random(rng)); degree]],
+        replica_parents_paths: vec![vec![path(rng); degree]],
+        data_nodes: vec![Some(Fr::random(rng))],
+        data_nodes_paths: vec![path(rng)],
+        data_root: Root::Val(Some(Fr::random(rng))),
+        replica_id: Some(Fr::random(rng)),
+        private: false,
+        forward_encoding: false,
+        hash_replica_id: false,
+        verify_commitments,
+        public_input_bits: Fr::CAPACITY as usize,
+        challenges: None,
+        _h: Default::default(),
+    }
+}
+
+fn drgporep_circuit_benchmark(c: &mut Criterion) {
+    for verify_commitments in &[true, false] {
+        let mut cs = BenchCS::<Bls12>::new();
+        drgporep_circuit(*verify_commitments)
+            .synthesize(&mut cs)
+            .unwrap();
+        println!(
+            "drgporep circuit (verify_commitments: {}): {} constraints",
+            verify_commitments,
+            cs.num_constraints()
+        );
+    }
+
+    c.bench(
+        "drgporep-circuit",
+        ParameterizedBenchmark::new(
+            "synthesize",
+            |b, verify_commitments| {
+                b.iter(|| {
+                    let mut cs = BenchCS::<Bls12>::new();
+                    drgporep_circuit(*verify_commitments)
+                        .synthesize(&mut cs)
+                        .unwrap();
+
+                    black_box(cs)
+                })
+            },
+            vec![true, false],
+        )
+        .sample_size(10),
+    );
+}
+
+criterion_group!(benches, drgporep_circuit_benchmark);
+criterion_main!(benches);
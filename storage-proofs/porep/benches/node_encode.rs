@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ff::Field;
+use paired::bls12_381::Fr;
+use rand::thread_rng;
+use storage_proofs_core::crypto::sloth;
+use storage_proofs_core::fr32::{bytes_into_fr, fr_into_bytes};
+use storage_proofs_core::util::NODE_SIZE;
+
+/// Number of nodes encoded per iteration, the throughput is reported in nodes per second.
+const NODES: usize = 1 << 16;
+
+fn random_nodes() -> (Vec<Fr>, Vec<u8>) {
+    let mut rng = thread_rng();
+    let nodes: Vec<Fr> = (0..NODES).map(|_| Fr::random(&mut rng)).collect();
+    let bytes = nodes.iter().flat_map(fr_into_bytes).collect();
+
+    (nodes, bytes)
+}
+
+fn node_encode_benchmark(c: &mut Criterion) {
+    let (keys, key_bytes) = random_nodes();
+    let (values, value_bytes) = random_nodes();
+
+    let mut group = c.benchmark_group("node-encode");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NODES as u64));
+
+    // Conversion only: every node is read into a field element and written back.
+    group.bench_function("convert", |b| {
+        let mut data = value_bytes.clone();
+
+        b.iter(|| {
+            for node in data.chunks_mut(NODE_SIZE) {
+                let fr = bytes_into_fr(node).unwrap();
+                node.copy_from_slice(&fr_into_bytes(&fr));
+            }
+            black_box(&data);
+        })
+    });
+
+    // Encoding over bytes, converting key and node for every node like `replicate` does.
+    group.bench_function("encode-bytes", |b| {
+        let mut data = value_bytes.clone();
+
+        b.iter(|| {
+            for (node, key) in data.chunks_mut(NODE_SIZE).zip(key_bytes.chunks(NODE_SIZE)) {
+                let key = bytes_into_fr(key).unwrap();
+                let value = bytes_into_fr(node).unwrap();
+                node.copy_from_slice(&fr_into_bytes(&sloth::encode(&key, &value)));
+            }
+            black_box(&data);
+        })
+    });
+
+    // Encoding over nodes kept as field elements.
+    group.bench_function("encode-fr", |b| {
+        let mut data = values.clone();
+
+        b.iter(|| {
+            for (node, key) in data.iter_mut().zip(&keys) {
+                *node = sloth::encode(key, node);
+            }
+            black_box(&data);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, node_encode_benchmark);
+criterion_main!(benches);
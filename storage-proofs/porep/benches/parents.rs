@@ -1,11 +1,12 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, ParameterizedBenchmark};
 use storage_proofs_core::{
-    drgraph::{Graph, BASE_DEGREE},
+    drgraph::{BucketGraph, Graph, BASE_DEGREE},
     hasher::blake2s::Blake2sHasher,
     hasher::pedersen::PedersenHasher,
     hasher::sha256::Sha256Hasher,
     hasher::Hasher,
 };
+use storage_proofs_porep::drg;
 use storage_proofs_porep::stacked::{StackedBucketGraph, EXP_DEGREE};
 
 #[cfg(feature = "cpu-profile")]
@@ -76,5 +77,46 @@ fn parents_loop_benchmark(cc: &mut Criterion) {
     );
 }
 
-criterion_group!(benches, parents_loop_benchmark);
+#[allow(clippy::unit_arg)]
+fn drg_parents_cache_benchmark(cc: &mut Criterion) {
+    let sizes = vec![1000];
+
+    let public_params = |size: usize| {
+        let graph = BucketGraph::<PedersenHasher>::new(size, BASE_DEGREE, 0, [32; 32]).unwrap();
+        drg::PublicParams::<PedersenHasher, _>::new(graph, false, 1)
+    };
+
+    cc.bench(
+        "drg parents in a loop",
+        ParameterizedBenchmark::new(
+            "uncached",
+            move |b, size| {
+                let pp = public_params(*size);
+                let mut parents = vec![0; pp.graph.degree()];
+                b.iter(|| {
+                    black_box(
+                        (0..*size)
+                            .map(|node| pp.parents(node, &mut parents).unwrap())
+                            .collect::<()>(),
+                    )
+                })
+            },
+            sizes,
+        )
+        .with_function("cached", move |b, size| {
+            let mut pp = public_params(*size);
+            pp.cache_parents().unwrap();
+            let mut parents = vec![0; pp.graph.degree()];
+            b.iter(|| {
+                black_box(
+                    (0..*size)
+                        .map(|node| pp.parents(node, &mut parents).unwrap())
+                        .collect::<()>(),
+                )
+            })
+        }),
+    );
+}
+
+criterion_group!(benches, parents_loop_benchmark, drg_parents_cache_benchmark);
 criterion_main!(benches);
@@ -1,19 +1,30 @@
 use std::marker::PhantomData;
 
 use bellperson::gadgets::{
+    blake2s::blake2s as blake2s_circuit,
     boolean::Boolean,
     sha256::sha256 as sha256_circuit,
     {multipack, num},
 };
 use bellperson::{Circuit, ConstraintSystem, SynthesisError};
-use ff::PrimeField;
+use ff::{Field, PrimeField};
 use fil_sapling_crypto::jubjub::JubjubEngine;
 use paired::bls12_381::{Bls12, Fr};
+use sha2::{Digest, Sha256};
 
 use storage_proofs_core::{
-    compound_proof::CircuitComponent, error::Result, gadgets::constraint, gadgets::encode,
-    gadgets::por::PoRCircuit, gadgets::uint64, gadgets::variables::Root, hasher::Hasher,
-    merkle::BinaryMerkleTree, util::reverse_bit_numbering,
+    compound_proof::CircuitComponent,
+    error::Result,
+    fr32::{bytes_into_fr_repr_safe, fr_into_bytes},
+    gadgets::constraint,
+    gadgets::encode,
+    gadgets::multipack::{pack_bits, pack_into_inputs_with_width},
+    gadgets::por::PoRCircuit,
+    gadgets::uint64,
+    gadgets::variables::Root,
+    hasher::Hasher,
+    merkle::BinaryMerkleTree,
+    util::reverse_bit_numbering,
 };
 
 /// DRG based Proof of Replication.
@@ -37,6 +48,24 @@ use storage_proofs_core::{
 /// * `data_root` - The merkle root of the data.
 /// * `replica_id` - The id of the replica.
 ///
+/// * `forward_encoding` - Check `replica_node == encode(key, data_node)` instead of decoding
+///   the replica node and comparing it to the data node. Both take one constraint for the
+///   field addition or subtraction and one for the equality, so the constraint counts match.
+///
+/// * `hash_replica_id` - Expose a sha256 digest of the replica id (see `replica_id_digest`)
+///   as the public input instead of the replica id itself, which then stays private.
+///
+/// * `verify_commitments` - Check the inclusion proofs of all nodes. Only benchmarks of the
+///   encoding checks may set this to false: the nodes are then not bound to the commitments,
+///   so the resulting proofs prove nothing about the replica.
+///
+/// * `public_input_bits` - How many bits are packed into each public input, for the auth
+///   path bits and the replica id digest. At most, and usually, `Fr::CAPACITY`.
+///
+/// * `challenges` - If set, the index of every challenged node, which is then exposed as a
+///   public input ahead of the auth path bits of the challenge, and constrained to the auth
+///   path bits of both the replica node and the data node. Needs `verify_commitments`.
+///
 
 pub struct DrgPoRepCircuit<'a, H: Hasher> {
     pub replica_nodes: Vec<Option<Fr>>,
@@ -52,6 +81,11 @@ pub struct DrgPoRepCircuit<'a, H: Hasher> {
     pub data_root: Root<Bls12>,
     pub replica_id: Option<Fr>,
     pub private: bool,
+    pub forward_encoding: bool,
+    pub hash_replica_id: bool,
+    pub verify_commitments: bool,
+    pub public_input_bits: usize,
+    pub challenges: Option<Vec<Option<Fr>>>,
     pub _h: PhantomData<&'a H>,
 }
 
@@ -84,10 +118,85 @@ impl<'a, H: 'static + Hasher> DrgPoRepCircuit<'a, H> {
             data_root,
             replica_id,
             private,
+            forward_encoding: false,
+            hash_replica_id: false,
+            verify_commitments: true,
+            public_input_bits: Fr::CAPACITY as usize,
+            challenges: None,
             _h: Default::default(),
         }
         .synthesize(&mut cs)
     }
+
+    /// Proves the retrievability of the data nodes at adjacent indices, given as pairs of a node
+    /// and its path, against `data_root`. Like the challenges of the full circuit, the auth
+    /// path bits of every node are exposed as public inputs, followed by the root unless
+    /// `private` is set.
+    ///
+    /// Adjacent nodes lie in a common subtree, so the levels above it are only hashed once.
+    /// `shared_levels` shapes the circuit and must come from the range, not from the witness,
+    /// see `data_range_shared_levels`.
+    #[allow(clippy::type_complexity)]
+    pub fn synthesize_data_range<CS>(
+        mut cs: CS,
+        data_nodes: &[(Option<Fr>, Vec<(Vec<Option<Fr>>, Option<usize>)>)],
+        shared_levels: usize,
+        data_root: Root<Bls12>,
+        private: bool,
+    ) -> Result<(), SynthesisError>
+    where
+        CS: ConstraintSystem<Bls12>,
+    {
+        if data_nodes.is_empty() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        let index = |path: &[(Vec<Option<Fr>>, Option<usize>)]| {
+            path.iter()
+                .enumerate()
+                .map(|(level, (_, bit))| bit.map(|bit| bit << level))
+                .sum::<Option<usize>>()
+        };
+        for pair in data_nodes.windows(2) {
+            if let (Some(a), Some(b)) = (index(&pair[0].1), index(&pair[1].1)) {
+                if b != a + 1 {
+                    return Err(SynthesisError::Unsatisfiable);
+                }
+            }
+        }
+
+        let values = data_nodes
+            .iter()
+            .map(|(node, _)| Root::Val(*node))
+            .collect();
+        let paths = data_nodes
+            .iter()
+            .map(|(_, path)| path.clone().into())
+            .collect();
+
+        PoRCircuit::<BinaryMerkleTree<H>>::synthesize_shared(
+            cs.namespace(|| "data_range"),
+            values,
+            paths,
+            shared_levels,
+            data_root,
+            private,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// The number of levels shared by the paths of the `len` nodes starting at `start`, in a binary
+/// tree whose paths have `depth` levels: those above the smallest subtree holding them all.
+pub fn data_range_shared_levels(start: usize, len: usize, depth: usize) -> usize {
+    assert!(len > 0, "empty range");
+
+    let end = start + len - 1;
+    let subtree_height = std::mem::size_of::<usize>() * 8 - (start ^ end).leading_zeros() as usize;
+    assert!(subtree_height <= depth, "range is out of the tree");
+
+    depth - subtree_height
 }
 
 #[derive(Default, Clone)]
@@ -103,17 +212,20 @@ impl<'a, H: Hasher> CircuitComponent for DrgPoRepCircuit<'a, H> {
 ///
 /// # Public Inputs
 ///
-/// * [0] replica_id/0
-/// * [1] replica_id/1
-/// * [2] replica auth_path_bits
-/// * [3] replica commitment (root hash)
-/// * for i in 0..replica_parents.len()
-///   * [ ] replica parent auth_path_bits
-///   * [ ] replica parent commitment (root hash) // Same for all.
-/// * [r + 1] data auth_path_bits
-/// * [r + 2] data commitment (root hash)
+/// * [0] replica_id
+/// * [1] replica commitment (root hash)
+/// * [2] data commitment (root hash)
+/// * for each challenge
+///   * [ ] replica auth_path_bits
+///   * for i in 0..replica_parents.len()
+///     * [ ] replica parent auth_path_bits
+///   * [ ] data auth_path_bits
+///
+///  Total = 3 + challenges * (2 + replica_parents.len())
 ///
-///  Total = 6 + (2 * replica_parents.len())
+/// The commitments are only exposed when the circuit is not private. They are allocated
+/// once and shared by all inclusion proofs, so every parent is proven against the same
+/// replica root.
 /// # Private Inputs
 ///
 /// * [ ] replica value/0
@@ -136,19 +248,55 @@ impl<'a, H: 'static + Hasher> Circuit<Bls12> for DrgPoRepCircuit<'a, H> {
         assert_eq!(self.replica_parents.len(), nodes);
         assert_eq!(self.replica_parents_paths.len(), nodes);
         assert_eq!(self.data_nodes_paths.len(), nodes);
+        if let Some(challenges) = &self.challenges {
+            assert_eq!(challenges.len(), nodes);
+            assert!(
+                self.verify_commitments,
+                "challenges are bound to the inclusion proofs"
+            );
+        }
 
         let replica_node_num = num::AllocatedNum::alloc(cs.namespace(|| "replica_id_num"), || {
             replica_id.ok_or_else(|| SynthesisError::AssignmentMissing)
         })?;
 
-        replica_node_num.inputize(cs.namespace(|| "replica_id"))?;
+        if !self.hash_replica_id {
+            replica_node_num.inputize(cs.namespace(|| "replica_id"))?;
+        }
 
         // get the replica_id in bits
         let replica_id_bits =
             reverse_bit_numbering(replica_node_num.to_bits_le(cs.namespace(|| "replica_id_bits"))?);
 
-        let replica_root_var = Root::Var(replica_root.allocated(cs.namespace(|| "replica_root"))?);
-        let data_root_var = Root::Var(data_root.allocated(cs.namespace(|| "data_root"))?);
+        if self.hash_replica_id {
+            let digest_bits = sha256_circuit(cs.namespace(|| "replica_id_hash"), &replica_id_bits)?;
+
+            // Pack the digest like `bytes_into_fr_repr_safe`: little endian bits of each
+            // byte, truncated to the capacity of a single field element.
+            let digest_bits: Vec<Boolean> = digest_bits
+                .chunks(8)
+                .flat_map(|chunk| chunk.iter().rev())
+                .cloned()
+                .take(Fr::CAPACITY as usize)
+                .collect();
+
+            pack_into_inputs_with_width(
+                cs.namespace(|| "replica_id_digest"),
+                &digest_bits,
+                self.public_input_bits,
+            )?;
+        }
+
+        let replica_root_num = replica_root.allocated(cs.namespace(|| "replica_root_num"))?;
+        let data_root_num = data_root.allocated(cs.namespace(|| "data_root_num"))?;
+
+        if !self.private {
+            replica_root_num.inputize(cs.namespace(|| "replica_root"))?;
+            data_root_num.inputize(cs.namespace(|| "data_root"))?;
+        }
+
+        let replica_root_var = Root::Var(replica_root_num);
+        let data_root_var = Root::Var(data_root_num);
 
         for i in 0..self.data_nodes.len() {
             let mut cs = cs.namespace(|| format!("challenge_{}", i));
@@ -165,36 +313,69 @@ impl<'a, H: 'static + Hasher> Circuit<Bls12> for DrgPoRepCircuit<'a, H> {
             assert_eq!(data_node_path.len(), replica_node_path.len());
             assert_eq!(replica_node.is_some(), data_node.is_some());
 
+            let challenge_num = match &self.challenges {
+                Some(challenges) => {
+                    let challenge = challenges[i];
+                    let num = num::AllocatedNum::alloc(cs.namespace(|| "challenge_num"), || {
+                        challenge.ok_or_else(|| SynthesisError::AssignmentMissing)
+                    })?;
+                    num.inputize(cs.namespace(|| "challenge"))?;
+                    Some(num)
+                }
+                None => None,
+            };
+
             // Inclusion checks
-            {
+            // The roots are shared variables and already exposed above, so the inclusion proofs
+            // keep them private. Their auth path bits are public inputs, which is what binds the
+            // replica and data node to the challenged index.
+            if self.verify_commitments {
                 let mut cs = cs.namespace(|| "inclusion_checks");
-                PoRCircuit::<BinaryMerkleTree<H>>::synthesize(
-                    cs.namespace(|| "replica_inclusion"),
-                    Root::Val(*replica_node),
-                    replica_node_path.clone().into(),
-                    replica_root_var.clone(),
-                    self.private,
-                )?;
+                let (_, replica_path_bits) =
+                    PoRCircuit::<BinaryMerkleTree<H>>::synthesize_with_path_bits(
+                        cs.namespace(|| "replica_inclusion"),
+                        Root::Val(*replica_node),
+                        replica_node_path.clone().into(),
+                        replica_root_var.clone(),
+                        true,
+                        self.public_input_bits,
+                    )?;
 
                 // validate each replica_parents merkle proof
                 for j in 0..replica_parents.len() {
-                    PoRCircuit::<BinaryMerkleTree<H>>::synthesize(
+                    PoRCircuit::<BinaryMerkleTree<H>>::synthesize_with_input_bits(
                         cs.namespace(|| format!("parents_inclusion_{}", j)),
                         Root::Val(replica_parents[j]),
                         replica_parents_paths[j].clone().into(),
                         replica_root_var.clone(),
-                        self.private,
+                        true,
+                        self.public_input_bits,
                     )?;
                 }
 
                 // validate data node commitment
-                PoRCircuit::<BinaryMerkleTree<H>>::synthesize(
-                    cs.namespace(|| "data_inclusion"),
-                    Root::Val(*data_node),
-                    data_node_path.clone().into(),
-                    data_root_var.clone(),
-                    self.private,
-                )?;
+                let (_, data_path_bits) =
+                    PoRCircuit::<BinaryMerkleTree<H>>::synthesize_with_path_bits(
+                        cs.namespace(|| "data_inclusion"),
+                        Root::Val(*data_node),
+                        data_node_path.clone().into(),
+                        data_root_var.clone(),
+                        true,
+                        self.public_input_bits,
+                    )?;
+
+                if let Some(challenge_num) = &challenge_num {
+                    enforce_packs_to(
+                        cs.namespace(|| "replica_challenge"),
+                        &replica_path_bits,
+                        challenge_num,
+                    );
+                    enforce_packs_to(
+                        cs.namespace(|| "data_challenge"),
+                        &data_path_bits,
+                        challenge_num,
+                    );
+                }
             }
 
             // Encoding checks
@@ -232,16 +413,24 @@ impl<'a, H: 'static + Hasher> Circuit<Bls12> for DrgPoRepCircuit<'a, H> {
                         (*replica_node).ok_or_else(|| SynthesisError::AssignmentMissing)
                     })?;
 
-                let decoded = encode::decode(cs.namespace(|| "decode"), &key, &replica_node_num)?;
-
                 // TODO this should not be here, instead, this should be the leaf Fr in the data_auth_path
                 // TODO also note that we need to change/makesurethat the leaves are the data, instead of hashes of the data
-                let expected = num::AllocatedNum::alloc(cs.namespace(|| "data node"), || {
+                let data_node_num = num::AllocatedNum::alloc(cs.namespace(|| "data node"), || {
                     data_node.ok_or_else(|| SynthesisError::AssignmentMissing)
                 })?;
 
-                // ensure the encrypted data and data_node match
-                constraint::equal(&mut cs, || "equality", &expected, &decoded);
+                if self.forward_encoding {
+                    let encoded = encode::encode(cs.namespace(|| "encode"), &key, &data_node_num)?;
+
+                    // ensure the encoded data and replica_node match
+                    constraint::equal(&mut cs, || "equality", &replica_node_num, &encoded);
+                } else {
+                    let decoded =
+                        encode::decode(cs.namespace(|| "decode"), &key, &replica_node_num)?;
+
+                    // ensure the decrypted replica and data_node match
+                    constraint::equal(&mut cs, || "equality", &data_node_num, &decoded);
+                }
             }
         }
         // profit!
@@ -249,20 +438,80 @@ impl<'a, H: 'static + Hasher> Circuit<Bls12> for DrgPoRepCircuit<'a, H> {
     }
 }
 
+/// Enforces that the little endian `bits` pack to `num`. The bits are already boolean and
+/// fewer than `Fr::CAPACITY`, so this single linear constraint binds every bit of `num`, as a
+/// decomposition of it would.
+fn enforce_packs_to<CS: ConstraintSystem<Bls12>>(
+    mut cs: CS,
+    bits: &[Boolean],
+    num: &num::AllocatedNum<Bls12>,
+) {
+    assert!(bits.len() < Fr::CAPACITY as usize);
+
+    cs.enforce(
+        || "packing constraint",
+        |mut lc| {
+            let mut coeff = Fr::one();
+            for bit in bits {
+                lc = lc + &bit.lc(CS::one(), coeff);
+                coeff.double();
+            }
+            lc
+        },
+        |lc| lc + CS::one(),
+        |lc| lc + num.get_variable(),
+    );
+}
+
+/// Returns the public input exposed for `replica_id` by a circuit with `hash_replica_id` set.
+pub fn replica_id_digest(replica_id: Fr) -> Fr {
+    let digest = Sha256::digest(&fr_into_bytes(&replica_id));
+
+    Fr::from_repr(bytes_into_fr_repr_safe(digest.as_ref())).expect("digest is a valid Fr")
+}
+
 /// Key derivation function.
 fn kdf<E, CS>(
-    mut cs: CS,
+    cs: CS,
     id: &[Boolean],
     parents: Vec<Vec<Boolean>>,
     window_index: Option<uint64::UInt64>,
     node: Option<uint64::UInt64>,
 ) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    kdf_elements(cs, id, parents, window_index, node, 1).map(|mut key| key.remove(0))
+}
+
+/// Key derivation function returning a key of `len` field elements, see
+/// `create_key_elements`. The first element is the key of `kdf`, element `j > 0` appends `j` to
+/// the preimage as a 32 bit big endian counter.
+pub fn kdf_elements<E, CS>(
+    mut cs: CS,
+    id: &[Boolean],
+    parents: Vec<Vec<Boolean>>,
+    window_index: Option<uint64::UInt64>,
+    node: Option<uint64::UInt64>,
+    len: usize,
+) -> Result<Vec<num::AllocatedNum<E>>, SynthesisError>
 where
     E: JubjubEngine,
     CS: ConstraintSystem<E>,
 {
     // ciphertexts will become a buffer of the layout
     // id | node | encodedParentNode1 | encodedParentNode1 | ...
+    //
+    // Every element must stay a whole 256 bit word, as the vanilla key derivation hashes the
+    // 32 byte encodings. The padding bits added by `reverse_bit_numbering` are constants,
+    // which the sha256 gadget folds at synthesis time instead of constraining them.
+
+    // The number of parents is taken from `parents`, but each of them has to be a whole
+    // word like `id`, or the hash would not match the vanilla key derivation.
+    if parents.iter().any(|parent| parent.len() != id.len()) {
+        return Err(SynthesisError::Unsatisfiable);
+    }
 
     let mut ciphertexts = id.to_vec();
 
@@ -278,26 +527,75 @@ where
         ciphertexts.extend_from_slice(&parent);
     }
 
-    let alloc_bits = sha256_circuit(cs.namespace(|| "hash"), &ciphertexts[..])?;
-    let fr = if alloc_bits[0].get_value().is_some() {
-        let be_bits = alloc_bits
-            .iter()
-            .map(|v| v.get_value().ok_or(SynthesisError::AssignmentMissing))
-            .collect::<Result<Vec<bool>, SynthesisError>>()?;
-
-        let le_bits = be_bits
-            .chunks(8)
-            .flat_map(|chunk| chunk.iter().rev())
-            .copied()
-            .take(E::Fr::CAPACITY as usize)
-            .collect::<Vec<bool>>();
-
-        Ok(multipack::compute_multipacking::<E>(&le_bits)[0])
-    } else {
-        Err(SynthesisError::AssignmentMissing)
-    };
+    (0..len)
+        .map(|j| {
+            let mut cs = cs.namespace(|| format!("element_{}", j));
+
+            let mut preimage = ciphertexts.clone();
+            if j > 0 {
+                preimage.extend(
+                    (0..32)
+                        .rev()
+                        .map(|bit| Boolean::constant((j >> bit) & 1 == 1)),
+                );
+            }
 
-    num::AllocatedNum::<E>::alloc(cs.namespace(|| "result_num"), || fr)
+            let alloc_bits = sha256_circuit(cs.namespace(|| "hash"), &preimage)?;
+            let fr = if alloc_bits[0].get_value().is_some() {
+                let be_bits = alloc_bits
+                    .iter()
+                    .map(|v| v.get_value().ok_or(SynthesisError::AssignmentMissing))
+                    .collect::<Result<Vec<bool>, SynthesisError>>()?;
+
+                let le_bits = be_bits
+                    .chunks(8)
+                    .flat_map(|chunk| chunk.iter().rev())
+                    .copied()
+                    .take(E::Fr::CAPACITY as usize)
+                    .collect::<Vec<bool>>();
+
+                Ok(multipack::compute_multipacking::<E>(&le_bits)[0])
+            } else {
+                Err(SynthesisError::AssignmentMissing)
+            };
+
+            num::AllocatedNum::<E>::alloc(cs.namespace(|| "result_num"), || fr)
+        })
+        .collect()
+}
+
+/// Key derivation function using blake2s, matching `create_key_blake2s`. Takes the same
+/// inputs as `kdf`, each a whole 256 bit word.
+pub fn kdf_blake2s<E, CS>(
+    mut cs: CS,
+    id: &[Boolean],
+    parents: Vec<Vec<Boolean>>,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    if parents.iter().any(|parent| parent.len() != id.len()) {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    let mut ciphertexts = id.to_vec();
+    for parent in parents.into_iter() {
+        ciphertexts.extend_from_slice(&parent);
+    }
+
+    // The inputs are big endian within each byte, as sha256 expects, the blake2s gadget
+    // takes the bits of each byte in little endian order.
+    let preimage: Vec<Boolean> = ciphertexts
+        .chunks(8)
+        .flat_map(|chunk| chunk.iter().rev())
+        .cloned()
+        .collect();
+
+    let personalization = [0u8; 8];
+    let alloc_bits = blake2s_circuit(cs.namespace(|| "hash"), &preimage, &personalization)?;
+
+    pack_bits(cs.namespace(|| "result_num"), &alloc_bits)
 }
 
 #[cfg(test)]
@@ -305,6 +603,7 @@ mod tests {
 
     use super::*;
 
+    use bellperson::gadgets::boolean::AllocatedBit;
     use bellperson::util_cs::test_cs::TestConstraintSystem;
     use ff::Field;
     use generic_array::typenum;
@@ -314,14 +613,14 @@ mod tests {
     use rand_xorshift::XorShiftRng;
     use storage_proofs_core::{
         cache_key::CacheKey,
-        compound_proof,
-        drgraph::{graph_height, BucketGraph, BASE_DEGREE},
-        fr32::{bytes_into_fr, fr_into_bytes},
-        hasher::PedersenHasher,
+        compound_proof::{self, CompoundProof},
+        drgraph::{graph_height, BucketGraph, Graph, BASE_DEGREE},
+        fr32::{bytes_into_fr, u64_into_fr},
+        hasher::{identity::IdentityHasher, PedersenHasher},
         merkle::MerkleProofTrait,
         proof::ProofScheme,
         test_helper::setup_replica,
-        util::{data_at_node, default_rows_to_discard},
+        util::{data_at_node, data_at_node_offset, default_rows_to_discard, NODE_SIZE},
     };
 
     use super::super::compound::DrgPoRepCompound;
@@ -469,8 +768,8 @@ mod tests {
         }
 
         assert!(cs.is_satisfied(), "constraints not satisfied");
-        assert_eq!(cs.num_inputs(), 18, "wrong number of inputs");
-        assert_eq!(cs.num_constraints(), 149_580, "wrong number of constraints");
+        // The roots are exposed once instead of once per inclusion proof.
+        assert_eq!(cs.num_inputs(), 12, "wrong number of inputs");
 
         assert_eq!(cs.get_input(0, "ONE"), Fr::one());
 
@@ -478,6 +777,13 @@ mod tests {
             cs.get_input(1, "drgporep/replica_id/input variable"),
             replica_id.unwrap()
         );
+        let comm_r: Fr = proof_nc.replica_root.into();
+        let comm_d: Fr = proof_nc.data_root.into();
+        assert_eq!(
+            cs.get_input(2, "drgporep/replica_root/input variable"),
+            comm_r
+        );
+        assert_eq!(cs.get_input(3, "drgporep/data_root/input variable"), comm_d);
 
         let generated_inputs =
                 <DrgPoRepCompound<_, _> as compound_proof::CompoundProof<_, _>>::generate_public_inputs(
@@ -504,31 +810,908 @@ mod tests {
     }
 
     #[test]
-    fn drgporep_input_circuit_num_constraints() {
+    fn drgporep_circuit_with_identity_hasher() {
         let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
 
-        // 1 GB
-        let n = (1 << 30) / 32;
+        let nodes = 16;
+        let challenge = 2;
+        let replica_id: Fr = Fr::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = drg::SetupParams {
+            drg: drg::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = drg::DrgPoRep::<IdentityHasher, BucketGraph<_>>::setup(&sp)
+            .expect("failed to create drgporep setup");
+        let (tau, aux) = drg::DrgPoRep::<IdentityHasher, _>::replicate(
+            &pp,
+            &replica_id.into(),
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("failed to replicate");
+
+        let pub_inputs = drg::PublicInputs {
+            replica_id: Some(replica_id.into()),
+            challenges: vec![challenge],
+            tau: Some(tau),
+        };
+        let priv_inputs = drg::PrivateInputs::<IdentityHasher> {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: default_rows_to_discard(nodes, BINARY_ARITY),
+        };
+        let proof = drg::DrgPoRep::<IdentityHasher, _>::prove(&pp, &pub_inputs, &priv_inputs)
+            .expect("failed to prove");
+        assert!(
+            drg::DrgPoRep::<IdentityHasher, _>::verify(&pp, &pub_inputs, &proof)
+                .expect("failed to verify")
+        );
+
+        // The same wiring, with the merkle paths hashed by `H`.
+        fn synthesize<H: 'static + Hasher>(
+            proof: &drg::Proof<IdentityHasher>,
+            replica_id: Fr,
+        ) -> TestConstraintSystem<Bls12> {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            DrgPoRepCircuit::<H>::synthesize(
+                cs.namespace(|| "drgporep"),
+                vec![Some(proof.replica_nodes[0].data.into())],
+                vec![proof.replica_nodes[0].proof.as_options()],
+                Root::Val(Some(proof.replica_root.into())),
+                vec![proof.replica_parents[0]
+                    .iter()
+                    .map(|(_, parent)| Some(parent.data.into()))
+                    .collect()],
+                vec![proof.replica_parents[0]
+                    .iter()
+                    .map(|(_, parent)| parent.proof.as_options())
+                    .collect()],
+                vec![Some(proof.nodes[0].data.into())],
+                vec![proof.nodes[0].proof.as_options()],
+                Root::Val(Some(proof.data_root.into())),
+                Some(replica_id),
+                false,
+            )
+            .expect("failed to synthesize circuit");
+            cs
+        }
+
+        let cs = synthesize::<IdentityHasher>(&proof, replica_id);
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert_eq!(cs.num_inputs(), 12, "wrong number of inputs");
+
+        let generated_inputs =
+            <DrgPoRepCompound<_, _> as compound_proof::CompoundProof<_, _>>::generate_public_inputs(
+                &pub_inputs,
+                &pp,
+                None,
+            )
+            .unwrap();
+        let inputs: Vec<Fr> = cs
+            .get_inputs()
+            .iter()
+            .skip(1)
+            .map(|(input, _)| *input)
+            .collect();
+        assert_eq!(inputs, generated_inputs);
+
+        // Only the kdf is left, every merkle hash is a single constraint.
+        let identity_constraints = cs.num_constraints();
+        let pedersen_constraints =
+            synthesize::<PedersenHasher>(&proof, replica_id).num_constraints();
+        assert!(identity_constraints < pedersen_constraints);
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    fn fr_to_kdf_bits(cs: &mut TestConstraintSystem<Bls12>, name: &str, value: Fr) -> Vec<Boolean> {
+        let num = num::AllocatedNum::alloc(cs.namespace(|| format!("{}_num", name)), || Ok(value))
+            .unwrap();
+        reverse_bit_numbering(
+            num.to_bits_le(cs.namespace(|| format!("{}_bits", name)))
+                .unwrap(),
+        )
+    }
+
+    fn kdf_matches_vanilla(m: usize) {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let id = Fr::random(rng);
+        let parents: Vec<Fr> = (0..m).map(|_| Fr::random(rng)).collect();
+
+        let id_bits = fr_to_kdf_bits(&mut cs, "id", id);
+        let parents_bits = parents
+            .iter()
+            .enumerate()
+            .map(|(i, parent)| fr_to_kdf_bits(&mut cs, &format!("parent_{}", i), *parent))
+            .collect();
+
+        let key =
+            kdf(cs.namespace(|| "kdf"), &id_bits, parents_bits, None, None).expect("kdf failed");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        let mut hasher = Sha256::new();
+        hasher.input(&fr_into_bytes(&id));
+        for parent in &parents {
+            hasher.input(&fr_into_bytes(parent));
+        }
+        let expected = Fr::from_repr(bytes_into_fr_repr_safe(hasher.result().as_ref())).unwrap();
+
+        assert_eq!(key.get_value().unwrap(), expected);
+    }
+
+    #[test]
+    fn kdf_3_parents() {
+        kdf_matches_vanilla(3);
+    }
+
+    #[test]
+    fn kdf_8_parents() {
+        kdf_matches_vanilla(8);
+    }
+
+    #[test]
+    fn kdf_elements_first_element_decodes() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let mut cs = TestConstraintSystem::<Bls12>::new();
         let m = BASE_DEGREE;
-        let tree_depth = graph_height::<typenum::U2>(n);
+        let node = m + 1;
+
+        let id = Fr::random(rng);
+        let parents: Vec<Fr> = (0..m).map(|_| Fr::random(rng)).collect();
+        let parents_data: Vec<u8> = parents.iter().flat_map(fr_into_bytes).collect();
+        let parent_nodes: Vec<u32> = (0..m as u32).collect();
+
+        let id_bits = fr_to_kdf_bits(&mut cs, "id", id);
+        let parents_bits = parents
+            .iter()
+            .enumerate()
+            .map(|(i, parent)| fr_to_kdf_bits(&mut cs, &format!("parent_{}", i), *parent))
+            .collect();
+
+        let key = kdf_elements(
+            cs.namespace(|| "kdf"),
+            &id_bits,
+            parents_bits,
+            None,
+            None,
+            2,
+        )
+        .expect("kdf failed");
+        assert_eq!(key.len(), 2);
+
+        let expected = drg::create_key_elements::<PedersenHasher>(
+            &id.into(),
+            node,
+            &parent_nodes,
+            &parents_data,
+            2,
+        )
+        .unwrap();
+        for (element, expected) in key.iter().zip(&expected) {
+            let expected: Fr = (*expected).into();
+            assert_eq!(element.get_value().unwrap(), expected);
+        }
+        assert_ne!(expected[0], expected[1]);
+
+        // The first element is the key of `kdf`, and decodes the replica node.
+        let graph = BucketGraph::<PedersenHasher>::new(node + 1, m, 0, [32; 32]).unwrap();
+        let graph_key = graph
+            .create_key(&id.into(), node, &parent_nodes, &parents_data, None)
+            .unwrap();
+        assert_eq!(expected[0], graph_key);
+
+        let data = Fr::random(rng);
+        let replica: Fr = crate::encode::encode(expected[0], data.into()).into();
+
+        let replica_num = num::AllocatedNum::alloc(cs.namespace(|| "replica"), || Ok(replica))
+            .expect("failed to allocate replica");
+        let data_num = num::AllocatedNum::alloc(cs.namespace(|| "data"), || Ok(data))
+            .expect("failed to allocate data");
+        let decoded = encode::decode(cs.namespace(|| "decode"), &key[0], &replica_num)
+            .expect("failed to decode");
+        constraint::equal(&mut cs, || "equality", &data_num, &decoded);
+
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+    }
+
+    #[test]
+    fn derive_key_bytes_matches_kdf() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let m = BASE_DEGREE;
+        let node = m + 1;
+
+        let id = Fr::random(rng);
+        let parents: Vec<Fr> = (0..m).map(|_| Fr::random(rng)).collect();
+        let parents_data: Vec<u8> = parents.iter().flat_map(fr_into_bytes).collect();
+        let parent_nodes: Vec<u32> = (0..m as u32).collect();
+
+        let parent_bytes: Vec<&[u8]> = parents_data.chunks(NODE_SIZE).collect();
+        let key_bytes = drg::derive_key_bytes(&fr_into_bytes(&id), &parent_bytes);
+        let key = bytes_into_fr(&key_bytes).expect("key is not canonical");
+
+        // The key used in replication.
+        let graph = BucketGraph::<PedersenHasher>::new(node + 1, m, 0, [32; 32]).unwrap();
+        let graph_key: Fr = graph
+            .create_key(&id.into(), node, &parent_nodes, &parents_data, None)
+            .unwrap()
+            .into();
+        assert_eq!(key, graph_key);
+        assert_eq!(key_bytes.to_vec(), fr_into_bytes(&key));
+
+        let id_bits = fr_to_kdf_bits(&mut cs, "id", id);
+        let parents_bits = parents
+            .iter()
+            .enumerate()
+            .map(|(i, parent)| fr_to_kdf_bits(&mut cs, &format!("parent_{}", i), *parent))
+            .collect();
+        let circuit_key =
+            kdf(cs.namespace(|| "kdf"), &id_bits, parents_bits, None, None).expect("kdf failed");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert_eq!(circuit_key.get_value().unwrap(), key);
+
+        // A node without parents hashes the replica id only.
+        let first_key: Fr = graph
+            .create_key(&id.into(), 0, &[0; BASE_DEGREE], &parents_data, None)
+            .unwrap()
+            .into();
+        let first_key_bytes = drg::derive_key_bytes(&fr_into_bytes(&id), &[]);
+        assert_eq!(bytes_into_fr(&first_key_bytes).unwrap(), first_key);
+    }
+
+    #[test]
+    fn kdf_blake2s_matches_vanilla() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let m = BASE_DEGREE;
+
+        let id = Fr::random(rng);
+        let parents: Vec<Fr> = (0..m).map(|_| Fr::random(rng)).collect();
+        let parents_data: Vec<u8> = parents.iter().flat_map(fr_into_bytes).collect();
+        let parent_nodes: Vec<u32> = (0..m as u32).collect();
+
+        let synthesize = |blake2s: bool| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let id_bits = fr_to_kdf_bits(&mut cs, "id", id);
+            let parents_bits = parents
+                .iter()
+                .enumerate()
+                .map(|(i, parent)| fr_to_kdf_bits(&mut cs, &format!("parent_{}", i), *parent))
+                .collect();
+            let before = cs.num_constraints();
+
+            let key = if blake2s {
+                kdf_blake2s(cs.namespace(|| "kdf"), &id_bits, parents_bits)
+            } else {
+                kdf(cs.namespace(|| "kdf"), &id_bits, parents_bits, None, None)
+            }
+            .expect("kdf failed");
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+
+            (key.get_value().unwrap(), cs.num_constraints() - before)
+        };
+
+        let (key, blake2s_constraints) = synthesize(true);
+        let expected: Fr =
+            drg::create_key_blake2s::<PedersenHasher>(&id.into(), m, &parent_nodes, &parents_data)
+                .unwrap()
+                .into();
+        assert_eq!(key, expected);
+
+        let (_, sha256_constraints) = synthesize(false);
+        assert!(blake2s_constraints < sha256_constraints);
+    }
+
+    #[test]
+    fn kdf_folds_constant_padding() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let m = BASE_DEGREE;
+
+        let id = Fr::random(rng);
+        let parents: Vec<Fr> = (0..m).map(|_| Fr::random(rng)).collect();
+
+        // Either keeps the constant padding bits, or allocates them like any other bit.
+        let synthesize = |allocate_padding: bool| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let mut to_bits = |name: &str, value: Fr| {
+                let bits = fr_to_kdf_bits(&mut cs, name, value);
+                if !allocate_padding {
+                    return bits;
+                }
+                bits.into_iter()
+                    .enumerate()
+                    .map(|(i, bit)| match bit {
+                        Boolean::Constant(value) => Boolean::from(
+                            AllocatedBit::alloc(
+                                cs.namespace(|| format!("{}_padding_{}", name, i)),
+                                Some(value),
+                            )
+                            .unwrap(),
+                        ),
+                        bit => bit,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let id_bits = to_bits("id", id);
+            let parents_bits = parents
+                .iter()
+                .enumerate()
+                .map(|(i, parent)| to_bits(&format!("parent_{}", i), *parent))
+                .collect::<Vec<_>>();
+            let before = cs.num_constraints();
+
+            let key = kdf(cs.namespace(|| "kdf"), &id_bits, parents_bits, None, None)
+                .expect("kdf failed");
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+
+            (key.get_value().unwrap(), cs.num_constraints() - before)
+        };
+
+        let (key, constant_constraints) = synthesize(false);
+        let (allocated_key, allocated_constraints) = synthesize(true);
+        assert_eq!(key, allocated_key);
+        assert!(
+            constant_constraints < allocated_constraints,
+            "constant padding costs {} constraints, allocated padding {}",
+            constant_constraints,
+            allocated_constraints
+        );
+    }
+
+    #[test]
+    fn kdf_rejects_malformed_parent() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let id_bits = fr_to_kdf_bits(&mut cs, "id", Fr::random(rng));
+        let mut parents_bits = vec![
+            fr_to_kdf_bits(&mut cs, "parent_0", Fr::random(rng)),
+            fr_to_kdf_bits(&mut cs, "parent_1", Fr::random(rng)),
+        ];
+        // Drop the padding bit, leaving a parent that is not a whole word.
+        parents_bits[1].pop();
+
+        assert!(kdf(cs.namespace(|| "kdf"), &id_bits, parents_bits, None, None).is_err());
+    }
+
+    /// Replicates random data and returns a (verified) vanilla proof for `challenges`.
+    fn test_proof(
+        nodes: usize,
+        challenges: Vec<usize>,
+    ) -> (
+        drg::PublicParams<PedersenHasher, BucketGraph<PedersenHasher>>,
+        drg::PublicInputs<<PedersenHasher as Hasher>::Domain>,
+        drg::Proof<PedersenHasher>,
+    ) {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let (pp, pub_inputs, proof, _) = test_proof_for_data(&data, challenges);
+        (pp, pub_inputs, proof)
+    }
+
+    /// Like `test_proof`, but replicating `data`, and also returning the replica.
+    fn test_proof_for_data(
+        data: &[u8],
+        challenges: Vec<usize>,
+    ) -> (
+        drg::PublicParams<PedersenHasher, BucketGraph<PedersenHasher>>,
+        drg::PublicInputs<<PedersenHasher as Hasher>::Domain>,
+        drg::Proof<PedersenHasher>,
+        Vec<u8>,
+    ) {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = data.len() / NODE_SIZE;
+        let replica_id: Fr = Fr::random(rng);
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(data, &replica_path);
+
+        let sp = drg::SetupParams {
+            drg: drg::DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: challenges.len(),
+        };
+
+        let pp = drg::DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp)
+            .expect("failed to create drgporep setup");
+        let (tau, aux) = drg::DrgPoRep::<PedersenHasher, _>::replicate(
+            &pp,
+            &replica_id.into(),
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("failed to replicate");
+
+        let pub_inputs = drg::PublicInputs {
+            replica_id: Some(replica_id.into()),
+            challenges,
+            tau: Some(tau),
+        };
+        let priv_inputs = drg::PrivateInputs::<PedersenHasher> {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: default_rows_to_discard(nodes, BINARY_ARITY),
+        };
+
+        let proof = drg::DrgPoRep::<PedersenHasher, _>::prove(&pp, &pub_inputs, &priv_inputs)
+            .expect("failed to prove");
+        assert!(
+            drg::DrgPoRep::<PedersenHasher, _>::verify(&pp, &pub_inputs, &proof)
+                .expect("failed to verify"),
+            "failed to verify (non circuit)"
+        );
+
+        let replica = mmapped_data.to_vec();
+        cache_dir.close().expect("Failed to remove cache dir");
+
+        (pp, pub_inputs, proof, replica)
+    }
+
+    #[test]
+    fn drgporep_public_input_bits() {
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2]);
+
+        let num_inputs = |public_input_bits: usize| {
+            let mut circuit =
+                DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+                    .expect("failed to create circuit");
+            circuit.public_input_bits = public_input_bits;
+            let inputs = DrgPoRepCompound::generate_public_inputs_with_input_bits(
+                &pub_inputs,
+                &pp,
+                public_input_bits,
+            )
+            .expect("failed to generate public inputs");
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            circuit
+                .synthesize(&mut cs)
+                .expect("failed to synthesize circuit");
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert!(cs.verify(&inputs), "failed to verify inputs");
+
+            cs.num_inputs()
+        };
+
+        // The 4 auth path bits of each of the 2 + BASE_DEGREE inclusion proofs take 1 input
+        // by default, and 2 when packing 2 bits per input.
+        let default_inputs = num_inputs(Fr::CAPACITY as usize);
+        assert_eq!(default_inputs, 12);
+        assert_eq!(num_inputs(2), default_inputs + 2 + BASE_DEGREE);
+    }
+
+    #[test]
+    fn drgporep_zero_data_nodes() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        // Node 0 has no parents, node 3 is an all zero node with parents.
+        let zero_nodes = [0, 3];
+        let mut data: Vec<u8> = (0..16)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        for node in &zero_nodes {
+            let start = data_at_node_offset(*node);
+            data[start..start + NODE_SIZE].copy_from_slice(&[0; NODE_SIZE]);
+        }
+
+        let (pp, pub_inputs, proof, replica) = test_proof_for_data(&data, vec![3]);
+        let zero: <PedersenHasher as Hasher>::Domain = Fr::zero().into();
+        assert_eq!(proof.nodes[0].data, zero);
+        assert_ne!(
+            proof.replica_nodes[0].data, zero,
+            "zero node was not encoded"
+        );
+
+        let circuit = DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+            .expect("failed to create circuit");
+        let inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert!(cs.verify(&inputs), "failed to verify inputs");
+
+        let replica_id = pub_inputs.replica_id.unwrap();
+        for node in &zero_nodes {
+            let extracted = drg::DrgPoRep::extract(&pp, &replica_id, &replica, *node, None)
+                .expect("failed to extract");
+            assert_eq!(extracted, vec![0; NODE_SIZE]);
+        }
+        let extracted = drg::DrgPoRep::extract_all(&pp, &replica_id, &replica, None)
+            .expect("failed to extract");
+        assert_eq!(extracted, data);
+    }
+
+    #[test]
+    fn drgporep_data_range() {
+        let (_, _, proof) = test_proof(16, vec![4, 5, 6]);
+        let data_nodes: Vec<_> = proof
+            .nodes
+            .iter()
+            .map(|node| (Some(node.data.into()), node.proof.as_options()))
+            .collect();
+        let data_root: Fr = proof.data_root.into();
+
+        let depth = data_nodes[0].1.len();
+        // 4, 5 and 6 are in the subtree of 4 to 7.
+        let shared_levels = data_range_shared_levels(4, 3, depth);
+        assert_eq!(shared_levels, depth - 2);
+
+        let synthesize = |data_nodes: &[_], shared_levels| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            DrgPoRepCircuit::<PedersenHasher>::synthesize_data_range(
+                cs.namespace(|| "drgporep"),
+                data_nodes,
+                shared_levels,
+                Root::Val(Some(data_root)),
+                false,
+            )
+            .map(|_| cs)
+        };
+
+        let cs = synthesize(&data_nodes[..], shared_levels).expect("failed to synthesize circuit");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        // The path of each node and the root.
+        assert_eq!(cs.num_inputs(), 1 + 3 + 1);
+        let expected_inputs: Vec<Fr> = (4..7u64)
+            .map(u64_into_fr)
+            .chain(std::iter::once(data_root))
+            .collect();
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+        // Nothing is shared, each path is ascended to the root.
+        let unshared = synthesize(&data_nodes[..], 0).expect("failed to synthesize circuit");
+        assert!(unshared.is_satisfied(), "constraints not satisfied");
+        assert!(cs.num_constraints() < unshared.num_constraints());
+
+        let gap = [data_nodes[0].clone(), data_nodes[2].clone()];
+        assert!(synthesize(&gap[..], shared_levels).is_err());
+    }
+
+    #[test]
+    fn drgporep_replica_id_is_bound_to_public_input() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2]);
+
+        let circuit = DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+            .expect("failed to create circuit");
+        let inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert!(cs.verify(&inputs), "failed to verify inputs");
+
+        // The replica id is the first public input.
+        let mut wrong_inputs = inputs.clone();
+        wrong_inputs[0] = Fr::random(rng);
+        assert!(!cs.verify(&wrong_inputs), "verified a different replica id");
+
+        // Exposing a different replica id than the one fed into the kdf must not be satisfiable.
+        cs.set("replica_id/input variable", Fr::random(rng));
+        assert!(
+            !cs.is_satisfied(),
+            "public replica id is not bound to the kdf input"
+        );
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn drgporep_groth16_end_to_end() {
+        use bellperson::groth16;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2, 9]);
+
+        let blank_circuit = <DrgPoRepCompound<_, _> as CompoundProof<_, _>>::blank_circuit(&pp);
+        let groth_params = groth16::generate_random_parameters::<Bls12, _, _>(blank_circuit, rng)
+            .expect("failed to generate parameters");
+        let pvk = groth16::prepare_verifying_key(&groth_params.vk);
+
+        let circuit = DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+            .expect("failed to create circuit");
+        let groth_proof = groth16::create_random_proof(circuit, &groth_params, rng)
+            .expect("failed to create proof");
+
+        let inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+        assert!(
+            groth16::verify_proof(&pvk, &groth_proof, &inputs).expect("failed to verify"),
+            "failed to verify groth16 proof"
+        );
+
+        let mut tampered = inputs;
+        let last = tampered.len() - 1;
+        tampered[last] = Fr::random(rng);
+        assert!(
+            !groth16::verify_proof(&pvk, &groth_proof, &tampered).expect("failed to verify"),
+            "verified groth16 proof against a tampered public input"
+        );
+    }
+
+    #[test]
+    fn drgporep_challenge_is_bound_to_public_input() {
+        let (pp, pub_inputs, proof) = test_proof(16, vec![5]);
+
+        let circuit = DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+            .expect("failed to create circuit");
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+
+        // The verifier challenges node 2, the proof is for node 5.
+        let challenged = drg::PublicInputs {
+            challenges: vec![2],
+            ..pub_inputs
+        };
+        let inputs = DrgPoRepCompound::generate_public_inputs(&challenged, &pp, None)
+            .expect("failed to generate public inputs");
+        assert!(!cs.verify(&inputs), "verified a proof for another node");
+
+        // Inputs: replica_id, comm_r and comm_d, then the paths of the replica node, each of
+        // its parents and the data node.
+        let replica_path_input = 3;
+        let data_path_input = 3 + 1 + pp.graph.degree();
+        cs.set(
+            "challenge_0/inclusion_checks/replica_inclusion/path/input 0",
+            inputs[replica_path_input],
+        );
+        cs.set(
+            "challenge_0/inclusion_checks/data_inclusion/path/input 0",
+            inputs[data_path_input],
+        );
+        assert!(
+            !cs.is_satisfied(),
+            "paths for node 5 satisfied the public challenge 2"
+        );
+    }
+
+    #[test]
+    fn drgporep_data_path_for_another_node_fails_public_inputs() {
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2, 5]);
+        let inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+
+        // Prove the data node of challenge 5 for challenge 2, and the other way around.
+        let mut circuit =
+            DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+                .expect("failed to create circuit");
+        circuit.data_nodes.swap(0, 1);
+        circuit.data_nodes_paths.swap(0, 1);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(
+            !cs.verify(&inputs),
+            "data paths for other nodes matched the public challenges"
+        );
+    }
+
+    #[test]
+    fn drgporep_hashed_replica_id_input() {
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2]);
+        let replica_id: Fr = pub_inputs.replica_id.unwrap().into();
+
+        let mut circuit =
+            DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+                .expect("failed to create circuit");
+        circuit.hash_replica_id = true;
+        let mut inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert!(!cs.verify(&inputs), "exposed the raw replica id");
+
+        let expected = Fr::from_repr(bytes_into_fr_repr_safe(
+            Sha256::digest(&fr_into_bytes(&replica_id)).as_ref(),
+        ))
+        .unwrap();
+        assert_eq!(replica_id_digest(replica_id), expected);
+
+        inputs[0] = expected;
+        assert!(cs.verify(&inputs), "failed to verify the replica id digest");
+    }
+
+    #[test]
+    fn drgporep_challenge_input() {
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2]);
+        let inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+
+        let synthesize = |challenge: u64| {
+            let mut circuit =
+                DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+                    .expect("failed to create circuit");
+            circuit.challenges = Some(vec![Some(u64_into_fr(challenge))]);
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            circuit
+                .synthesize(&mut cs)
+                .expect("failed to synthesize circuit");
+            cs
+        };
+
+        let cs = synthesize(2);
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert_eq!(
+            cs.get_input(4, "challenge_0/challenge/input variable"),
+            u64_into_fr(2)
+        );
+
+        // The challenge precedes the auth path bits, after the replica id and the roots.
+        let mut expected_inputs = inputs.clone();
+        expected_inputs.insert(3, u64_into_fr(2));
+        assert!(
+            cs.verify(&expected_inputs),
+            "failed to verify the challenge"
+        );
+        assert!(!cs.verify(&inputs));
+
+        let cs = synthesize(3);
+        assert!(
+            !cs.is_satisfied(),
+            "exposed a challenge which disagrees with the paths"
+        );
+    }
+
+    #[test]
+    fn drgporep_forward_encoding_matches_decoding() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let (pp, pub_inputs, proof) = test_proof(16, vec![2, 7]);
+        let inputs = DrgPoRepCompound::generate_public_inputs(&pub_inputs, &pp, None)
+            .expect("failed to generate public inputs");
+
+        let synthesize = |forward_encoding| {
+            let mut circuit =
+                DrgPoRepCompound::circuit(&pub_inputs, Default::default(), &proof, &pp, None)
+                    .expect("failed to create circuit");
+            circuit.forward_encoding = forward_encoding;
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            circuit
+                .synthesize(&mut cs)
+                .expect("failed to synthesize circuit");
+            cs
+        };
+
+        let mut decoding = synthesize(false);
+        let mut encoding = synthesize(true);
+        assert!(
+            decoding.is_satisfied(),
+            "decoding constraints not satisfied"
+        );
+        assert!(
+            encoding.is_satisfied(),
+            "encoding constraints not satisfied"
+        );
+        assert!(decoding.verify(&inputs), "failed to verify decoding inputs");
+        assert!(encoding.verify(&inputs), "failed to verify encoding inputs");
+        assert_eq!(decoding.num_constraints(), encoding.num_constraints());
+        assert_eq!(decoding.num_inputs(), encoding.num_inputs());
+
+        // A data node that does not encode to the replica node fails under both.
+        let data_node = "challenge_0/encoding_checks/data node/num";
+        let wrong = Fr::random(rng);
+        decoding.set(data_node, wrong);
+        encoding.set(data_node, wrong);
+        assert!(
+            !decoding.is_satisfied(),
+            "decoding accepted a wrong data node"
+        );
+        assert!(
+            !encoding.is_satisfied(),
+            "encoding accepted a wrong data node"
+        );
+    }
+
+    /// Synthesizes a circuit for a single challenge in a sector of `n` nodes with `m` parents,
+    /// returning its number of inputs and constraints.
+    fn circuit_size(n: usize, m: usize) -> (usize, usize) {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let tree_depth = graph_height::<typenum::U2>(n) - 1;
+        let path = |rng: &mut XorShiftRng| vec![(vec![Some(Fr::random(rng))], Some(0)); tree_depth];
 
         let mut cs = TestConstraintSystem::<Bls12>::new();
         DrgPoRepCircuit::<PedersenHasher>::synthesize(
             cs.namespace(|| "drgporep"),
-            vec![Some(Fr::random(rng)); 1],
-            vec![vec![(vec![Some(Fr::random(rng))], Some(0)); tree_depth]; 1],
+            vec![Some(Fr::random(rng))],
+            vec![path(rng)],
             Root::Val(Some(Fr::random(rng))),
-            vec![vec![Some(Fr::random(rng)); m]; 1],
-            vec![vec![vec![(vec![Some(Fr::random(rng))], Some(0)); tree_depth]; m]; 1],
-            vec![Some(Fr::random(rng)); 1],
-            vec![vec![(vec![Some(Fr::random(rng))], Some(0)); tree_depth]; 1],
+            vec![vec![Some(Fr::random(rng)); m]],
+            vec![vec![path(rng); m]],
+            vec![Some(Fr::random(rng))],
+            vec![path(rng)],
             Root::Val(Some(Fr::random(rng))),
             Some(Fr::random(rng)),
             false,
         )
         .expect("failed to synthesize circuit");
 
-        assert_eq!(cs.num_inputs(), 18, "wrong number of inputs");
-        assert_eq!(cs.num_constraints(), 391_404, "wrong number of constraints");
+        (cs.num_inputs(), cs.num_constraints())
+    }
+
+    #[test]
+    fn drgporep_input_circuit_num_constraints() {
+        // Nodes are always 32 bytes here, so only the sector size varies. Each of the 2 + m = 8
+        // inclusion proofs costs 1_374 constraints per level of the tree (the pedersen hash of
+        // the two children and the swap on the path bit). The remaining 105_606 do not depend on
+        // the sector size: the kdf over the replica id and the parents, the sloth decoding of the
+        // challenged node and the packing of the public inputs.
+        let cases = [
+            // The 16 nodes sector of `drgporep_input_circuit_with_bls12_381`.
+            (16, 149_574),
+            (1 << 10, 215_526),
+            (1 << 20, 325_446),
+            // 1GiB
+            ((1 << 30) / 32, 380_406),
+        ];
+
+        for &(n, expected) in &cases {
+            let depth = graph_height::<typenum::U2>(n) - 1;
+            assert_eq!(expected, 105_606 + 8 * 1_374 * depth);
+
+            let (inputs, constraints) = circuit_size(n, BASE_DEGREE);
+            // Replica id, comm_r, comm_d and the path of each inclusion proof.
+            assert_eq!(inputs, 12, "wrong number of inputs for {} nodes", n);
+            assert_eq!(
+                constraints, expected,
+                "wrong number of constraints for {} nodes",
+                n
+            );
+        }
     }
 }
@@ -2,19 +2,20 @@ use std::marker::PhantomData;
 
 use anyhow::{ensure, Context};
 use bellperson::Circuit;
+use ff::PrimeField;
 use generic_array::typenum;
 use paired::bls12_381::{Bls12, Fr};
 
 use storage_proofs_core::{
     compound_proof::{CircuitComponent, CompoundProof},
-    drgraph::Graph,
+    drgraph::{graph_height, Graph},
     error::Result,
-    gadgets::por::PoRCompound,
+    gadgets::multipack::compute_multipacking_with_width,
+    gadgets::por::challenge_into_auth_path_bits,
     gadgets::variables::Root,
     hasher::Hasher,
-    merkle::{BinaryMerkleTree, MerkleProofTrait},
+    merkle::MerkleProofTrait,
     parameter_cache::{CacheableParameters, ParameterSetMetadata},
-    por,
     proof::ProofScheme,
 };
 
@@ -64,19 +65,18 @@ where
     }
 }
 
-impl<'a, H, G> CompoundProof<'a, DrgPoRep<'a, H, G>, DrgPoRepCircuit<'a, H>>
-    for DrgPoRepCompound<H, G>
+impl<'a, H, G> DrgPoRepCompound<H, G>
 where
     H: 'static + Hasher,
     G::Key: AsRef<<H as Hasher>::Domain>,
     G: 'a + Graph<H> + ParameterSetMetadata + Sync + Send,
 {
-    fn generate_public_inputs(
+    /// Same as `CompoundProof::generate_public_inputs`, for circuits packing
+    /// `public_input_bits` bits into each public input (see `DrgPoRepCircuit`).
+    pub fn generate_public_inputs_with_input_bits(
         pub_in: &<DrgPoRep<'a, H, G> as ProofScheme<'a>>::PublicInputs,
         pub_params: &<DrgPoRep<'a, H, G> as ProofScheme<'a>>::PublicParams,
-        // We can ignore k because challenges are generated by caller and included
-        // in PublicInputs.
-        _k: Option<usize>,
+        public_input_bits: usize,
     ) -> Result<Vec<Fr>> {
         let replica_id = pub_in.replica_id.context("missing replica id")?;
         let challenges = &pub_in.challenges;
@@ -86,55 +86,54 @@ where
             "Public input parameter tau must be unset"
         );
 
-        let (comm_r, comm_d) = match pub_in.tau {
-            None => (None, None),
-            Some(tau) => (Some(tau.comm_r), Some(tau.comm_d)),
-        };
-
         let leaves = pub_params.graph.size();
 
-        let por_pub_params = por::PublicParams {
-            leaves,
-            private: pub_params.private,
+        // The commitments are exposed once by the circuit, so the inclusion proofs only
+        // contribute their auth path bits.
+        let por_inputs = |challenge: usize| {
+            let path_bits = challenge_into_auth_path_bits(challenge, leaves);
+            compute_multipacking_with_width::<Bls12>(&path_bits, public_input_bits)
         };
 
         let mut input: Vec<Fr> = Vec::new();
         input.push(replica_id.into());
 
+        if let Some(tau) = pub_in.tau {
+            input.push(tau.comm_r.into());
+            input.push(tau.comm_d.into());
+        }
+
         let mut parents = vec![0; pub_params.graph.degree()];
         for challenge in challenges {
-            let mut por_nodes = vec![*challenge as u32];
+            input.extend(por_inputs(*challenge));
+
             pub_params.graph.parents(*challenge, &mut parents)?;
-            por_nodes.extend_from_slice(&parents);
-
-            for node in por_nodes {
-                let por_pub_inputs = por::PublicInputs {
-                    commitment: comm_r,
-                    challenge: node as usize,
-                };
-                let por_inputs = PoRCompound::<BinaryMerkleTree<H>>::generate_public_inputs(
-                    &por_pub_inputs,
-                    &por_pub_params,
-                    None,
-                )?;
-
-                input.extend(por_inputs);
+            for parent in &parents {
+                input.extend(por_inputs(*parent as usize));
             }
 
-            let por_pub_inputs = por::PublicInputs {
-                commitment: comm_d,
-                challenge: *challenge,
-            };
-
-            let por_inputs = PoRCompound::<BinaryMerkleTree<H>>::generate_public_inputs(
-                &por_pub_inputs,
-                &por_pub_params,
-                None,
-            )?;
-            input.extend(por_inputs);
+            input.extend(por_inputs(*challenge));
         }
         Ok(input)
     }
+}
+
+impl<'a, H, G> CompoundProof<'a, DrgPoRep<'a, H, G>, DrgPoRepCircuit<'a, H>>
+    for DrgPoRepCompound<H, G>
+where
+    H: 'static + Hasher,
+    G::Key: AsRef<<H as Hasher>::Domain>,
+    G: 'a + Graph<H> + ParameterSetMetadata + Sync + Send,
+{
+    fn generate_public_inputs(
+        pub_in: &<DrgPoRep<'a, H, G> as ProofScheme<'a>>::PublicInputs,
+        pub_params: &<DrgPoRep<'a, H, G> as ProofScheme<'a>>::PublicParams,
+        // We can ignore k because challenges are generated by caller and included
+        // in PublicInputs.
+        _k: Option<usize>,
+    ) -> Result<Vec<Fr>> {
+        Self::generate_public_inputs_with_input_bits(pub_in, pub_params, Fr::CAPACITY as usize)
+    }
 
     fn circuit(
         public_inputs: &<DrgPoRep<'a, H, G> as ProofScheme<'a>>::PublicInputs,
@@ -156,17 +155,19 @@ where
             "Number of replica nodes must match"
         );
 
+        let depth = graph_height::<typenum::U2>(public_params.graph.size()) - 1;
+
         let replica_nodes: Vec<_> = proof
             .replica_nodes
             .iter()
             .map(|node| Some(node.data.into()))
             .collect();
 
-        let replica_nodes_paths: Vec<_> = proof
+        let replica_nodes_paths = proof
             .replica_nodes
             .iter()
-            .map(|node| node.proof.as_options())
-            .collect();
+            .map(|node| node.proof.as_circuit_options(depth))
+            .collect::<Result<Vec<_>>>()?;
 
         let is_private = public_params.private;
 
@@ -195,17 +196,16 @@ where
             })
             .collect();
 
-        let replica_parents_paths: Vec<Vec<_>> = proof
+        let replica_parents_paths = proof
             .replica_parents
             .iter()
             .map(|parents| {
-                let p: Vec<_> = parents
+                parents
                     .iter()
-                    .map(|(_, parent)| parent.proof.as_options())
-                    .collect();
-                p
+                    .map(|(_, parent)| parent.proof.as_circuit_options(depth))
+                    .collect::<Result<Vec<_>>>()
             })
-            .collect();
+            .collect::<Result<Vec<_>>>()?;
 
         let data_nodes: Vec<_> = proof
             .nodes
@@ -213,11 +213,11 @@ where
             .map(|node| Some(node.data.into()))
             .collect();
 
-        let data_nodes_paths: Vec<_> = proof
+        let data_nodes_paths = proof
             .nodes
             .iter()
-            .map(|node| node.proof.as_options())
-            .collect();
+            .map(|node| node.proof.as_circuit_options(depth))
+            .collect::<Result<Vec<_>>>()?;
 
         ensure!(
             public_inputs.tau.is_none() == public_params.private,
@@ -235,6 +235,11 @@ where
             data_root,
             replica_id: replica_id.map(Into::into),
             private: public_params.private,
+            forward_encoding: false,
+            hash_replica_id: false,
+            verify_commitments: true,
+            public_input_bits: Fr::CAPACITY as usize,
+            challenges: None,
             _h: Default::default(),
         })
     }
@@ -272,6 +277,11 @@ where
             data_root,
             replica_id: None,
             private: public_params.private,
+            forward_encoding: false,
+            hash_replica_id: false,
+            verify_commitments: true,
+            public_input_bits: Fr::CAPACITY as usize,
+            challenges: None,
             _h: Default::default(),
         }
     }
@@ -281,6 +291,8 @@ where
 mod tests {
     use super::*;
 
+    use std::time::Duration;
+
     use bellperson::util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem};
     use ff::Field;
     use merkletree::store::StoreConfig;
@@ -294,6 +306,7 @@ mod tests {
         fr32::fr_into_bytes,
         hasher::{Hasher, PedersenHasher, PoseidonHasher},
         merkle::{BinaryMerkleTree, MerkleTreeTrait},
+        multi_proof::MultiProof,
         proof::NoRequirements,
         test_helper::setup_replica,
         util::default_rows_to_discard,
@@ -461,6 +474,78 @@ mod tests {
             .expect("failed while verifying");
 
             assert!(verified);
+
+            let (instrumented, timings) = DrgPoRepCompound::<Tree::Hasher, _>::prove_instrumented(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                &gparams,
+            )
+            .expect("failed while proving");
+            assert!(DrgPoRepCompound::<Tree::Hasher, _>::verify(
+                &public_params,
+                &public_inputs,
+                &instrumented,
+                &NoRequirements,
+            )
+            .expect("failed while verifying"));
+
+            let phases =
+                timings.vanilla_proving + timings.witness_assignment + timings.groth_proving;
+            assert!(timings.vanilla_proving > Duration::from_secs(0));
+            assert!(timings.groth_proving > Duration::from_secs(0));
+            assert!(phases <= timings.total);
+            // Nothing but bookkeeping happens between the phases.
+            assert!(
+                timings.total - phases <= timings.total / 10,
+                "untimed work in {:?}",
+                timings
+            );
+
+            // Proofs sharing the verifying key can be verified together,
+            // but a single bad proof fails the whole batch.
+            let public_inputs2 = drg::PublicInputs {
+                challenges: vec![2, 5],
+                ..public_inputs.clone()
+            };
+            let proof2 = DrgPoRepCompound::<Tree::Hasher, _>::prove(
+                &public_params,
+                &public_inputs2,
+                &private_inputs,
+                &gparams,
+            )
+            .expect("failed while proving");
+
+            let mut corrupted_proof = proof2.circuit_proofs[0].clone();
+            corrupted_proof.c = proof.circuit_proofs[0].c;
+            let corrupted = MultiProof::new(vec![corrupted_proof], proof2.verifying_key);
+
+            let valid_batch = vec![
+                MultiProof::new(proof.circuit_proofs.clone(), proof.verifying_key),
+                MultiProof::new(proof2.circuit_proofs.clone(), proof2.verifying_key),
+            ];
+            let verified = DrgPoRepCompound::<Tree::Hasher, _>::batch_verify(
+                &public_params,
+                &[public_inputs.clone(), public_inputs2.clone()],
+                &valid_batch,
+                &NoRequirements,
+            )
+            .expect("failed while batch verifying");
+            assert!(verified);
+
+            let corrupt_batch = vec![proof, proof2, corrupted];
+            let verified = DrgPoRepCompound::<Tree::Hasher, _>::batch_verify(
+                &public_params,
+                &[
+                    public_inputs.clone(),
+                    public_inputs2.clone(),
+                    public_inputs2,
+                ],
+                &corrupt_batch,
+                &NoRequirements,
+            )
+            .expect("failed while batch verifying");
+            assert!(!verified, "batch with a corrupted proof verified");
         }
 
         cache_dir.close().expect("Failed to remove cache dir");
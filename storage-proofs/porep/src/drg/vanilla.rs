@@ -1,21 +1,28 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::marker::PhantomData;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use anyhow::{ensure, Context};
-use generic_array::typenum;
+use anyhow::{anyhow, ensure, Context};
+use generic_array::typenum::{self, Unsigned};
+use merkletree::merkle::get_merkle_tree_len;
 use merkletree::store::{ReplicaConfig, StoreConfig};
+use rand::Rng;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use storage_proofs_core::{
-    drgraph::Graph,
+    drgraph::{graph_height, Graph, BASE_DEGREE},
     error::Result,
-    fr32::bytes_into_fr_repr_safe,
-    hasher::{Domain, HashFunction, Hasher, PoseidonArity},
+    fr32::{bytes_into_fr, bytes_into_fr_repr_safe},
+    hasher::{Blake2sDomain, Blake2sFunction, Domain, HashFunction, Hasher, PoseidonArity},
     merkle::{
-        create_base_lcmerkle_tree, create_base_merkle_tree, BinaryLCMerkleTree, BinaryMerkleTree,
-        LCMerkleTree, MerkleProof, MerkleProofTrait, MerkleTreeTrait,
+        create_base_lcmerkle_tree, create_base_merkle_tree, create_disk_tree, create_lc_tree,
+        BinaryLCMerkleTree, BinaryMerkleTree, LCMerkleTree, MerkleProof, MerkleProofTrait,
+        MerkleTreeTrait,
     },
     parameter_cache::ParameterSetMetadata,
     proof::{NoRequirements, ProofScheme},
@@ -25,7 +32,7 @@ use storage_proofs_core::{
 
 use crate::{encode, PoRep};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tau<T> {
     pub comm_r: T,
     pub comm_d: T,
@@ -41,14 +48,205 @@ impl<T: Domain> Tau<T> {
 pub struct ProverAux<H: Hasher> {
     pub tree_d: BinaryMerkleTree<H>,
     pub tree_r: BinaryLCMerkleTree<H>,
+    /// Where the trees are stored, if they were built by `replicate`.
+    persisted: Option<PersistedAux>,
+}
+
+/// Where `replicate` stored the trees of a `ProverAux`, which is all that is needed to open
+/// them again in another process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedAux {
+    pub nodes: usize,
+    pub config: StoreConfig,
+    pub replica_path: PathBuf,
 }
 
 impl<H: Hasher> ProverAux<H> {
     pub fn new(tree_d: BinaryMerkleTree<H>, tree_r: BinaryLCMerkleTree<H>) -> Self {
-        ProverAux { tree_d, tree_r }
+        ProverAux {
+            tree_d,
+            tree_r,
+            persisted: None,
+        }
+    }
+
+    fn with_persisted(self, persisted: PersistedAux) -> Self {
+        ProverAux {
+            persisted: Some(persisted),
+            ..self
+        }
+    }
+
+    /// Where the trees are stored, `None` unless this was returned by `replicate`.
+    pub fn persisted(&self) -> Option<&PersistedAux> {
+        self.persisted.as_ref()
+    }
+
+    /// Writes where the trees are stored to `path`, so that `read` can open them again once
+    /// this is dropped. The trees and the replica must stay where `replicate` put them.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let persisted = self
+            .persisted
+            .as_ref()
+            .context("the trees of this aux were not built by replicate")?;
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("could not create aux {:?}", path))?;
+        serde_json::to_writer(file, persisted)?;
+
+        Ok(())
+    }
+
+    /// Opens the trees written by `write`. Proofs generated with them are identical to the
+    /// ones generated with the aux returned by `replicate`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("could not open aux {:?}", path))?;
+        let persisted: PersistedAux = serde_json::from_reader(file)?;
+
+        Self::open(persisted)
+    }
+
+    /// Opens the trees stored at `persisted`. Nothing is loaded up front: the trees read the
+    /// nodes and the rows of the paths from disk, and the leaves of the replica tree from the
+    /// replica.
+    pub fn open(persisted: PersistedAux) -> Result<Self> {
+        use storage_proofs_core::cache_key::CacheKey;
+
+        let tree_len = get_merkle_tree_len(persisted.nodes, typenum::U2::to_usize())?;
+        let tree_d = create_disk_tree::<BinaryMerkleTree<H>>(tree_len, &[persisted.config.clone()])
+            .context("tree_d")?;
+
+        let replica_config = ReplicaConfig {
+            path: persisted.replica_path.clone(),
+            offsets: vec![0],
+        };
+        let tree_r_last_config =
+            StoreConfig::from_config(&persisted.config, CacheKey::CommRLastTree.to_string(), None);
+        let tree_r = create_lc_tree::<BinaryLCMerkleTree<H>>(
+            tree_len,
+            &[tree_r_last_config],
+            &replica_config,
+        )
+        .context("tree_r")?;
+
+        Ok(ProverAux::new(tree_d, tree_r).with_persisted(persisted))
+    }
+}
+
+/// Throughput of a replication, as reported by `DrgPoRep::replicate_with_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicationStats {
+    /// Number of nodes encoded.
+    pub nodes: usize,
+    /// Number of bytes encoded.
+    pub bytes: usize,
+    /// Total time spent replicating.
+    pub elapsed: Duration,
+    /// Time spent deriving keys and encoding nodes.
+    pub kdf_time: Duration,
+    /// Time spent building the data and replica trees.
+    pub merkle_time: Duration,
+}
+
+impl ReplicationStats {
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn nodes_per_sec(&self) -> f64 {
+        self.nodes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// How far a replication got, as written by `DrgPoRep::replicate_checkpointed` and read by
+/// `DrgPoRep::resume_replicate`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationCheckpoint<T> {
+    pub replica_id: T,
+    /// Number of nodes encoded in the state, the following nodes still hold the data.
+    pub encoded: usize,
+    /// Number of nodes encoded between two checkpoints.
+    pub interval: usize,
+    /// Where the partially encoded replica is stored.
+    pub state_path: PathBuf,
+    /// Where the data tree is stored and where the replica goes.
+    pub persisted: PersistedAux,
+}
+
+impl<T: Domain> ReplicationCheckpoint<T> {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("could not open checkpoint {:?}", path))?;
+
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Replaces the checkpoint at `path`. It is written next to it first and then renamed, so
+    /// a crash leaves either the previous or the new checkpoint behind.
+    fn write(&self, path: &Path) -> Result<()> {
+        let tmp_path = with_suffix(path, ".tmp");
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("could not create checkpoint {:?}", tmp_path))?;
+        serde_json::to_writer(&file, self)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not write checkpoint {:?}", path))?;
+
+        Ok(())
+    }
+
+    /// Writes the nodes of `data` encoded since the last checkpoint to the state, up to `done`,
+    /// and then the checkpoint itself.
+    fn save(&mut self, path: &Path, data: &[u8], done: usize) -> Result<()> {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let start = data_at_node_offset(self.encoded);
+        let end = data_at_node_offset(done);
+        let mut state = OpenOptions::new()
+            .write(true)
+            .open(&self.state_path)
+            .with_context(|| format!("could not open checkpoint state {:?}", self.state_path))?;
+        state.seek(SeekFrom::Start(start as u64))?;
+        state.write_all(&data[start..end])?;
+        state.sync_data()?;
+
+        self.encoded = done;
+        self.write(path)
+    }
+
+    /// Removes the checkpoint at `path` and its state, once replication is done.
+    fn remove(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(&self.state_path)
+            .with_context(|| format!("could not remove checkpoint state {:?}", self.state_path))?;
+        std::fs::remove_file(path)
+            .with_context(|| format!("could not remove checkpoint {:?}", path))?;
+
+        Ok(())
     }
 }
 
+/// Writes `replica` to `replica_path`, which the replica tree reads its leaves from. The file is
+/// written over without truncating it first, so `replica` may be a mapping of it.
+fn write_replica(replica_path: &Path, replica: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(replica_path)
+        .with_context(|| format!("could not open replica file {:?}", replica_path))?;
+    file.write_all(replica)?;
+    file.set_len(replica.len() as u64)?;
+
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
 #[derive(Debug, Clone)]
 pub struct PublicInputs<T: Domain> {
     pub replica_id: Option<T>,
@@ -56,6 +254,69 @@ pub struct PublicInputs<T: Domain> {
     pub tau: Option<Tau<T>>,
 }
 
+impl<T: Domain> PublicInputs<T> {
+    /// Checks that every challenge is a node of the graph and that the commitments, if any, are
+    /// valid field elements. Both `prove` and `verify` call this first.
+    pub fn validate<H, G>(
+        &self,
+        pub_params: &PublicParams<H, G>,
+    ) -> std::result::Result<(), VerifyError>
+    where
+        H: Hasher<Domain = T>,
+        G: Graph<H> + ParameterSetMetadata,
+    {
+        let nodes = pub_params.graph.size();
+        if let Some(&challenge) = self.challenges.iter().find(|&&c| c >= nodes) {
+            return Err(VerifyError::ChallengeOutOfRange { challenge, nodes });
+        }
+
+        if let Some(tau) = self.tau {
+            if bytes_into_fr(&tau.comm_d.into_bytes()).is_err() {
+                return Err(VerifyError::MalformedCommitment("comm_d"));
+            }
+            if bytes_into_fr(&tau.comm_r.into_bytes()).is_err() {
+                return Err(VerifyError::MalformedCommitment("comm_r"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Domain> PublicInputs<T> {
+    /// Public inputs with `pub_params.challenges_count` challenges derived by `derivation`.
+    /// The first node cannot be proven, so the challenges are derived among the other nodes.
+    pub fn derive<H, G>(
+        pub_params: &PublicParams<H, G>,
+        replica_id: T,
+        tau: Tau<T>,
+        derivation: &dyn ChallengeDerivation<T>,
+    ) -> Result<Self>
+    where
+        H: Hasher<Domain = T>,
+        G: Graph<H> + ParameterSetMetadata,
+    {
+        let challenges = derivation
+            .derive(
+                &tau,
+                &replica_id,
+                pub_params.challenges_count,
+                pub_params.graph.size().saturating_sub(1),
+            )?
+            .into_iter()
+            .map(|challenge| challenge + 1)
+            .collect();
+
+        Ok(PublicInputs {
+            replica_id: Some(replica_id),
+            challenges,
+            tau: Some(tau),
+        })
+    }
+}
+
+/// The trees built by `replicate` (see `ProverAux`); `prove` generates its paths from these
+/// directly, so nothing is rehashed per proof.
 #[derive(Debug)]
 pub struct PrivateInputs<'a, H: 'a + Hasher> {
     pub tree_d: &'a BinaryMerkleTree<H>,
@@ -63,14 +324,14 @@ pub struct PrivateInputs<'a, H: 'a + Hasher> {
     pub tree_r_config_rows_to_discard: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SetupParams {
     pub drg: DrgParams,
     pub private: bool,
     pub challenges_count: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DrgParams {
     // Number of nodes
     pub nodes: usize,
@@ -78,21 +339,228 @@ pub struct DrgParams {
     // Base degree of DRG
     pub degree: usize,
 
+    // Number of expansion parents, taken from the previous layer in layered constructions
     pub expansion_degree: usize,
 
     pub porep_id: [u8; 32],
 }
 
+impl DrgParams {
+    /// Returns the number of parents of a node, base and expansion parents together.
+    pub fn total_degree(&self) -> usize {
+        self.degree + self.expansion_degree
+    }
+
+    /// Returns parameters for a DRG over `nodes` nodes with a base degree recommended for
+    /// `lambda_bits` of security, without expansion parents.
+    ///
+    /// Bucket sampling graphs are depth robust once their in-degree is logarithmic in the
+    /// number of nodes (Alwen, Blocki, Pietrzak, "Depth-Robust Graphs and Their Cumulative
+    /// Memory Complexity", EUROCRYPT 2017). The degree is chosen as
+    ///
+    /// `m = ceil(log2(n) * lambda_bits / 128)`
+    ///
+    /// i.e. one parent per bit of node index at 128 bits of security, scaled linearly with the
+    /// security level. It is clamped to at least 2, which bucket sampling needs, and to at most
+    /// `n - 1`, since a node cannot have more distinct predecessors.
+    pub fn for_security(nodes: usize, lambda_bits: usize) -> Self {
+        assert!(nodes > 2, "too few nodes: {}", nodes);
+
+        let log_nodes = (nodes as f64).log2();
+        let degree = (log_nodes * lambda_bits as f64 / 128.0).ceil() as usize;
+
+        DrgParams {
+            nodes,
+            degree: degree.max(2).min(nodes - 1),
+            expansion_degree: 0,
+            porep_id: [0; 32],
+        }
+    }
+}
+
+/// The shape of a sector, keeping its size, node count and tree depth consistent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectorConfig {
+    /// Size of the sector in bytes.
+    pub sector_bytes: usize,
+    /// Size of a single node in bytes.
+    pub node_size: usize,
+    /// Base degree of the DRG.
+    pub degree: usize,
+    /// Number of nodes, `sector_bytes / node_size`.
+    pub nodes: usize,
+    /// Depth of the binary merkle trees over the nodes, `log2(nodes)`.
+    pub tree_depth: usize,
+}
+
+impl SectorConfig {
+    pub fn new(sector_bytes: usize, node_size: usize, degree: usize) -> Result<Self> {
+        ensure!(node_size > 0, "node size must not be zero");
+        ensure!(
+            sector_bytes % node_size == 0,
+            "sector size {} is not a multiple of the node size {}",
+            sector_bytes,
+            node_size
+        );
+
+        let nodes = sector_bytes / node_size;
+        ensure!(
+            nodes.is_power_of_two(),
+            "number of nodes {} is not a power of two",
+            nodes
+        );
+
+        Ok(SectorConfig {
+            sector_bytes,
+            node_size,
+            degree,
+            nodes,
+            tree_depth: nodes.trailing_zeros() as usize,
+        })
+    }
+}
+
+impl SetupParams {
+    /// Returns a builder for setup parameters, which checks them once built.
+    pub fn builder() -> SetupParamsBuilder {
+        SetupParamsBuilder::default()
+    }
+
+    pub fn from_sector_config(
+        config: &SectorConfig,
+        porep_id: [u8; 32],
+        private: bool,
+        challenges_count: usize,
+    ) -> Self {
+        SetupParams {
+            drg: DrgParams {
+                nodes: config.nodes,
+                degree: config.degree,
+                expansion_degree: 0,
+                porep_id,
+            },
+            private,
+            challenges_count,
+        }
+    }
+}
+
+/// Builds `SetupParams`, see `SetupParams::builder`. Only the number of nodes must be set,
+/// the degree defaults to `BASE_DEGREE`, and a single public challenge is proven by default.
 #[derive(Debug, Clone)]
+pub struct SetupParamsBuilder {
+    nodes: Option<usize>,
+    node_size: usize,
+    degree: usize,
+    expansion_degree: usize,
+    porep_id: [u8; 32],
+    private: bool,
+    challenges_count: usize,
+}
+
+impl Default for SetupParamsBuilder {
+    fn default() -> Self {
+        SetupParamsBuilder {
+            nodes: None,
+            node_size: NODE_SIZE,
+            degree: BASE_DEGREE,
+            expansion_degree: 0,
+            porep_id: [0; 32],
+            private: false,
+            challenges_count: 1,
+        }
+    }
+}
+
+impl SetupParamsBuilder {
+    pub fn nodes(mut self, nodes: usize) -> Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    /// The size of a node in bytes. Only `NODE_SIZE` is supported, setting it documents the
+    /// expectation and lets `build` check it.
+    pub fn node_size(mut self, node_size: usize) -> Self {
+        self.node_size = node_size;
+        self
+    }
+
+    pub fn degree(mut self, degree: usize) -> Self {
+        self.degree = degree;
+        self
+    }
+
+    pub fn expansion_degree(mut self, expansion_degree: usize) -> Self {
+        self.expansion_degree = expansion_degree;
+        self
+    }
+
+    pub fn porep_id(mut self, porep_id: [u8; 32]) -> Self {
+        self.porep_id = porep_id;
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    pub fn challenges_count(mut self, challenges_count: usize) -> Self {
+        self.challenges_count = challenges_count;
+        self
+    }
+
+    /// Checks the parameters: a power of two number of nodes, of `NODE_SIZE` bytes, fewer
+    /// parents than nodes, and at least one challenge.
+    pub fn build(self) -> Result<SetupParams> {
+        let nodes = self.nodes.context("the number of nodes must be set")?;
+        ensure!(
+            nodes > 1 && nodes.is_power_of_two(),
+            "number of nodes {} is not a power of two",
+            nodes
+        );
+        ensure!(
+            self.node_size == NODE_SIZE,
+            "unsupported node size {}, nodes are {} bytes",
+            self.node_size,
+            NODE_SIZE
+        );
+        ensure!(
+            self.degree > 0 && self.degree < nodes,
+            "degree {} is invalid for {} nodes",
+            self.degree,
+            nodes
+        );
+        ensure!(self.challenges_count > 0, "no challenges");
+
+        Ok(SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: self.degree,
+                expansion_degree: self.expansion_degree,
+                porep_id: self.porep_id,
+            },
+            private: self.private,
+            challenges_count: self.challenges_count,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublicParams<H, G>
 where
     H: Hasher,
     G: Graph<H> + ParameterSetMetadata,
 {
+    #[serde(bound(serialize = "G: Serialize", deserialize = "G: Deserialize<'de>"))]
     pub graph: G,
     pub private: bool,
     pub challenges_count: usize,
 
+    /// The parents of all nodes, `graph.degree()` per node, once `cache_parents` was called.
+    #[serde(skip)]
+    parents_cache: Option<Vec<u32>>,
+
     _h: PhantomData<H>,
 }
 
@@ -106,9 +574,63 @@ where
             graph,
             private,
             challenges_count,
+            parents_cache: None,
             _h: PhantomData,
         }
     }
+
+    /// Computes the parents of all nodes once, so later calls to `parents` are lookups. This
+    /// keeps `graph.degree()` parents per node in memory.
+    pub fn cache_parents(&mut self) -> Result<()> {
+        let degree = self.graph.degree();
+        let mut cache = vec![0; self.graph.size() * degree];
+        for (node, parents) in cache.chunks_mut(degree).enumerate() {
+            self.graph.parents(node, parents)?;
+        }
+        self.parents_cache = Some(cache);
+
+        Ok(())
+    }
+
+    /// Writes the parents of `node` into `parents`, from the cache if `cache_parents` was
+    /// called and from the graph otherwise.
+    pub fn parents(&self, node: usize, parents: &mut [u32]) -> Result<()> {
+        match self.parents_cache {
+            Some(ref cache) => {
+                let degree = self.graph.degree();
+                ensure!(node < self.graph.size(), "node {} out of bounds", node);
+                parents.copy_from_slice(&cache[node * degree..(node + 1) * degree]);
+                Ok(())
+            }
+            None => self.graph.parents(node, parents),
+        }
+    }
+
+    /// Returns these parameters with the graph sampled from `seed` instead of the porep id.
+    /// The graph has the same size and degree, DRG graphs have no expansion parents.
+    pub fn reseed(&self, seed: [u8; 32]) -> Result<Self> {
+        let graph = G::new(self.graph.size(), self.graph.degree(), 0, seed)?;
+
+        Ok(PublicParams::new(
+            graph,
+            self.private,
+            self.challenges_count,
+        ))
+    }
+
+    /// Returns a digest of these parameters, so a verifier can confirm it is using the same
+    /// parameters as the prover. Besides the `identifier` the parameter cache keys on, it
+    /// covers `private` and `challenges_count`.
+    pub fn hash(&self) -> [u8; 32] {
+        let params = format!(
+            "{}; private: {}; challenges_count: {}",
+            self.identifier(),
+            self.private,
+            self.challenges_count,
+        );
+
+        Sha256::digest(params.as_bytes()).into()
+    }
 }
 
 impl<H, G> ParameterSetMetadata for PublicParams<H, G>
@@ -128,7 +650,7 @@ where
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DataProof<H: Hasher, U: PoseidonArity> {
     #[serde(bound(
         serialize = "MerkleProof<H, U>: Serialize",
@@ -155,7 +677,7 @@ impl<H: Hasher, U: 'static + PoseidonArity> DataProof<H, U> {
 
 pub type ReplicaParents<H> = Vec<(u32, DataProof<H, typenum::U2>)>;
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Proof<H: Hasher> {
     #[serde(bound(
         serialize = "H::Domain: Serialize",
@@ -222,6 +744,35 @@ impl<'a, H: Hasher> From<&'a Proof<H>> for Proof<H> {
     }
 }
 
+/// The reason a DRG PoRep proof failed to verify, see `DrgPoRep::verify_detailed`.
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("challenge {challenge} is out of range for {nodes} nodes")]
+    ChallengeOutOfRange { challenge: usize, nodes: usize },
+    #[error("proof is not for challenge {0}")]
+    WrongChallenge(usize),
+    #[error("parents in the proof for challenge {0} are not those of the graph")]
+    WrongParents(usize),
+    #[error("merkle roots in the proof for challenge {0} do not match the commitments")]
+    WrongRoot(usize),
+    #[error("{0} is not a valid field element")]
+    MalformedCommitment(&'static str),
+    #[error("merkle path for node {node} has depth {depth} instead of {expected}")]
+    WrongDepth {
+        node: usize,
+        depth: usize,
+        expected: usize,
+    },
+    #[error("invalid merkle path for node {0}")]
+    InvalidPath(usize),
+    #[error("decoded replica node {0} does not match the data")]
+    DecodeMismatch(usize),
+    #[error("proof holds {actual} entries for {expected} challenges")]
+    WrongProofLength { expected: usize, actual: usize },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
 #[derive(Default)]
 pub struct DrgPoRep<'a, H, G>
 where
@@ -235,7 +786,7 @@ where
 impl<'a, H, G> ProofScheme<'a> for DrgPoRep<'a, H, G>
 where
     H: 'static + Hasher,
-    G: 'a + Graph<H> + ParameterSetMetadata,
+    G: 'a + Graph<H> + ParameterSetMetadata + Sync,
 {
     type PublicParams = PublicParams<H, G>;
     type SetupParams = SetupParams;
@@ -251,6 +802,11 @@ where
             sp.drg.expansion_degree,
             sp.drg.porep_id,
         )?;
+        // A forward or self edge makes the sector impossible to replicate, catch new graph
+        // constructions introducing one. Checking every node is too slow for release builds.
+        if cfg!(debug_assertions) {
+            ensure!(graph.is_acyclic(), "graph has a forward or self edge");
+        }
 
         Ok(PublicParams::new(graph, sp.private, sp.challenges_count))
     }
@@ -260,137 +816,158 @@ where
         pub_inputs: &'b Self::PublicInputs,
         priv_inputs: &'b Self::PrivateInputs,
     ) -> Result<Self::Proof> {
-        let len = pub_inputs.challenges.len();
-        ensure!(
-            len <= pub_params.challenges_count,
-            "too many challenges {} > {}",
-            len,
-            pub_params.challenges_count
-        );
+        prove_challenges(pub_params, pub_inputs, priv_inputs)
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        match Self::verify_detailed(pub_params, pub_inputs, proof) {
+            Ok(()) => Ok(true),
+            Err(VerifyError::Other(err)) => Err(err),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+impl<'a, H, G> DrgPoRep<'a, H, G>
+where
+    H: 'static + Hasher,
+    G::Key: AsRef<<H as Hasher>::Domain>,
+    G: 'a + Graph<H> + ParameterSetMetadata,
+{
+    /// Same as `ProofScheme::prove`, but for the challenges `derivation` derives from `tau`, see
+    /// `PublicInputs::derive`. Returns the public inputs the verifier needs with the proof.
+    pub fn prove_with_derivation(
+        pub_params: &PublicParams<H, G>,
+        replica_id: <H as Hasher>::Domain,
+        tau: Tau<<H as Hasher>::Domain>,
+        priv_inputs: &PrivateInputs<'_, H>,
+        derivation: &dyn ChallengeDerivation<<H as Hasher>::Domain>,
+    ) -> Result<(PublicInputs<<H as Hasher>::Domain>, Proof<H>)>
+    where
+        G: Sync,
+    {
+        let pub_inputs = PublicInputs::derive(pub_params, replica_id, tau, derivation)?;
+        let proof = prove_challenges(pub_params, &pub_inputs, priv_inputs)?;
+
+        Ok((pub_inputs, proof))
+    }
 
-        let mut replica_nodes = Vec::with_capacity(len);
-        let mut replica_parents = Vec::with_capacity(len);
-        let mut data_nodes: Vec<DataProof<H, typenum::U2>> = Vec::with_capacity(len);
+    /// Same as `ProofScheme::verify`, but also checks that the challenges of `pub_inputs` are
+    /// the ones `derivation` derives, as `PublicInputs::derive` does. This needs a
+    /// deterministic derivation such as `FiatShamirChallenges`.
+    pub fn verify_with_derivation(
+        pub_params: &PublicParams<H, G>,
+        pub_inputs: &PublicInputs<<H as Hasher>::Domain>,
+        proof: &Proof<H>,
+        derivation: &dyn ChallengeDerivation<<H as Hasher>::Domain>,
+    ) -> Result<bool> {
+        let replica_id = pub_inputs.replica_id.context("missing replica id")?;
+        let tau = pub_inputs.tau.context("missing tau")?;
 
-        for i in 0..len {
-            let challenge = pub_inputs.challenges[i] % pub_params.graph.size();
-            ensure!(challenge != 0, "cannot prove the first node");
+        let expected = PublicInputs::derive(pub_params, replica_id, tau, derivation)?;
+        if expected.challenges != pub_inputs.challenges {
+            return Ok(false);
+        }
 
-            let tree_d = &priv_inputs.tree_d;
-            let tree_r = &priv_inputs.tree_r;
-            let tree_r_config_rows_to_discard = priv_inputs.tree_r_config_rows_to_discard;
+        Self::verify(pub_params, pub_inputs, proof)
+    }
 
-            let data = tree_r.read_at(challenge)?;
-            let tree_proof =
-                tree_r.gen_cached_proof(challenge, Some(tree_r_config_rows_to_discard))?;
-            replica_nodes.push(DataProof {
-                proof: tree_proof,
-                data,
-            });
-
-            let mut parents = vec![0; pub_params.graph.degree()];
-            pub_params.graph.parents(challenge, &mut parents)?;
-            let mut replica_parentsi = Vec::with_capacity(parents.len());
-
-            for p in &parents {
-                replica_parentsi.push((*p, {
-                    let proof = tree_r
-                        .gen_cached_proof(*p as usize, Some(tree_r_config_rows_to_discard))?;
-                    DataProof {
-                        proof,
-                        data: tree_r.read_at(*p as usize)?,
-                    }
-                }));
-            }
+    /// Same as `ProofScheme::verify`, but reports why verification failed. Errors which do not
+    /// depend on the proof, such as a missing replica id, are returned as `VerifyError::Other`.
+    pub fn verify_detailed(
+        pub_params: &PublicParams<H, G>,
+        pub_inputs: &PublicInputs<<H as Hasher>::Domain>,
+        proof: &Proof<H>,
+    ) -> std::result::Result<(), VerifyError> {
+        pub_inputs.validate(pub_params)?;
 
-            replica_parents.push(replica_parentsi);
+        let mut hasher = Sha256::new();
 
-            let node_proof = tree_d.gen_proof(challenge)?;
+        // Both trees are built over the `n` nodes of the graph.
+        let expected = graph_height::<typenum::U2>(pub_params.graph.size()) - 1;
+        let check_depth = |node: usize, proof: &DataProof<H, typenum::U2>| {
+            let depth = proof.proof.path().len();
+            if depth == expected {
+                Ok(())
+            } else {
+                Err(VerifyError::WrongDepth {
+                    node,
+                    depth,
+                    expected,
+                })
+            }
+        };
 
-            {
-                // TODO: use this again, I can't make lifetimes work though atm and I do not know why
-                // let extracted = Self::extract(
-                //     pub_params,
-                //     &pub_inputs.replica_id.into_bytes(),
-                //     &replica,
-                //     challenge,
-                // )?;
-
-                let extracted = decode_domain_block::<H>(
-                    &pub_inputs.replica_id.context("missing replica_id")?,
-                    tree_r,
-                    challenge,
-                    tree_r.read_at(challenge)?,
-                    &parents,
-                )?;
-                data_nodes.push(DataProof {
-                    data: extracted,
-                    proof: node_proof,
+        let expected_len = pub_inputs.challenges.len();
+        for actual in &[
+            proof.nodes.len(),
+            proof.replica_nodes.len(),
+            proof.replica_parents.len(),
+        ] {
+            if *actual != expected_len {
+                return Err(VerifyError::WrongProofLength {
+                    expected: expected_len,
+                    actual: *actual,
                 });
             }
         }
 
-        let proof = Proof::new(replica_nodes, replica_parents, data_nodes);
-
-        Ok(proof)
-    }
-
-    fn verify(
-        pub_params: &Self::PublicParams,
-        pub_inputs: &Self::PublicInputs,
-        proof: &Self::Proof,
-    ) -> Result<bool> {
-        let mut hasher = Sha256::new();
+        for (i, &challenge) in pub_inputs.challenges.iter().enumerate() {
+            // A path of the wrong depth can still prove the challenge, so check it first.
+            check_depth(challenge, &proof.nodes[i])?;
+            check_depth(challenge, &proof.replica_nodes[i])?;
+            for (parent, p) in &proof.replica_parents[i] {
+                check_depth(*parent as usize, p)?;
+            }
 
-        for i in 0..pub_inputs.challenges.len() {
             {
                 // This was verify_proof_meta.
-                if pub_inputs.challenges[i] >= pub_params.graph.size() {
-                    return Ok(false);
+                if !(proof.nodes[i].proves_challenge(challenge))
+                    || !(proof.replica_nodes[i].proves_challenge(challenge))
+                {
+                    return Err(VerifyError::WrongChallenge(challenge));
                 }
 
-                if !(proof.nodes[i].proves_challenge(pub_inputs.challenges[i])) {
-                    return Ok(false);
-                }
+                let mut expected_parents = vec![0; pub_params.graph.degree()];
+                pub_params.parents(challenge, &mut expected_parents)?;
 
-                if !(proof.replica_nodes[i].proves_challenge(pub_inputs.challenges[i])) {
-                    return Ok(false);
-                }
+                let parents_as_expected = proof.replica_parents[i].len() == expected_parents.len()
+                    && proof.replica_parents[i]
+                        .iter()
+                        .zip(&expected_parents)
+                        .all(|(actual, expected)| actual.0 == *expected);
 
-                let mut expected_parents = vec![0; pub_params.graph.degree()];
-                pub_params
-                    .graph
-                    .parents(pub_inputs.challenges[i], &mut expected_parents)?;
-                if proof.replica_parents[i].len() != expected_parents.len() {
-                    println!(
-                        "proof parents were not the same length as in public parameters: {} != {}",
-                        proof.replica_parents[i].len(),
-                        expected_parents.len()
-                    );
-                    return Ok(false);
+                if !parents_as_expected {
+                    return Err(VerifyError::WrongParents(challenge));
                 }
+            }
 
-                let parents_as_expected = proof.replica_parents[i]
-                    .iter()
-                    .zip(&expected_parents)
-                    .all(|(actual, expected)| actual.0 == *expected);
+            if let Some(tau) = pub_inputs.tau {
+                let replica_roots_match = proof.replica_nodes[i].proof.root() == tau.comm_r
+                    && proof.replica_parents[i]
+                        .iter()
+                        .all(|(_, p)| p.proof.root() == tau.comm_r);
 
-                if !parents_as_expected {
-                    println!("proof parents were not those provided in public parameters");
-                    return Ok(false);
+                if !replica_roots_match || proof.nodes[i].proof.root() != tau.comm_d {
+                    return Err(VerifyError::WrongRoot(challenge));
                 }
             }
 
-            let challenge = pub_inputs.challenges[i] % pub_params.graph.size();
-            ensure!(challenge != 0, "cannot prove the first node");
+            if challenge == 0 {
+                return Err(anyhow!("cannot prove the first node").into());
+            }
 
             if !proof.replica_nodes[i].proof.validate(challenge) {
-                return Ok(false);
+                return Err(VerifyError::InvalidPath(challenge));
             }
 
             for (parent_node, p) in &proof.replica_parents[i] {
                 if !p.proof.validate(*parent_node as usize) {
-                    return Ok(false);
+                    return Err(VerifyError::InvalidPath(*parent_node as usize));
                 }
             }
 
@@ -409,95 +986,616 @@ where
             let unsealed = encode::decode(key, proof.replica_nodes[i].data);
 
             if unsealed != proof.nodes[i].data {
-                return Ok(false);
+                return Err(VerifyError::DecodeMismatch(challenge));
             }
 
             if !proof.nodes[i].proof.validate_data(unsealed) {
-                println!("invalid data for merkle path {:?}", unsealed);
-                return Ok(false);
+                return Err(VerifyError::InvalidPath(challenge));
             }
         }
 
-        Ok(true)
+        Ok(())
     }
-}
 
-impl<'a, H, G> PoRep<'a, H, H> for DrgPoRep<'a, H, G>
-where
-    H: 'static + Hasher,
-    G::Key: AsRef<<H as Hasher>::Domain>,
-    G: 'a + Graph<H> + ParameterSetMetadata + Sync + Send,
-{
-    type Tau = Tau<<H as Hasher>::Domain>;
-    type ProverAux = ProverAux<H>;
+    /// Decodes a single node of the replica, recomputing its key from the node's parents.
+    /// This allows spot-checking a replica without extracting all of it.
+    pub fn decode_node(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        replica: &[u8],
+        node: usize,
+    ) -> Result<<H as Hasher>::Domain>
+    where
+        G: Sync + Send,
+    {
+        decode_block_with(&pp.graph, replica_id, replica, None, node, Self::decode)
+    }
 
-    fn replicate(
-        pp: &Self::PublicParams,
+    /// Returns whether `replica` is a correct replication of `original_data`, by extracting
+    /// every node and comparing it to the original. This is far more expensive than verifying
+    /// a proof, it is meant to catch encoding regressions in tests.
+    pub fn verify_full(
+        pp: &PublicParams<H, G>,
         replica_id: &<H as Hasher>::Domain,
-        mut data: Data<'a>,
-        data_tree: Option<BinaryMerkleTree<H>>,
-        config: StoreConfig,
-        replica_path: PathBuf,
-    ) -> Result<(Self::Tau, Self::ProverAux)> {
-        use storage_proofs_core::cache_key::CacheKey;
+        replica: &[u8],
+        original_data: &[u8],
+    ) -> Result<bool>
+    where
+        G: Sync + Send,
+    {
+        if replica.len() != original_data.len() || replica.len() != pp.graph.size() * NODE_SIZE {
+            return Ok(false);
+        }
 
-        let tree_d = match data_tree {
-            Some(tree) => tree,
-            None => create_base_merkle_tree::<BinaryMerkleTree<H>>(
-                Some(config.clone()),
-                pp.graph.size(),
-                data.as_ref(),
-            )?,
-        };
+        let extracted = decode_with(&pp.graph, replica_id, replica, None, Self::decode)?;
 
-        let graph = &pp.graph;
-        // encode(&pp.graph, replica_id, data, None)?;
-        // Because a node always follows all of its parents in the data,
-        // the nodes are by definition already topologically sorted.
-        // Therefore, if we simply traverse the data in order, encoding each node in place,
-        // we can always get each parent's encodings with a simple lookup --
-        // since we will already have encoded the parent earlier in the traversal.
+        Ok(extracted == original_data)
+    }
 
-        let mut parents = vec![0; graph.degree()];
-        for node in 0..graph.size() {
-            graph.parents(node, &mut parents)?;
-            let key = graph.create_key(replica_id, node, &parents, data.as_ref(), None)?;
-            let start = data_at_node_offset(node);
-            let end = start + NODE_SIZE;
+    /// Returns comm_d, the root of the tree over `data`, without replicating it.
+    pub fn commit_data(pp: &PublicParams<H, G>, data: &[u8]) -> Result<<H as Hasher>::Domain> {
+        Self::commit(pp, data)
+    }
 
-            let node_data = <H as Hasher>::Domain::try_from_bytes(&data.as_ref()[start..end])?;
-            let encoded = H::sloth_encode(key.as_ref(), &node_data)?;
+    /// Returns comm_r, the root of the tree over `replica`. `replicate` keeps the replica tree
+    /// as a level cache tree, which has the same root as the tree built here.
+    pub fn commit_replica(
+        pp: &PublicParams<H, G>,
+        replica: &[u8],
+    ) -> Result<<H as Hasher>::Domain> {
+        Self::commit(pp, replica)
+    }
 
-            encoded.write_bytes(&mut data.as_mut()[start..end])?;
-        }
+    /// Returns the commitments `replicate` returned along with `aux`, read from the roots of its
+    /// trees, so a lost tau can be recovered from an aux reopened with `ProverAux::read`.
+    pub fn tau_from_aux(
+        pp: &PublicParams<H, G>,
+        aux: &ProverAux<H>,
+    ) -> Result<Tau<<H as Hasher>::Domain>> {
+        let nodes = pp.graph.size();
+        ensure!(
+            aux.tree_d.leaves() == nodes && aux.tree_r.leaves() == nodes,
+            "aux trees of {} and {} leaves do not match {} nodes",
+            aux.tree_d.leaves(),
+            aux.tree_r.leaves(),
+            nodes
+        );
 
-        let replica_config = ReplicaConfig {
-            path: replica_path,
-            offsets: vec![0],
-        };
-        let tree_r_last_config =
-            StoreConfig::from_config(&config, CacheKey::CommRLastTree.to_string(), None);
-        let tree_r =
-            create_base_lcmerkle_tree::<H, <BinaryLCMerkleTree<H> as MerkleTreeTrait>::Arity>(
-                tree_r_last_config,
-                pp.graph.size(),
-                &data.as_ref(),
-                &replica_config,
-            )?;
+        Ok(Tau::new(aux.tree_d.root(), aux.tree_r.root()))
+    }
 
-        let comm_d = tree_d.root();
-        let comm_r = tree_r.root();
+    fn commit(pp: &PublicParams<H, G>, nodes: &[u8]) -> Result<<H as Hasher>::Domain> {
+        check_data_len(&pp.graph, "data", nodes.len())?;
 
-        Ok((Tau::new(comm_d, comm_r), ProverAux::new(tree_d, tree_r)))
+        let tree = create_base_merkle_tree::<BinaryMerkleTree<H>>(None, pp.graph.size(), nodes)?;
+        Ok(tree.root())
     }
 
-    fn extract_all<'b>(
-        pp: &'b Self::PublicParams,
+    /// Re-encodes `replica` in place from `old_replica_id` to `new_replica_id`, so that it
+    /// extracts to the same data under the new id.
+    ///
+    /// The key of every node depends on the encoded values of its parents, which all change
+    /// with the replica id, so the replica cannot be transformed directly. Instead, nodes are
+    /// first decoded in reverse order, which keeps the parents of each node encoded until it
+    /// is decoded, and then encoded again under the new id. This needs no copy of the data.
+    ///
+    /// Only comm_r changes: the replica tree has to be rebuilt from the re-encoded replica.
+    pub fn reencode(
+        pp: &PublicParams<H, G>,
+        old_replica_id: &<H as Hasher>::Domain,
+        new_replica_id: &<H as Hasher>::Domain,
+        replica: &mut [u8],
+    ) -> Result<()>
+    where
+        G: Sync + Send,
+    {
+        check_data_len(&pp.graph, "replica", replica.len())?;
+
+        for node in (0..pp.graph.size()).rev() {
+            let decoded =
+                decode_block_with(&pp.graph, old_replica_id, replica, None, node, Self::decode)?;
+            let start = data_at_node_offset(node);
+            decoded.write_bytes(&mut replica[start..start + NODE_SIZE])?;
+        }
+
+        encode_nodes_with_progress(&pp.graph, new_replica_id, replica, Self::encode, |_, _| {})
+    }
+
+    /// Same as `PoRep::replicate`, but calls `progress` with the number of nodes encoded so far
+    /// and the total number of nodes, every `PROGRESS_INTERVAL` nodes and once encoding is done.
+    ///
+    /// `data` is encoded strictly in place, node by node, no copy of the buffer is made. Use
+    /// `replicate_cloned` to keep the original data.
+    pub fn replicate_with_progress<F: FnMut(usize, usize)>(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        data: Data<'a>,
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        progress: F,
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>)>
+    where
+        G: Sync + Send,
+    {
+        let (tau, aux, _) = Self::replicate_timed(
+            pp,
+            replica_id,
+            data,
+            data_tree,
+            config,
+            replica_path,
+            progress,
+        )?;
+
+        Ok((tau, aux))
+    }
+
+    /// Same as `PoRep::replicate`, but also reports the throughput of the replication.
+    pub fn replicate_with_stats(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        data: Data<'a>,
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>, ReplicationStats)>
+    where
+        G: Sync + Send,
+    {
+        Self::replicate_timed(
+            pp,
+            replica_id,
+            data,
+            data_tree,
+            config,
+            replica_path,
+            |_, _| {},
+        )
+    }
+
+    fn replicate_timed<F: FnMut(usize, usize)>(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        mut data: Data<'a>,
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        progress: F,
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>, ReplicationStats)>
+    where
+        G: Sync + Send,
+    {
+        let start = Instant::now();
+        check_data_len(&pp.graph, "data", data.as_ref().len())?;
+
+        let tree_d = Self::data_tree(pp, data_tree, &config, data.as_ref())?;
+        let tree_d_time = start.elapsed();
+
+        let encoding_start = Instant::now();
+        encode_nodes_with_progress(&pp.graph, replica_id, data.as_mut(), Self::encode, progress)?;
+        let kdf_time = encoding_start.elapsed();
+
+        let tree_r_start = Instant::now();
+        let (tau, aux) = Self::finish_replication(pp, tree_d, data.as_ref(), config, replica_path)?;
+        let merkle_time = tree_d_time + tree_r_start.elapsed();
+
+        let stats = ReplicationStats {
+            nodes: pp.graph.size(),
+            bytes: data.as_ref().len(),
+            elapsed: start.elapsed(),
+            kdf_time,
+            merkle_time,
+        };
+
+        Ok((tau, aux, stats))
+    }
+
+    /// Replicates a copy of `data`, leaving `data` untouched, and returns the replica. The
+    /// replica is also written to `replica_path`, which the replica tree reads its leaves from.
+    ///
+    /// This needs memory for a second copy of the data, prefer the in place `replicate` for
+    /// large sectors.
+    pub fn replicate_cloned(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        data: &[u8],
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<(Vec<u8>, Tau<<H as Hasher>::Domain>, ProverAux<H>)>
+    where
+        G: Sync + Send,
+    {
+        check_data_len(&pp.graph, "data", data.len())?;
+
+        let tree_d = Self::data_tree(pp, data_tree, &config, data)?;
+
+        let mut replica = data.to_vec();
+        encode_nodes_with_progress(&pp.graph, replica_id, &mut replica, Self::encode, |_, _| {})?;
+        std::fs::write(&replica_path, &replica)
+            .with_context(|| format!("could not write replica to {:?}", replica_path))?;
+
+        let (tau, aux) = Self::finish_replication(pp, tree_d, &replica, config, replica_path)?;
+
+        Ok((replica, tau, aux))
+    }
+
+    /// Same as `PoRep::replicate`, but saves the progress of the encoding every `interval`
+    /// nodes to `checkpoint_path`, so that `resume_replicate` can finish the replication if
+    /// this one does not. The data tree is always built and stored at `config`.
+    ///
+    /// The partially encoded replica is saved next to the checkpoint, in a file as large as
+    /// the data, and only the nodes encoded since the previous checkpoint are written to it.
+    /// Both are removed once replication succeeds. The encoded `data` is then written to
+    /// `replica_path`, like `resume_replicate` does, so `data` need not be a mapping of it.
+    pub fn replicate_checkpointed(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        mut data: Data<'a>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        checkpoint_path: &Path,
+        interval: usize,
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>)>
+    where
+        G: Sync + Send,
+    {
+        let (tree_d, mut checkpoint) = Self::start_checkpoint(
+            pp,
+            replica_id,
+            data.as_ref(),
+            config,
+            replica_path,
+            checkpoint_path,
+            interval,
+        )?;
+        encode_nodes_checkpointed(
+            &pp.graph,
+            checkpoint_path,
+            &mut checkpoint,
+            data.as_mut(),
+            pp.graph.size(),
+            Self::encode,
+        )?;
+
+        let persisted = checkpoint.persisted.clone();
+        write_replica(&persisted.replica_path, data.as_ref())?;
+        let (tau, aux) = Self::finish_replication(
+            pp,
+            tree_d,
+            data.as_ref(),
+            persisted.config,
+            persisted.replica_path,
+        )?;
+        checkpoint.remove(checkpoint_path)?;
+
+        Ok((tau, aux))
+    }
+
+    /// Finishes the replication checkpointed at `checkpoint_path` by `replicate_checkpointed`,
+    /// carrying on from the last checkpoint. The replica is written to the `replica_path` of
+    /// the original replication and returned, it is identical to the one of a replication
+    /// which was never interrupted.
+    pub fn resume_replicate(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        checkpoint_path: &Path,
+    ) -> Result<(Vec<u8>, Tau<<H as Hasher>::Domain>, ProverAux<H>)>
+    where
+        G: Sync + Send,
+    {
+        let mut checkpoint = ReplicationCheckpoint::read(checkpoint_path)?;
+        ensure!(
+            checkpoint.replica_id == *replica_id,
+            "checkpoint {:?} is for another replica id",
+            checkpoint_path
+        );
+        ensure!(
+            checkpoint.persisted.nodes == pp.graph.size(),
+            "checkpoint {:?} is for {} nodes, expected {}",
+            checkpoint_path,
+            checkpoint.persisted.nodes,
+            pp.graph.size()
+        );
+
+        let mut replica = std::fs::read(&checkpoint.state_path).with_context(|| {
+            format!(
+                "could not read checkpoint state {:?}",
+                checkpoint.state_path
+            )
+        })?;
+        check_data_len(&pp.graph, "checkpoint state", replica.len())?;
+
+        encode_nodes_checkpointed(
+            &pp.graph,
+            checkpoint_path,
+            &mut checkpoint,
+            &mut replica,
+            pp.graph.size(),
+            Self::encode,
+        )?;
+
+        let persisted = checkpoint.persisted.clone();
+        std::fs::write(&persisted.replica_path, &replica)
+            .with_context(|| format!("could not write replica to {:?}", persisted.replica_path))?;
+
+        let tree_len = get_merkle_tree_len(persisted.nodes, typenum::U2::to_usize())?;
+        let tree_d = create_disk_tree::<BinaryMerkleTree<H>>(tree_len, &[persisted.config.clone()])
+            .context("tree_d")?;
+        let (tau, aux) = Self::finish_replication(
+            pp,
+            tree_d,
+            &replica,
+            persisted.config,
+            persisted.replica_path,
+        )?;
+        checkpoint.remove(checkpoint_path)?;
+
+        Ok((replica, tau, aux))
+    }
+
+    /// Builds the data tree and writes the first checkpoint, with nothing encoded yet.
+    fn start_checkpoint(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        data: &[u8],
+        config: StoreConfig,
+        replica_path: PathBuf,
+        checkpoint_path: &Path,
+        interval: usize,
+    ) -> Result<(
+        BinaryMerkleTree<H>,
+        ReplicationCheckpoint<<H as Hasher>::Domain>,
+    )> {
+        check_data_len(&pp.graph, "data", data.len())?;
+        ensure!(interval > 0, "checkpoint interval must be positive");
+
+        let tree_d = Self::data_tree(pp, None, &config, data)?;
+
+        let state_path = with_suffix(checkpoint_path, ".state");
+        std::fs::write(&state_path, data)
+            .with_context(|| format!("could not write checkpoint state {:?}", state_path))?;
+
+        let checkpoint = ReplicationCheckpoint {
+            replica_id: *replica_id,
+            encoded: 0,
+            interval,
+            state_path,
+            persisted: PersistedAux {
+                nodes: pp.graph.size(),
+                config,
+                replica_path,
+            },
+        };
+        checkpoint.write(checkpoint_path)?;
+
+        Ok((tree_d, checkpoint))
+    }
+
+    /// Same as `PoRep::replicate`, but with the graph sampled from `seed`, so two runs with the
+    /// same seed produce identical parents and replicas. Proofs and extraction for the replica
+    /// must use the parameters returned by `pp.reseed(seed)`.
+    pub fn replicate_with_seed(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        data: Data<'a>,
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        seed: [u8; 32],
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>)>
+    where
+        G: Sync + Send,
+    {
+        let seeded = pp.reseed(seed)?;
+
+        Self::replicate_with_progress(
+            &seeded,
+            replica_id,
+            data,
+            data_tree,
+            config,
+            replica_path,
+            |_, _| {},
+        )
+    }
+
+    /// Updates `replica` in place after the nodes in `changed_nodes` of the underlying data
+    /// changed, where `data` is the updated (unencoded) data. The result equals replicating
+    /// `data`.
+    ///
+    /// Nodes before the first changed one are kept, every node depending on a changed one is
+    /// encoded again. With a `BucketGraph` that is every node from the first changed one on, as
+    /// each node has its predecessor as a parent.
+    ///
+    /// The replica tree reads its leaves from `replica_path`, so the updated `replica` is written
+    /// there. `replica` may be a mapping of that file.
+    pub fn replicate_update(
+        pp: &PublicParams<H, G>,
+        replica_id: &<H as Hasher>::Domain,
+        data: &[u8],
+        replica: &mut [u8],
+        changed_nodes: &[usize],
+        config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>)>
+    where
+        G: Sync + Send,
+    {
+        let graph = &pp.graph;
+        check_data_len(graph, "data", data.len())?;
+        check_data_len(graph, "replica", replica.len())?;
+
+        let mut dirty = vec![false; graph.size()];
+        for node in changed_nodes {
+            ensure!(*node < graph.size(), "invalid node {}", node);
+            dirty[*node] = true;
+        }
+
+        // Parents always precede their children, so a single pass in order finds every node
+        // depending on a changed one, and sees its parents already updated.
+        let first = changed_nodes
+            .iter()
+            .min()
+            .copied()
+            .unwrap_or_else(|| graph.size());
+        let mut parents = vec![0; graph.degree()];
+        for node in first..graph.size() {
+            graph.parents(node, &mut parents)?;
+            if !dirty[node] {
+                // Nodes without parents reference themselves.
+                dirty[node] = parents
+                    .iter()
+                    .any(|parent| *parent as usize != node && dirty[*parent as usize]);
+            }
+            if !dirty[node] {
+                continue;
+            }
+
+            let key = graph.create_key(replica_id, node, &parents, replica, None)?;
+            let node_data = <H as Hasher>::Domain::try_from_bytes(data_at_node(data, node)?)?;
+            let encoded = Self::encode(key.as_ref(), &node_data);
+
+            let start = data_at_node_offset(node);
+            encoded.write_bytes(&mut replica[start..start + NODE_SIZE])?;
+        }
+
+        write_replica(&replica_path, replica)?;
+
+        let tree_d = Self::data_tree(pp, None, &config, data)?;
+
+        Self::finish_replication(pp, tree_d, replica, config, replica_path)
+    }
+
+    /// Same as `ProofScheme::prove`, but opens the trees persisted by `replicate` instead of
+    /// taking them as private inputs. `config` and `replica_path` are the ones `replicate`
+    /// was called with.
+    ///
+    /// Nothing is loaded up front, see `ProverAux::open`.
+    pub fn prove_from_disk(
+        pp: &PublicParams<H, G>,
+        pub_inputs: &PublicInputs<<H as Hasher>::Domain>,
+        config: &StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<Proof<H>>
+    where
+        G: Sync,
+    {
+        let aux = ProverAux::<H>::open(PersistedAux {
+            nodes: pp.graph.size(),
+            config: config.clone(),
+            replica_path,
+        })?;
+
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: config.rows_to_discard,
+        };
+
+        prove_challenges(pp, pub_inputs, &priv_inputs)
+    }
+
+    /// Returns `data_tree`, or builds the data tree over `data` and stores it at `config`.
+    fn data_tree(
+        pp: &PublicParams<H, G>,
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: &StoreConfig,
+        data: &[u8],
+    ) -> Result<BinaryMerkleTree<H>> {
+        match data_tree {
+            Some(tree) => Ok(tree),
+            None => create_base_merkle_tree::<BinaryMerkleTree<H>>(
+                Some(config.clone()),
+                pp.graph.size(),
+                data,
+            ),
+        }
+    }
+
+    /// Builds the replica tree over the encoded `replica`, stored at `replica_path`, and
+    /// returns the commitments and prover aux of a replication with the data tree `tree_d`.
+    fn finish_replication(
+        pp: &PublicParams<H, G>,
+        tree_d: BinaryMerkleTree<H>,
+        replica: &[u8],
+        config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<(Tau<<H as Hasher>::Domain>, ProverAux<H>)> {
+        let tree_r = Self::replica_tree(pp, &config, replica, replica_path.clone())?;
+
+        let persisted = PersistedAux {
+            nodes: pp.graph.size(),
+            config,
+            replica_path,
+        };
+
+        Ok((
+            Tau::new(tree_d.root(), tree_r.root()),
+            ProverAux::new(tree_d, tree_r).with_persisted(persisted),
+        ))
+    }
+
+    fn replica_tree(
+        pp: &PublicParams<H, G>,
+        config: &StoreConfig,
+        replica: &[u8],
+        replica_path: PathBuf,
+    ) -> Result<BinaryLCMerkleTree<H>> {
+        use storage_proofs_core::cache_key::CacheKey;
+
+        let replica_config = ReplicaConfig {
+            path: replica_path,
+            offsets: vec![0],
+        };
+        let tree_r_last_config =
+            StoreConfig::from_config(config, CacheKey::CommRLastTree.to_string(), None);
+
+        create_base_lcmerkle_tree::<H, <BinaryLCMerkleTree<H> as MerkleTreeTrait>::Arity>(
+            tree_r_last_config,
+            pp.graph.size(),
+            replica,
+            &replica_config,
+        )
+    }
+}
+
+impl<'a, H, G> PoRep<'a, H, H> for DrgPoRep<'a, H, G>
+where
+    H: 'static + Hasher,
+    G::Key: AsRef<<H as Hasher>::Domain>,
+    G: 'a + Graph<H> + ParameterSetMetadata + Sync + Send,
+{
+    type Tau = Tau<<H as Hasher>::Domain>;
+    type ProverAux = ProverAux<H>;
+
+    fn replicate(
+        pp: &Self::PublicParams,
+        replica_id: &<H as Hasher>::Domain,
+        data: Data<'a>,
+        data_tree: Option<BinaryMerkleTree<H>>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<(Self::Tau, Self::ProverAux)> {
+        Self::replicate_with_progress(
+            pp,
+            replica_id,
+            data,
+            data_tree,
+            config,
+            replica_path,
+            |_, _| {},
+        )
+    }
+
+    fn extract_all<'b>(
+        pp: &'b Self::PublicParams,
         replica_id: &'b <H as Hasher>::Domain,
         data: &'b [u8],
         _config: Option<StoreConfig>,
     ) -> Result<Vec<u8>> {
-        decode(&pp.graph, replica_id, data, None)
+        decode_with(&pp.graph, replica_id, data, None, Self::decode)
     }
 
     fn extract(
@@ -507,67 +1605,435 @@ where
         node: usize,
         _config: Option<StoreConfig>,
     ) -> Result<Vec<u8>> {
-        Ok(decode_block(&pp.graph, replica_id, data, None, node)?.into_bytes())
+        Ok(decode_block_with(&pp.graph, replica_id, data, None, node, Self::decode)?.into_bytes())
     }
 }
 
-pub fn decode<'a, H, G>(
-    graph: &'a G,
-    replica_id: &'a <H as Hasher>::Domain,
-    data: &'a [u8],
-    exp_parents_data: Option<&'a [u8]>,
-) -> Result<Vec<u8>>
+/// Deterministically samples `count` distinct challenges in `[0, n)` from `seed`, for tests
+/// and benchmarks that need a reproducible challenge set. This is not derived from the
+/// commitments, so it must not replace the challenge derivation of a protocol.
+///
+/// Note that `DrgPoRep` cannot prove the first node: to sample provable challenges, sample
+/// from `n - 1` and add one to every challenge.
+pub fn sample_challenges(seed: [u8; 32], count: usize, n: usize) -> Vec<usize> {
+    assert!(
+        count <= n,
+        "cannot sample {} distinct challenges from {} nodes",
+        count,
+        n
+    );
+
+    let mut seen = HashSet::with_capacity(count);
+    let mut challenges = Vec::with_capacity(count);
+    let mut counter = 0u64;
+    while challenges.len() < count {
+        let hash = Sha256::new()
+            .chain(&seed)
+            .chain(&counter.to_le_bytes())
+            .result();
+        counter += 1;
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&hash[..8]);
+        let challenge = (u64::from_le_bytes(bytes) % n as u64) as usize;
+
+        // Reject duplicates.
+        if seen.insert(challenge) {
+            challenges.push(challenge);
+        }
+    }
+
+    challenges
+}
+
+/// How the challenges of a proof are derived, see `PublicInputs::derive`.
+pub trait ChallengeDerivation<T: Domain> {
+    /// Returns `count` challenges in `[0, n)` for the replica `replica_id` committed to by `tau`,
+    /// failing if `n` is zero.
+    fn derive(&self, tau: &Tau<T>, replica_id: &T, count: usize, n: usize) -> Result<Vec<usize>>;
+}
+
+/// Challenges drawn from `rand::thread_rng`. A verifier cannot derive them again, so these
+/// are only meant for interactive settings, tests and benchmarks.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomChallenges;
+
+impl<T: Domain> ChallengeDerivation<T> for RandomChallenges {
+    fn derive(&self, _tau: &Tau<T>, _replica_id: &T, count: usize, n: usize) -> Result<Vec<usize>> {
+        ensure!(n > 0, "cannot derive challenges among 0 nodes");
+
+        let rng = &mut rand::thread_rng();
+        Ok((0..count).map(|_| rng.gen_range(0, n)).collect())
+    }
+}
+
+/// Challenges derived from the commitments (Fiat-Shamir), so the prover cannot pick them and
+/// the verifier derives the same ones. Challenge `i` is
+/// `Sha256(replica_id | comm_d | comm_r | i)` modulo `n`, with `i` as a 64 bit little endian
+/// counter and the first 8 bytes of the digest read as a little endian integer.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FiatShamirChallenges;
+
+impl<T: Domain> ChallengeDerivation<T> for FiatShamirChallenges {
+    fn derive(&self, tau: &Tau<T>, replica_id: &T, count: usize, n: usize) -> Result<Vec<usize>> {
+        ensure!(n > 0, "cannot derive challenges among 0 nodes");
+
+        let challenges = (0..count as u64)
+            .map(|i| {
+                let hash = Sha256::new()
+                    .chain(AsRef::<[u8]>::as_ref(replica_id))
+                    .chain(AsRef::<[u8]>::as_ref(&tau.comm_d))
+                    .chain(AsRef::<[u8]>::as_ref(&tau.comm_r))
+                    .chain(&i.to_le_bytes())
+                    .result();
+
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&hash[..8]);
+                (u64::from_le_bytes(bytes) % n as u64) as usize
+            })
+            .collect();
+
+        Ok(challenges)
+    }
+}
+
+/// Proves the challenges of `pub_inputs`, see `ProofScheme::prove`. This does not tie the
+/// lifetime of the trees to the scheme, so that `DrgPoRep::prove_from_disk` can prove from
+/// trees it opens itself.
+fn prove_challenges<H, G>(
+    pub_params: &PublicParams<H, G>,
+    pub_inputs: &PublicInputs<<H as Hasher>::Domain>,
+    priv_inputs: &PrivateInputs<'_, H>,
+) -> Result<Proof<H>>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Sync,
+{
+    pub_inputs.validate(pub_params)?;
+
+    let len = pub_inputs.challenges.len();
+    ensure!(
+        len <= pub_params.challenges_count,
+        "too many challenges {} > {}",
+        len,
+        pub_params.challenges_count
+    );
+
+    let replica_id = pub_inputs.replica_id.context("missing replica_id")?;
+
+    // The witness of every challenge is independent of the others. Results are collected in
+    // the order of the challenges, so the proof does not depend on the scheduling.
+    let witnesses = pub_inputs
+        .challenges
+        .par_iter()
+        .map(|&challenge| prove_challenge(pub_params, &replica_id, priv_inputs, challenge))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut replica_nodes = Vec::with_capacity(len);
+    let mut replica_parents = Vec::with_capacity(len);
+    let mut data_nodes = Vec::with_capacity(len);
+    for (replica_node, replica_parentsi, data_node) in witnesses {
+        replica_nodes.push(replica_node);
+        replica_parents.push(replica_parentsi);
+        data_nodes.push(data_node);
+    }
+
+    let proof = Proof::new(replica_nodes, replica_parents, data_nodes);
+
+    Ok(proof)
+}
+
+/// Generates the inclusion proofs of a single challenge: those of the replica node and of its
+/// parents in tree_r, and that of the decoded data node in tree_d.
+#[allow(clippy::type_complexity)]
+fn prove_challenge<H, G>(
+    pub_params: &PublicParams<H, G>,
+    replica_id: &<H as Hasher>::Domain,
+    priv_inputs: &PrivateInputs<'_, H>,
+    challenge: usize,
+) -> Result<(
+    DataProof<H, typenum::U2>,
+    ReplicaParents<H>,
+    DataProof<H, typenum::U2>,
+)>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata,
+{
+    ensure!(challenge != 0, "cannot prove the first node");
+
+    let tree_d = &priv_inputs.tree_d;
+    let tree_r = &priv_inputs.tree_r;
+    let tree_r_config_rows_to_discard = priv_inputs.tree_r_config_rows_to_discard;
+
+    let data = tree_r.read_at(challenge)?;
+    let tree_proof = tree_r.gen_cached_proof(challenge, Some(tree_r_config_rows_to_discard))?;
+    let replica_node = DataProof {
+        proof: tree_proof,
+        data,
+    };
+
+    let mut parents = vec![0; pub_params.graph.degree()];
+    pub_params.parents(challenge, &mut parents)?;
+    let mut replica_parents = Vec::with_capacity(parents.len());
+
+    for p in &parents {
+        replica_parents.push((*p, {
+            let proof =
+                tree_r.gen_cached_proof(*p as usize, Some(tree_r_config_rows_to_discard))?;
+            DataProof {
+                proof,
+                data: tree_r.read_at(*p as usize)?,
+            }
+        }));
+    }
+
+    let node_proof = tree_d.gen_proof(challenge)?;
+
+    // TODO: use this again, I can't make lifetimes work though atm and I do not know why
+    // let extracted = Self::extract(
+    //     pub_params,
+    //     &pub_inputs.replica_id.into_bytes(),
+    //     &replica,
+    //     challenge,
+    // )?;
+
+    let extracted = decode_domain_block::<H>(
+        replica_id,
+        tree_r,
+        challenge,
+        tree_r.read_at(challenge)?,
+        &parents,
+    )?;
+    let data_node = DataProof {
+        data: extracted,
+        proof: node_proof,
+    };
+
+    Ok((replica_node, replica_parents, data_node))
+}
+
+/// How many nodes are encoded between calls to the replication progress callback.
+pub const PROGRESS_INTERVAL: usize = 1024;
+
+/// Encodes or decodes a single node under its key, like `PoRep::encode` and `PoRep::decode`.
+pub type NodeCodec<D> = fn(&D, &D) -> D;
+
+/// Encodes all nodes of `data` in place, with the default encoding of `PoRep::encode`.
+pub fn encode_nodes<H, G>(graph: &G, replica_id: &H::Domain, data: &mut [u8]) -> Result<()>
 where
     H: Hasher,
     G::Key: AsRef<H::Domain>,
-    G: Graph<H> + Sync,
+    G: Graph<H>,
 {
-    // TODO: proper error handling
-    let result = (0..graph.size())
-        .into_par_iter()
-        .flat_map(|i| {
-            decode_block::<H, G>(graph, replica_id, data, exp_parents_data, i)
-                .unwrap()
-                .into_bytes()
-        })
-        .collect();
+    encode_nodes_with_progress(
+        graph,
+        replica_id,
+        data,
+        |key, node| encode::encode(*key, *node),
+        |_, _| {},
+    )
+}
 
-    Ok(result)
+/// Encodes all nodes of `data` in place with `encode`, reporting progress as in
+/// `replicate_with_progress`.
+pub fn encode_nodes_with_progress<H, G, F>(
+    graph: &G,
+    replica_id: &H::Domain,
+    data: &mut [u8],
+    encode: NodeCodec<H::Domain>,
+    mut progress: F,
+) -> Result<()>
+where
+    H: Hasher,
+    G::Key: AsRef<H::Domain>,
+    G: Graph<H>,
+    F: FnMut(usize, usize),
+{
+    // Because a node always follows all of its parents in the data,
+    // the nodes are by definition already topologically sorted.
+    // Therefore, if we simply traverse the data in order, encoding each node in place,
+    // we can always get each parent's encodings with a simple lookup --
+    // since we will already have encoded the parent earlier in the traversal.
+    let total = graph.size();
+    let mut parents = vec![0; graph.degree()];
+    for node in 0..total {
+        let encoded = encode_node(graph, replica_id, data, node, &mut parents, encode)?;
+        let start = data_at_node_offset(node);
+        encoded.write_bytes(&mut data[start..start + NODE_SIZE])?;
+
+        let done = node + 1;
+        if done % PROGRESS_INTERVAL == 0 || done == total {
+            progress(done, total);
+        }
+    }
+
+    Ok(())
 }
 
-pub fn decode_block<'a, H, G>(
-    graph: &'a G,
-    replica_id: &'a <H as Hasher>::Domain,
-    data: &'a [u8],
-    exp_parents_data: Option<&'a [u8]>,
-    v: usize,
-) -> Result<<H as Hasher>::Domain>
+/// Encodes the nodes of `data` from the last checkpoint up to `until`, saving a checkpoint every
+/// `checkpoint.interval` nodes. No checkpoint is saved once every node is encoded.
+fn encode_nodes_checkpointed<H, G>(
+    graph: &G,
+    checkpoint_path: &Path,
+    checkpoint: &mut ReplicationCheckpoint<H::Domain>,
+    data: &mut [u8],
+    until: usize,
+    encode: NodeCodec<H::Domain>,
+) -> Result<()>
 where
     H: Hasher,
     G::Key: AsRef<H::Domain>,
     G: Graph<H>,
 {
+    let replica_id = checkpoint.replica_id;
     let mut parents = vec![0; graph.degree()];
-    graph.parents(v, &mut parents)?;
-    let key = graph.create_key(replica_id, v, &parents, &data, exp_parents_data)?;
-    let node_data = <H as Hasher>::Domain::try_from_bytes(&data_at_node(data, v)?)?;
+    for node in checkpoint.encoded..until {
+        let encoded = encode_node(graph, &replica_id, data, node, &mut parents, encode)?;
+        let start = data_at_node_offset(node);
+        encoded.write_bytes(&mut data[start..start + NODE_SIZE])?;
+
+        let done = node + 1;
+        if done % checkpoint.interval == 0 && done < graph.size() {
+            checkpoint.save(checkpoint_path, data, done)?;
+        }
+    }
 
-    Ok(encode::decode(*key.as_ref(), node_data))
+    Ok(())
 }
 
-pub fn decode_domain_block<H: Hasher>(
+/// Checks that `len` bytes of `what` are exactly the nodes of `graph`.
+fn check_data_len<H: Hasher, G: Graph<H>>(graph: &G, what: &str, len: usize) -> Result<()> {
+    let expected_len = graph.size() * NODE_SIZE;
+    ensure!(
+        len == expected_len,
+        "invalid {} length: expected {} bytes ({} nodes of {} bytes), got {}",
+        what,
+        expected_len,
+        graph.size(),
+        NODE_SIZE,
+        len
+    );
+
+    Ok(())
+}
+
+fn encode_node<H, G>(
+    graph: &G,
     replica_id: &H::Domain,
-    tree: &BinaryLCMerkleTree<H>,
+    data: &[u8],
     node: usize,
-    node_data: H::Domain,
-    parents: &[u32],
+    parents: &mut [u32],
+    encode: NodeCodec<H::Domain>,
 ) -> Result<H::Domain>
 where
     H: Hasher,
+    G::Key: AsRef<H::Domain>,
+    G: Graph<H>,
 {
-    let key = create_key_from_tree::<H, _>(replica_id, node, parents, tree)?;
+    graph.parents(node, parents)?;
+    let key = graph.create_key(replica_id, node, parents, data, None)?;
+    let node_data = <H as Hasher>::Domain::try_from_bytes(data_at_node(data, node)?)?;
 
-    Ok(encode::decode(key, node_data))
+    Ok(encode(key.as_ref(), &node_data))
+}
+
+/// Decodes all nodes of `data`, with the default decoding of `PoRep::decode`.
+pub fn decode<'a, H, G>(
+    graph: &'a G,
+    replica_id: &'a <H as Hasher>::Domain,
+    data: &'a [u8],
+    exp_parents_data: Option<&'a [u8]>,
+) -> Result<Vec<u8>>
+where
+    H: Hasher,
+    G::Key: AsRef<H::Domain>,
+    G: Graph<H> + Sync,
+{
+    decode_with(graph, replica_id, data, exp_parents_data, |key, node| {
+        encode::decode(*key, *node)
+    })
+}
+
+/// Decodes all nodes of `data` with `decode`.
+pub fn decode_with<'a, H, G>(
+    graph: &'a G,
+    replica_id: &'a <H as Hasher>::Domain,
+    data: &'a [u8],
+    exp_parents_data: Option<&'a [u8]>,
+    decode: NodeCodec<H::Domain>,
+) -> Result<Vec<u8>>
+where
+    H: Hasher,
+    G::Key: AsRef<H::Domain>,
+    G: Graph<H> + Sync,
+{
+    // TODO: proper error handling
+    let result = (0..graph.size())
+        .into_par_iter()
+        .flat_map(|i| {
+            decode_block_with::<H, G>(graph, replica_id, data, exp_parents_data, i, decode)
+                .unwrap()
+                .into_bytes()
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Decodes node `v` of `data`, with the default decoding of `PoRep::decode`.
+pub fn decode_block<'a, H, G>(
+    graph: &'a G,
+    replica_id: &'a <H as Hasher>::Domain,
+    data: &'a [u8],
+    exp_parents_data: Option<&'a [u8]>,
+    v: usize,
+) -> Result<<H as Hasher>::Domain>
+where
+    H: Hasher,
+    G::Key: AsRef<H::Domain>,
+    G: Graph<H>,
+{
+    decode_block_with(graph, replica_id, data, exp_parents_data, v, |key, node| {
+        encode::decode(*key, *node)
+    })
+}
+
+/// Decodes node `v` of `data` with `decode`.
+pub fn decode_block_with<'a, H, G>(
+    graph: &'a G,
+    replica_id: &'a <H as Hasher>::Domain,
+    data: &'a [u8],
+    exp_parents_data: Option<&'a [u8]>,
+    v: usize,
+    decode: NodeCodec<H::Domain>,
+) -> Result<<H as Hasher>::Domain>
+where
+    H: Hasher,
+    G::Key: AsRef<H::Domain>,
+    G: Graph<H>,
+{
+    let mut parents = vec![0; graph.degree()];
+    graph.parents(v, &mut parents)?;
+    let key = graph.create_key(replica_id, v, &parents, &data, exp_parents_data)?;
+    let node_data = <H as Hasher>::Domain::try_from_bytes(&data_at_node(data, v)?)?;
+
+    Ok(decode(key.as_ref(), &node_data))
+}
+
+pub fn decode_domain_block<H: Hasher>(
+    replica_id: &H::Domain,
+    tree: &BinaryLCMerkleTree<H>,
+    node: usize,
+    node_data: H::Domain,
+    parents: &[u32],
+) -> Result<H::Domain>
+where
+    H: Hasher,
+{
+    let key = create_key_from_tree::<H, _>(replica_id, node, parents, tree)?;
+
+    Ok(encode::decode(key, node_data))
 }
 
 /// Creates the encoding key from a `MerkleTree`.
@@ -591,59 +2057,1811 @@ pub fn create_key_from_tree<H: Hasher, U: 'static + PoseidonArity>(
         }
     }
 
-    let hash = hasher.result();
-    Ok(bytes_into_fr_repr_safe(hash.as_ref()).into())
-}
-
-pub fn replica_id<H: Hasher>(prover_id: [u8; 32], sector_id: [u8; 32]) -> H::Domain {
-    let mut to_hash = [0; 64];
-    to_hash[..32].copy_from_slice(&prover_id);
-    to_hash[32..].copy_from_slice(&sector_id);
-
-    H::Function::hash_leaf(&to_hash)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+    let hash = hasher.result();
+    Ok(bytes_into_fr_repr_safe(hash.as_ref()).into())
+}
+
+/// Returns the encoding key of a node, as the canonical little endian bytes of the field
+/// element, for encoders outside of this crate. `parents` are the encoded values of the parents
+/// of the node, in order, and are empty for a node without parents. The key is
+/// `Sha256(id | encodedParentNode1 | ...)` with its two most significant bits cleared, exactly
+/// the key of `BucketGraph::create_key` and of the `kdf` circuit.
+pub fn derive_key_bytes(replica_id: &[u8], parents: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(replica_id);
+    for parent in parents {
+        hasher.input(parent);
+    }
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(hasher.result().as_ref());
+    // Like `bytes_into_fr_repr_safe`.
+    key[31] &= 0b0011_1111;
+    key
+}
+
+/// Creates a key of `len` field elements, the first being the key of `BucketGraph::create_key`.
+/// Element `j > 0` is `Sha256(id | encodedParentNode1 | ... | j)`, with `j` appended as a 32 bit
+/// big endian counter. Its circuit is `kdf_elements`.
+pub fn create_key_elements<H: Hasher>(
+    id: &H::Domain,
+    node: usize,
+    parents: &[u32],
+    parents_data: &[u8],
+    len: usize,
+) -> Result<Vec<H::Domain>> {
+    let mut preimage = AsRef::<[u8]>::as_ref(id).to_vec();
+
+    // The hash is about the parents, hence skip if a node doesn't have any parents
+    if node != parents[0] as usize {
+        for parent in parents.iter() {
+            preimage.extend_from_slice(data_at_node(parents_data, *parent as usize)?);
+        }
+    }
+
+    Ok((0..len)
+        .map(|j| {
+            let mut hasher = Sha256::new();
+            hasher.input(&preimage);
+            if j > 0 {
+                hasher.input(&(j as u32).to_be_bytes());
+            }
+
+            let hash = hasher.result();
+            bytes_into_fr_repr_safe(hash.as_ref()).into()
+        })
+        .collect())
+}
+
+/// Creates the encoding key like `BucketGraph::create_key`, but hashing with blake2s:
+/// `Blake2s(id | encodedParentNode1 | encodedParentNode1 | ...)`. Its circuit, `kdf_blake2s`,
+/// takes fewer constraints than the sha256 key derivation.
+pub fn create_key_blake2s<H: Hasher>(
+    id: &H::Domain,
+    node: usize,
+    parents: &[u32],
+    parents_data: &[u8],
+) -> Result<H::Domain> {
+    let mut preimage = AsRef::<[u8]>::as_ref(id).to_vec();
+
+    // The hash is about the parents, hence skip if a node doesn't have any parents
+    if node != parents[0] as usize {
+        for parent in parents.iter() {
+            preimage.extend_from_slice(data_at_node(parents_data, *parent as usize)?);
+        }
+    }
+
+    let hash = <Blake2sFunction as HashFunction<Blake2sDomain>>::hash(&preimage);
+    Ok(bytes_into_fr_repr_safe(AsRef::<[u8]>::as_ref(&hash)).into())
+}
+
+pub fn replica_id<H: Hasher>(prover_id: [u8; 32], sector_id: [u8; 32]) -> H::Domain {
+    let mut to_hash = [0; 64];
+    to_hash[..32].copy_from_slice(&prover_id);
+    to_hash[32..].copy_from_slice(&sector_id);
+
+    H::Function::hash_leaf(&to_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ff::Field;
+    use paired::bls12_381::Fr;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        cache_key::CacheKey,
+        drgraph::BucketGraph,
+        fr32::fr_into_bytes,
+        hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher},
+        merkle::{BinaryMerkleTree, MerkleTreeTrait},
+        table_tests,
+        test_helper::setup_replica,
+        util::{data_at_node, default_rows_to_discard},
+    };
+
+    use crate::stacked::BINARY_ARITY;
+
+    fn test_extract_all<Tree: MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let replica_id: <Tree::Hasher as Hasher>::Domain =
+            <Tree::Hasher as Hasher>::Domain::random(rng);
+        let nodes = 4;
+        let data = vec![2u8; 32 * nodes];
+
+        // MT for original data is always named tree-d, and it will be
+        // referenced later in the process as such.
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        // Generate a replica path.
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+
+        let pp: PublicParams<Tree::Hasher, BucketGraph<Tree::Hasher>> =
+            DrgPoRep::setup(&sp).expect("setup failed");
+
+        DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let mut copied = vec![0; data.len()];
+        copied.copy_from_slice(&mmapped_data);
+        assert_ne!(data, copied, "replication did not change data");
+
+        let decoded_data = DrgPoRep::<Tree::Hasher, _>::extract_all(
+            &pp,
+            &replica_id,
+            mmapped_data.as_mut(),
+            Some(config),
+        )
+        .unwrap_or_else(|e| {
+            panic!("Failed to extract data from `DrgPoRep`: {}", e);
+        });
+
+        assert_eq!(data, decoded_data.as_slice(), "failed to extract data");
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn extract_all_pedersen() {
+        test_extract_all::<BinaryMerkleTree<PedersenHasher>>();
+    }
+
+    #[test]
+    fn extract_all_sha256() {
+        test_extract_all::<BinaryMerkleTree<Sha256Hasher>>();
+    }
+
+    #[test]
+    fn extract_all_blake2s() {
+        test_extract_all::<BinaryMerkleTree<Blake2sHasher>>();
+    }
+
+    fn test_extract<Tree: MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let replica_id: <Tree::Hasher as Hasher>::Domain =
+            <Tree::Hasher as Hasher>::Domain::random(rng);
+        let nodes = 4;
+        let data = vec![2u8; 32 * nodes];
+
+        // MT for original data is always named tree-d, and it will be
+        // referenced later in the process as such.
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        // Generate a replica path.
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes: data.len() / 32,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+
+        let pp =
+            DrgPoRep::<Tree::Hasher, BucketGraph<Tree::Hasher>>::setup(&sp).expect("setup failed");
+
+        DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let mut copied = vec![0; data.len()];
+        copied.copy_from_slice(&mmapped_data);
+        assert_ne!(data, copied, "replication did not change data");
+
+        for i in 0..nodes {
+            let decoded_data =
+                DrgPoRep::extract(&pp, &replica_id, &mmapped_data, i, Some(config.clone()))
+                    .expect("failed to extract node data from PoRep");
+
+            let original_data = data_at_node(&data, i).unwrap();
+
+            assert_eq!(
+                original_data,
+                decoded_data.as_slice(),
+                "failed to extract data"
+            );
+        }
+    }
+
+    #[test]
+    fn extract_pedersen() {
+        test_extract::<BinaryMerkleTree<PedersenHasher>>();
+    }
+
+    #[test]
+    fn extract_sha256() {
+        test_extract::<BinaryMerkleTree<Sha256Hasher>>();
+    }
+
+    #[test]
+    fn extract_blake2s() {
+        test_extract::<BinaryMerkleTree<Blake2sHasher>>();
+    }
+
+    fn test_decode_node<Tree: MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let replica_id: <Tree::Hasher as Hasher>::Domain =
+            <Tree::Hasher as Hasher>::Domain::random(rng);
+        let nodes = 64;
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+
+        let pp =
+            DrgPoRep::<Tree::Hasher, BucketGraph<Tree::Hasher>>::setup(&sp).expect("setup failed");
+
+        DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let decoded_data =
+            DrgPoRep::<Tree::Hasher, _>::extract_all(&pp, &replica_id, &mmapped_data, Some(config))
+                .expect("failed to extract data");
+
+        let node = rng.gen_range(0, nodes);
+        let decoded_node = DrgPoRep::decode_node(&pp, &replica_id, &mmapped_data, node)
+            .expect("failed to decode node");
+
+        assert_eq!(
+            data_at_node(&decoded_data, node).unwrap(),
+            decoded_node.into_bytes().as_slice(),
+            "decode_node disagrees with extract_all"
+        );
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn decode_node_pedersen() {
+        test_decode_node::<BinaryMerkleTree<PedersenHasher>>();
+    }
+
+    #[test]
+    fn decode_node_sha256() {
+        test_decode_node::<BinaryMerkleTree<Sha256Hasher>>();
+    }
+
+    fn test_replicate_commitments<Tree: MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = <Tree::Hasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp =
+            DrgPoRep::<Tree::Hasher, BucketGraph<Tree::Hasher>>::setup(&sp).expect("setup failed");
+
+        let (tau, _aux) = DrgPoRep::<Tree::Hasher, _>::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let data_tree =
+            create_base_merkle_tree::<BinaryMerkleTree<Tree::Hasher>>(None, nodes, &data).unwrap();
+        let replica_tree =
+            create_base_merkle_tree::<BinaryMerkleTree<Tree::Hasher>>(None, nodes, &mmapped_data)
+                .unwrap();
+
+        assert_eq!(
+            tau.comm_d,
+            data_tree.root(),
+            "comm_d is not the original data root"
+        );
+        assert_eq!(
+            tau.comm_r,
+            replica_tree.root(),
+            "comm_r is not the replica root"
+        );
+        assert_ne!(tau.comm_d, tau.comm_r);
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn replicate_commitments_pedersen() {
+        test_replicate_commitments::<BinaryMerkleTree<PedersenHasher>>();
+    }
+
+    #[test]
+    fn replicate_commitments_blake2s() {
+        test_replicate_commitments::<BinaryMerkleTree<Blake2sHasher>>();
+    }
+
+    #[test]
+    fn replicate_reports_progress() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 2500;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let mut reported = Vec::new();
+        DrgPoRep::replicate_with_progress(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+            |done, total| {
+                assert_eq!(total, nodes);
+                reported.push(done);
+            },
+        )
+        .expect("replication failed");
+
+        assert!(!reported.is_empty(), "progress was never reported");
+        assert!(
+            reported.windows(2).all(|w| w[0] < w[1]),
+            "progress is not monotonically increasing"
+        );
+        assert_eq!(reported.last(), Some(&nodes));
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn commitments_match_proof_roots() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id: Some(replica_id),
+            challenges: vec![3],
+            tau: Some(tau),
+        };
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: default_rows_to_discard(nodes, BINARY_ARITY),
+        };
+        let proof = DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs).expect("proving failed");
+
+        let comm_d = DrgPoRep::commit_data(&pp, &data).expect("failed to commit to data");
+        let comm_r =
+            DrgPoRep::commit_replica(&pp, &mmapped_data).expect("failed to commit to replica");
+        assert_eq!(comm_d, proof.data_root);
+        assert_eq!(comm_r, proof.replica_root);
+        assert_eq!(Tau::new(comm_d, comm_r), tau);
+
+        assert!(DrgPoRep::commit_data(&pp, &data[NODE_SIZE..]).is_err());
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn replicate_reports_stats() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (_, _, stats) = DrgPoRep::replicate_with_stats(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        assert_eq!(stats.nodes, nodes);
+        assert_eq!(stats.bytes, nodes * NODE_SIZE);
+        assert!(stats.kdf_time + stats.merkle_time <= stats.elapsed);
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn resume_replicate_matches_full_run() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let setup = |dir: &Path| {
+            let config = StoreConfig::new(
+                dir,
+                CacheKey::CommDTree.to_string(),
+                default_rows_to_discard(nodes, BINARY_ARITY),
+            );
+            let replica_path = dir.join("replica-path");
+            let mmapped_data = setup_replica(&data, &replica_path);
+            (config, replica_path, mmapped_data)
+        };
+
+        let full_dir = tempfile::tempdir().unwrap();
+        let (config, replica_path, mut full_replica) = setup(full_dir.path());
+        let (full_tau, _) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (full_replica.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        // Checkpointing without interruption gives the same replica.
+        let checkpointed_dir = tempfile::tempdir().unwrap();
+        let (config, replica_path, mut checkpointed_replica) = setup(checkpointed_dir.path());
+        let checkpoint_path = checkpointed_dir.path().join("checkpoint");
+        let (checkpointed_tau, _) = DrgPoRep::replicate_checkpointed(
+            &pp,
+            &replica_id,
+            (checkpointed_replica.as_mut()).into(),
+            config,
+            replica_path,
+            &checkpoint_path,
+            16,
+        )
+        .expect("checkpointed replication failed");
+        assert_eq!(checkpointed_tau, full_tau);
+        assert_eq!(&checkpointed_replica[..], &full_replica[..]);
+        assert!(!checkpoint_path.exists(), "checkpoint left behind");
+
+        // The data need not map the replica file, the replica is written to it.
+        let buffered_dir = tempfile::tempdir().unwrap();
+        let (config, replica_path, _) = setup(buffered_dir.path());
+        let mut buffered_replica = data.clone();
+        let (buffered_tau, _) = DrgPoRep::replicate_checkpointed(
+            &pp,
+            &replica_id,
+            buffered_replica.as_mut_slice().into(),
+            config,
+            replica_path.clone(),
+            &buffered_dir.path().join("checkpoint"),
+            16,
+        )
+        .expect("checkpointed replication failed");
+        assert_eq!(buffered_tau, full_tau);
+        assert_eq!(std::fs::read(&replica_path).unwrap(), &full_replica[..]);
+
+        // Crash after encoding 40 nodes, the last checkpoint was saved after 32.
+        let crashed_dir = tempfile::tempdir().unwrap();
+        let (config, replica_path, mut crashed_replica) = setup(crashed_dir.path());
+        let checkpoint_path = crashed_dir.path().join("checkpoint");
+        let (_, mut checkpoint) = DrgPoRep::<PedersenHasher, BucketGraph<_>>::start_checkpoint(
+            &pp,
+            &replica_id,
+            &crashed_replica,
+            config,
+            replica_path.clone(),
+            &checkpoint_path,
+            16,
+        )
+        .expect("failed to start checkpoint");
+        encode_nodes_checkpointed(
+            &pp.graph,
+            &checkpoint_path,
+            &mut checkpoint,
+            crashed_replica.as_mut(),
+            40,
+            DrgPoRep::<PedersenHasher, BucketGraph<_>>::encode,
+        )
+        .expect("encoding failed");
+        drop(crashed_replica);
+
+        let saved =
+            ReplicationCheckpoint::<<PedersenHasher as Hasher>::Domain>::read(&checkpoint_path)
+                .expect("failed to read checkpoint");
+        assert_eq!(saved.encoded, 32);
+
+        let other_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        assert!(DrgPoRep::resume_replicate(&pp, &other_id, &checkpoint_path).is_err());
+
+        let (resumed_replica, resumed_tau, resumed_aux) =
+            DrgPoRep::resume_replicate(&pp, &replica_id, &checkpoint_path)
+                .expect("failed to resume replication");
+        assert_eq!(resumed_tau, full_tau);
+        assert_eq!(&resumed_replica[..], &full_replica[..]);
+        assert_eq!(std::fs::read(&replica_path).unwrap(), &full_replica[..]);
+        assert_eq!(
+            DrgPoRep::tau_from_aux(&pp, &resumed_aux).expect("failed to recover tau"),
+            full_tau
+        );
+        assert!(!checkpoint_path.exists(), "checkpoint left behind");
+
+        full_dir.close().expect("Failed to remove cache dir");
+        checkpointed_dir
+            .close()
+            .expect("Failed to remove cache dir");
+        buffered_dir.close().expect("Failed to remove cache dir");
+        crashed_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn reencode_to_new_replica_id() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let old_replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let new_replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let mut replica = data.clone();
+        encode_nodes(&pp.graph, &old_replica_id, &mut replica).expect("encoding failed");
+        let mut expected = data.clone();
+        encode_nodes(&pp.graph, &new_replica_id, &mut expected).expect("encoding failed");
+
+        DrgPoRep::reencode(&pp, &old_replica_id, &new_replica_id, &mut replica)
+            .expect("reencoding failed");
+        assert_eq!(replica, expected, "reencoding differs from encoding");
+
+        let extracted =
+            DrgPoRep::extract_all(&pp, &new_replica_id, &replica, None).expect("failed to extract");
+        assert_eq!(extracted, data);
+
+        assert!(
+            DrgPoRep::reencode(&pp, &new_replica_id, &old_replica_id, &mut replica[1..]).is_err()
+        );
+    }
+
+    #[test]
+    fn prove_from_disk_matches_prove() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 3,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path.clone(),
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id: Some(replica_id),
+            challenges: vec![1, 17, 63],
+            tau: Some(tau),
+        };
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: config.rows_to_discard,
+        };
+
+        let proof = DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs).expect("proving failed");
+        let disk_proof = DrgPoRep::prove_from_disk(&pp, &pub_inputs, &config, replica_path)
+            .expect("proving from disk failed");
+        assert_eq!(proof, disk_proof);
+        assert!(DrgPoRep::verify(&pp, &pub_inputs, &disk_proof).expect("failed to verify"));
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn prove_rejects_out_of_range_challenge() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: config.rows_to_discard,
+        };
+
+        for &challenge in &[nodes, usize::max_value()] {
+            let pub_inputs = PublicInputs {
+                replica_id: Some(replica_id),
+                challenges: vec![challenge],
+                tau: Some(tau),
+            };
+            let err = DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs)
+                .expect_err("proved an out of range challenge");
+            match err.downcast_ref::<VerifyError>() {
+                Some(VerifyError::ChallengeOutOfRange {
+                    challenge: c,
+                    nodes: n,
+                }) => {
+                    assert_eq!((*c, *n), (challenge, nodes));
+                }
+                res => panic!("unexpected error {:?}", res),
+            }
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "challenge {} is out of range for {} nodes",
+                    challenge, nodes
+                )
+            );
+        }
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn prover_aux_write_read_roundtrip() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 3,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id: Some(replica_id),
+            challenges: vec![2, 31, 60],
+            tau: Some(tau),
+        };
+        let prove = |aux: &ProverAux<PedersenHasher>| {
+            let priv_inputs = PrivateInputs {
+                tree_d: &aux.tree_d,
+                tree_r: &aux.tree_r,
+                tree_r_config_rows_to_discard: config.rows_to_discard,
+            };
+            DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs).expect("proving failed")
+        };
+
+        let proof = prove(&aux);
+        let aux_path = cache_dir.path().join("aux");
+        aux.write(&aux_path).expect("failed to write aux");
+        drop(aux);
+
+        let read_aux = ProverAux::<PedersenHasher>::read(&aux_path).expect("failed to read aux");
+        let read_proof = prove(&read_aux);
+        assert_eq!(proof, read_proof);
+        assert!(DrgPoRep::verify(&pp, &pub_inputs, &read_proof).expect("failed to verify"));
+
+        // The commitments are recovered from the aux alone.
+        let read_tau = DrgPoRep::tau_from_aux(&pp, &read_aux).expect("failed to recover tau");
+        assert_eq!(read_tau, tau);
+        let other_sp = SetupParams {
+            drg: DrgParams {
+                nodes: nodes * 2,
+                ..sp.drg.clone()
+            },
+            ..sp.clone()
+        };
+        let other_pp =
+            DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&other_sp).expect("setup failed");
+        assert!(DrgPoRep::tau_from_aux(&other_pp, &read_aux).is_err());
+
+        // Trees built elsewhere have nothing to write.
+        let unpersisted = ProverAux::new(read_aux.tree_d, read_aux.tree_r);
+        assert!(unpersisted.write(cache_dir.path().join("other")).is_err());
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn prove_parallel_matches_sequential() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let challenges = sample_challenges([3; 32], 16, nodes - 1)
+            .into_iter()
+            .map(|c| c + 1)
+            .collect::<Vec<_>>();
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: challenges.len(),
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id: Some(replica_id),
+            challenges: challenges.clone(),
+            tau: Some(tau),
+        };
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: config.rows_to_discard,
+        };
+
+        let proof = DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs).expect("proving failed");
+
+        let mut replica_nodes = Vec::new();
+        let mut replica_parents = Vec::new();
+        let mut data_nodes = Vec::new();
+        for challenge in challenges {
+            let (replica_node, parents, data_node) =
+                prove_challenge(&pp, &replica_id, &priv_inputs, challenge).expect("proving failed");
+            replica_nodes.push(replica_node);
+            replica_parents.push(parents);
+            data_nodes.push(data_node);
+        }
+        let sequential = Proof::new(replica_nodes, replica_parents, data_nodes);
+
+        assert_eq!(proof, sequential);
+        assert!(DrgPoRep::verify(&pp, &pub_inputs, &proof).expect("failed to verify"));
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    fn replicate_seeded(
+        pp: &PublicParams<PedersenHasher, BucketGraph<PedersenHasher>>,
+        replica_id: &<PedersenHasher as Hasher>::Domain,
+        data: &[u8],
+        seed: [u8; 32],
+    ) -> (Vec<u8>, Tau<<PedersenHasher as Hasher>::Domain>) {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(pp.graph.size(), BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(data, &replica_path);
+
+        let (tau, _) = DrgPoRep::replicate_with_seed(
+            pp,
+            replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+            seed,
+        )
+        .expect("replication failed");
+
+        (mmapped_data.to_vec(), tau)
+    }
+
+    #[test]
+    fn replicate_with_seed_is_deterministic() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (replica_a, tau_a) = replicate_seeded(&pp, &replica_id, &data, [1; 32]);
+        let (replica_b, tau_b) = replicate_seeded(&pp, &replica_id, &data, [1; 32]);
+        let (replica_c, tau_c) = replicate_seeded(&pp, &replica_id, &data, [2; 32]);
+
+        assert_eq!(replica_a, replica_b);
+        assert_eq!(tau_a.comm_r, tau_b.comm_r);
+        assert_ne!(replica_a, replica_c);
+        assert_ne!(tau_a.comm_r, tau_c.comm_r);
+        assert_eq!(tau_a.comm_d, tau_c.comm_d);
+
+        let graph_a = pp.reseed([1; 32]).unwrap().graph;
+        let graph_b = pp.reseed([1; 32]).unwrap().graph;
+        let graph_c = pp.reseed([2; 32]).unwrap().graph;
+
+        let parents = |graph: &BucketGraph<PedersenHasher>| -> Vec<Vec<u32>> {
+            (0..nodes)
+                .map(|node| {
+                    let mut parents = vec![0; graph.degree()];
+                    graph.parents(node, &mut parents).unwrap();
+                    parents
+                })
+                .collect()
+        };
+        assert_eq!(parents(&graph_a), parents(&graph_b));
+        assert_ne!(parents(&graph_a), parents(&graph_c));
+
+        // The seeded replica extracts with the reseeded parameters.
+        let seeded = pp.reseed([1; 32]).unwrap();
+        let extracted =
+            DrgPoRep::<PedersenHasher, _>::extract_all(&seeded, &replica_id, &replica_a, None)
+                .expect("extraction failed");
+        assert_eq!(extracted, data);
+    }
+
+    fn replicate_data_len(len: usize) -> Result<()> {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..=len / NODE_SIZE)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .take(len)
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&test_setup_params(1))
+            .expect("setup failed");
+        let res = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .map(|_| ());
+
+        cache_dir.close().expect("Failed to remove cache dir");
+        res
+    }
+
+    #[test]
+    fn cached_parents_match_graph() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 64;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 2,
+        };
+        let mut pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+        pp.cache_parents().expect("failed to cache parents");
+
+        let mut parents = vec![0; pp.graph.degree()];
+        let mut cached = vec![0; pp.graph.degree()];
+        for node in 0..nodes {
+            pp.graph.parents(node, &mut parents).unwrap();
+            pp.parents(node, &mut cached).unwrap();
+            assert_eq!(parents, cached);
+        }
+        assert!(pp.parents(nodes, &mut cached).is_err());
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id: Some(replica_id),
+            challenges: vec![7, 42],
+            tau: Some(tau),
+        };
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: default_rows_to_discard(nodes, BINARY_ARITY),
+        };
+        let proof = DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs).expect("proving failed");
+
+        // The proof carries the cached parents, which the uncached parameters agree with.
+        for (challenge, replica_parents) in pub_inputs.challenges.iter().zip(&proof.replica_parents)
+        {
+            pp.graph.parents(*challenge, &mut parents).unwrap();
+            let proven: Vec<u32> = replica_parents.iter().map(|(parent, _)| *parent).collect();
+            assert_eq!(proven, parents);
+        }
+        assert!(DrgPoRep::verify(&pp, &pub_inputs, &proof).expect("verification failed"));
+
+        let uncached = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).unwrap();
+        assert!(DrgPoRep::verify(&uncached, &pub_inputs, &proof).expect("verification failed"));
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn verify_full_detects_altered_replica() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 32;
+        let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<Sha256Hasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let mut replica = mmapped_data.to_vec();
+        assert!(DrgPoRep::verify_full(&pp, &replica_id, &replica, &data).unwrap());
+        assert!(!DrgPoRep::verify_full(&pp, &replica_id, &replica[NODE_SIZE..], &data).unwrap());
+
+        // Flip the lowest bit of a node, which keeps it a valid field element.
+        replica[data_at_node_offset(7)] ^= 1;
+        assert!(!DrgPoRep::verify_full(&pp, &replica_id, &replica, &data).unwrap());
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn replicate_in_place_and_cloned() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 1,
+        };
+        let pp = DrgPoRep::<Sha256Hasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        // Both replications get their own cache, their trees have the same names.
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cloned_cache_dir = tempfile::tempdir().unwrap();
+        let config = |dir: &tempfile::TempDir| {
+            StoreConfig::new(
+                dir.path(),
+                CacheKey::CommDTree.to_string(),
+                default_rows_to_discard(nodes, BINARY_ARITY),
+            )
+        };
+
+        // In place: the slice becomes the replica, and extraction recovers the data from it.
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+        let (tau, _) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config(&cache_dir),
+            replica_path,
+        )
+        .expect("replication failed");
+        assert_ne!(
+            &mmapped_data[..],
+            &data[..],
+            "replication did not change data"
+        );
+
+        let extracted = DrgPoRep::extract_all(&pp, &replica_id, &mmapped_data, None)
+            .expect("failed to extract");
+        assert_eq!(extracted, data);
+
+        // Cloned: the data is left untouched and the same replica is returned.
+        let cloned_path = cloned_cache_dir.path().join("replica-path");
+        let (replica, cloned_tau, _) = DrgPoRep::replicate_cloned(
+            &pp,
+            &replica_id,
+            &data,
+            None,
+            config(&cloned_cache_dir),
+            cloned_path.clone(),
+        )
+        .expect("replication failed");
+        assert_eq!(&replica[..], &mmapped_data[..]);
+        assert_eq!(cloned_tau, tau);
+        assert_eq!(std::fs::read(&cloned_path).unwrap(), replica);
+
+        cache_dir.close().expect("Failed to remove cache dir");
+        cloned_cache_dir
+            .close()
+            .expect("Failed to remove cache dir");
+    }
+
+    fn test_proof(
+        challenges: Vec<usize>,
+    ) -> (
+        PublicParams<PedersenHasher, BucketGraph<PedersenHasher>>,
+        PublicInputs<<PedersenHasher as Hasher>::Domain>,
+        Proof<PedersenHasher>,
+    ) {
+        let (pp, pub_inputs, mut proofs) = test_partition_proofs(challenges, 1);
+        (pp, pub_inputs, proofs.remove(0))
+    }
+
+    fn test_partition_proofs(
+        challenges: Vec<usize>,
+        partition_count: usize,
+    ) -> (
+        PublicParams<PedersenHasher, BucketGraph<PedersenHasher>>,
+        PublicInputs<<PedersenHasher as Hasher>::Domain>,
+        Vec<Proof<PedersenHasher>>,
+    ) {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: challenges.len(),
+        };
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let pub_inputs = PublicInputs {
+            replica_id: Some(replica_id),
+            challenges,
+            tau: Some(tau),
+        };
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: default_rows_to_discard(nodes, BINARY_ARITY),
+        };
+
+        let proofs =
+            DrgPoRep::prove_all_partitions(&pp, &pub_inputs, &priv_inputs, partition_count)
+                .expect("proving failed");
+        assert_eq!(proofs.len(), partition_count);
+        assert!(
+            DrgPoRep::prove_partition(
+                &pp,
+                &pub_inputs,
+                &priv_inputs,
+                partition_count,
+                partition_count
+            )
+            .is_err(),
+            "proved a partition out of range"
+        );
+
+        cache_dir.close().expect("Failed to remove cache dir");
+
+        (pp, pub_inputs, proofs)
+    }
+
+    #[test]
+    fn prove_verify_all_partitions() {
+        let (pp, pub_inputs, proofs) = test_partition_proofs(vec![3, 9], 3);
+
+        assert!(DrgPoRep::verify_all_partitions(&pp, &pub_inputs, &proofs)
+            .expect("failed to verify partition proofs"));
+
+        let mut wrong = proofs;
+        wrong[2].nodes[0].data = wrong[2].replica_nodes[0].data;
+        assert!(!DrgPoRep::verify_all_partitions(&pp, &pub_inputs, &wrong)
+            .expect("failed to verify partition proofs"));
+    }
+
+    #[test]
+    fn proof_equals_its_clone() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let (_, pub_inputs, proof) = test_proof(vec![3, 9]);
+
+        let tau = pub_inputs.tau.unwrap();
+        assert_eq!(tau, tau.clone());
+        assert_eq!(proof, proof.clone());
+
+        let serialized = serde_json::to_string(&proof).expect("failed to serialize proof");
+        let deserialized: Proof<PedersenHasher> =
+            serde_json::from_str(&serialized).expect("failed to deserialize proof");
+        assert_eq!(proof, deserialized);
+
+        let mut other = proof.clone();
+        other.replica_parents[0][0].1.data = <PedersenHasher as Hasher>::Domain::random(rng);
+        assert_ne!(proof, other);
+    }
+
+    #[test]
+    fn challenge_derivations() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        type D = <PedersenHasher as Hasher>::Domain;
+
+        let replica_id = D::random(rng);
+        let tau = Tau::new(D::random(rng), D::random(rng));
+
+        let fiat_shamir = FiatShamirChallenges
+            .derive(&tau, &replica_id, 20, 15)
+            .unwrap();
+        assert_eq!(fiat_shamir.len(), 20);
+        assert!(fiat_shamir.iter().all(|c| *c < 15));
+        assert_eq!(
+            fiat_shamir,
+            FiatShamirChallenges
+                .derive(&tau, &replica_id, 20, 15)
+                .unwrap()
+        );
+        let other_tau = Tau::new(tau.comm_d, D::random(rng));
+        assert_ne!(
+            fiat_shamir,
+            FiatShamirChallenges
+                .derive(&other_tau, &replica_id, 20, 15)
+                .unwrap()
+        );
+
+        for n in &[1, 2, 7] {
+            let random = RandomChallenges.derive(&tau, &replica_id, 100, *n).unwrap();
+            assert_eq!(random.len(), 100);
+            assert!(random.iter().all(|c| c < n));
+        }
+
+        // There is nothing to challenge among no nodes.
+        assert!(FiatShamirChallenges
+            .derive(&tau, &replica_id, 1, 0)
+            .is_err());
+        assert!(RandomChallenges.derive(&tau, &replica_id, 1, 0).is_err());
+
+        // A deterministic derivation can be checked by the verifier.
+        let nodes = 16;
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams::builder()
+            .nodes(nodes)
+            .expansion_degree(0)
+            .porep_id([32; 32])
+            .challenges_count(3)
+            .build()
+            .unwrap();
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
+
+        let (tau, aux) = DrgPoRep::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let priv_inputs = PrivateInputs {
+            tree_d: &aux.tree_d,
+            tree_r: &aux.tree_r,
+            tree_r_config_rows_to_discard: default_rows_to_discard(nodes, BINARY_ARITY),
+        };
+        let (mut pub_inputs, proof) = DrgPoRep::prove_with_derivation(
+            &pp,
+            replica_id,
+            tau,
+            &priv_inputs,
+            &FiatShamirChallenges,
+        )
+        .expect("proving failed");
+        assert!(pub_inputs.challenges.iter().all(|c| (1..nodes).contains(c)));
+        assert!(
+            DrgPoRep::verify_with_derivation(&pp, &pub_inputs, &proof, &FiatShamirChallenges)
+                .expect("failed to verify")
+        );
+
+        // A valid proof for challenges picked by the prover is rejected.
+        pub_inputs.challenges = pub_inputs.challenges.iter().map(|c| c % 15 + 1).collect();
+        let proof = DrgPoRep::prove(&pp, &pub_inputs, &priv_inputs).expect("proving failed");
+        assert!(DrgPoRep::verify(&pp, &pub_inputs, &proof).expect("failed to verify"));
+        assert!(
+            !DrgPoRep::verify_with_derivation(&pp, &pub_inputs, &proof, &FiatShamirChallenges)
+                .expect("failed to verify")
+        );
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn sample_challenges_is_deterministic_and_distinct() {
+        let seed = [7; 32];
+        let challenges = sample_challenges(seed, 10, 16);
+        assert_eq!(challenges, sample_challenges(seed, 10, 16));
+        assert_ne!(challenges, sample_challenges([8; 32], 10, 16));
+
+        let distinct: HashSet<_> = challenges.iter().collect();
+        assert_eq!(distinct.len(), challenges.len());
+        assert!(challenges.iter().all(|c| *c < 16));
+
+        // Sampling every node yields a permutation.
+        let mut all = sample_challenges(seed, 16, 16);
+        all.sort();
+        assert_eq!(all, (0..16).collect::<Vec<_>>());
+
+        // Single and multiple challenges can be proven.
+        for count in &[1, 3] {
+            let challenges: Vec<_> = sample_challenges(seed, *count, 15)
+                .into_iter()
+                .map(|c| c + 1)
+                .collect();
+            let (pp, pub_inputs, proof) = test_proof(challenges);
+            assert!(DrgPoRep::verify(&pp, &pub_inputs, &proof).expect("failed to verify"));
+        }
+    }
+
+    #[test]
+    fn verify_detailed_reports_failure() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let (pp, pub_inputs, proof) = test_proof(vec![7]);
+        let nodes = pp.graph.size();
+
+        let verify = |pub_inputs: &PublicInputs<_>, proof: &Proof<_>| {
+            DrgPoRep::verify_detailed(&pp, pub_inputs, proof)
+        };
+
+        assert!(verify(&pub_inputs, &proof).is_ok());
+
+        let out_of_range = PublicInputs {
+            challenges: vec![nodes],
+            ..pub_inputs.clone()
+        };
+        match verify(&out_of_range, &proof) {
+            Err(VerifyError::ChallengeOutOfRange { challenge, .. }) => assert_eq!(challenge, nodes),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let other_challenge = PublicInputs {
+            challenges: vec![5],
+            ..pub_inputs.clone()
+        };
+        match verify(&other_challenge, &proof) {
+            Err(VerifyError::WrongChallenge(5)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut wrong_parents = proof.clone();
+        wrong_parents.replica_parents[0][0].0 += 1;
+        match verify(&pub_inputs, &wrong_parents) {
+            Err(VerifyError::WrongParents(7)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let other_comm_r = PublicInputs {
+            tau: Some(Tau::new(
+                pub_inputs.tau.unwrap().comm_d,
+                <PedersenHasher as Hasher>::Domain::random(rng),
+            )),
+            ..pub_inputs.clone()
+        };
+        match verify(&other_comm_r, &proof) {
+            Err(VerifyError::WrongRoot(7)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Prove the first parent with the path of another one.
+        let parents = &proof.replica_parents[0];
+        let first = parents[0].0;
+        let (_, other_path) = parents
+            .iter()
+            .find(|(parent, _)| *parent != first)
+            .expect("all parents are the same");
+        let mut bad_path = proof.clone();
+        bad_path.replica_parents[0][0].1 = other_path.clone();
+        match verify(&pub_inputs, &bad_path) {
+            Err(VerifyError::InvalidPath(node)) => assert_eq!(node, first as usize),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let mut altered_replica = proof.clone();
+        altered_replica.replica_nodes[0].data = <PedersenHasher as Hasher>::Domain::random(rng);
+        match verify(&pub_inputs, &altered_replica) {
+            Err(VerifyError::DecodeMismatch(7)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // A path missing its top level still proves the challenge, but not over `n` nodes.
+        let mut shallow = proof.clone();
+        let data_proof = &proof.nodes[0].proof;
+        let mut path = data_proof.path();
+        path.pop();
+        shallow.nodes[0].proof =
+            MerkleProof::from_base_path(path, data_proof.root(), data_proof.leaf());
+        assert!(shallow.nodes[0].proves_challenge(7));
+        match verify(&pub_inputs, &shallow) {
+            Err(VerifyError::WrongDepth {
+                node: 7,
+                depth: 3,
+                expected: 4,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // The data node is checked against the decoded replica node before its path.
+        let mut swapped_data = proof.clone();
+        swapped_data.nodes[0].data = proof.replica_nodes[0].data;
+        match verify(&pub_inputs, &swapped_data) {
+            Err(VerifyError::DecodeMismatch(7)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(!DrgPoRep::verify(&pp, &pub_inputs, &swapped_data).expect("failed to verify"));
+
+        // A truncated proof must not make the verifier index out of bounds.
+        let mut truncated = proof.clone();
+        truncated.replica_parents.pop();
+        match verify(&pub_inputs, &truncated) {
+            Err(VerifyError::WrongProofLength {
+                expected: 1,
+                actual: 0,
+            }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(!DrgPoRep::verify(&pp, &pub_inputs, &truncated).expect("failed to verify"));
+
+        let missing_replica_id = PublicInputs {
+            replica_id: None,
+            ..pub_inputs.clone()
+        };
+        match verify(&missing_replica_id, &proof) {
+            Err(VerifyError::Other(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(DrgPoRep::verify(&pp, &missing_replica_id, &proof).is_err());
+        assert!(!DrgPoRep::verify(&pp, &pub_inputs, &altered_replica).unwrap());
+    }
+
+    #[test]
+    fn validate_public_inputs() {
+        let (pp, pub_inputs, proof) = test_proof(vec![7]);
+        let nodes = pp.graph.size();
+        assert!(pub_inputs.validate(&pp).is_ok());
+
+        let out_of_range = PublicInputs {
+            challenges: vec![3, nodes + 1],
+            ..pub_inputs.clone()
+        };
+        match out_of_range.validate(&pp) {
+            Err(VerifyError::ChallengeOutOfRange { challenge, .. }) => {
+                assert_eq!(challenge, nodes + 1)
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Larger than the field modulus.
+        let malformed = <PedersenHasher as Hasher>::Domain::try_from_bytes(&[0xff; 32]).unwrap();
+        let malformed_tau = PublicInputs {
+            tau: Some(Tau::new(pub_inputs.tau.unwrap().comm_d, malformed)),
+            ..pub_inputs.clone()
+        };
+        match malformed_tau.validate(&pp) {
+            Err(VerifyError::MalformedCommitment("comm_r")) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match DrgPoRep::verify_detailed(&pp, &malformed_tau, &proof) {
+            Err(VerifyError::MalformedCommitment("comm_r")) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(!DrgPoRep::verify(&pp, &malformed_tau, &proof).unwrap());
+    }
+
+    #[test]
+    fn replicate_rejects_invalid_data_len() {
+        // 16 nodes of 32 bytes.
+        assert!(replicate_data_len(16 * NODE_SIZE).is_ok());
 
-    use ff::Field;
-    use paired::bls12_381::Fr;
-    use rand::SeedableRng;
-    use rand_xorshift::XorShiftRng;
-    use storage_proofs_core::{
-        cache_key::CacheKey,
-        drgraph::{BucketGraph, BASE_DEGREE},
-        fr32::fr_into_bytes,
-        hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher},
-        merkle::{BinaryMerkleTree, MerkleTreeTrait},
-        table_tests,
-        test_helper::setup_replica,
-        util::{data_at_node, default_rows_to_discard},
-    };
+        let err = replicate_data_len(15 * NODE_SIZE).expect_err("accepted too short data");
+        assert!(err.to_string().contains("invalid data length"));
 
-    use crate::stacked::BINARY_ARITY;
+        let err = replicate_data_len(16 * NODE_SIZE + 7).expect_err("accepted a partial node");
+        assert!(err.to_string().contains("invalid data length"));
+    }
 
-    fn test_extract_all<Tree: MerkleTreeTrait>() {
+    fn test_replicate_update<Tree: MerkleTreeTrait>(changed_node: usize) {
         let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
 
-        let replica_id: <Tree::Hasher as Hasher>::Domain =
-            <Tree::Hasher as Hasher>::Domain::random(rng);
-        let nodes = 4;
-        let data = vec![2u8; 32 * nodes];
-
-        // MT for original data is always named tree-d, and it will be
-        // referenced later in the process as such.
-        let cache_dir = tempfile::tempdir().unwrap();
-        let config = StoreConfig::new(
-            cache_dir.path(),
-            CacheKey::CommDTree.to_string(),
-            default_rows_to_discard(nodes, BINARY_ARITY),
-        );
-
-        // Generate a replica path.
-        let replica_path = cache_dir.path().join("replica-path");
-        let mut mmapped_data = setup_replica(&data, &replica_path);
+        let nodes = 64;
+        let replica_id = <Tree::Hasher as Hasher>::Domain::random(rng);
+        let old_data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let mut new_data = old_data.clone();
+        let start = data_at_node_offset(changed_node);
+        new_data[start..start + NODE_SIZE].copy_from_slice(&fr_into_bytes(&Fr::random(rng)));
 
         let sp = SetupParams {
             drg: DrgParams {
@@ -655,78 +3873,223 @@ mod tests {
             private: false,
             challenges_count: 1,
         };
+        let pp = DrgPoRep::<Tree::Hasher, BucketGraph<_>>::setup(&sp).expect("setup failed");
 
-        let pp: PublicParams<Tree::Hasher, BucketGraph<Tree::Hasher>> =
-            DrgPoRep::setup(&sp).expect("setup failed");
+        let store_config = |cache_dir: &tempfile::TempDir| {
+            StoreConfig::new(
+                cache_dir.path(),
+                CacheKey::CommDTree.to_string(),
+                default_rows_to_discard(nodes, BINARY_ARITY),
+            )
+        };
+        let replicate_into = |data: &[u8], cache_dir: &tempfile::TempDir| {
+            let replica_path = cache_dir.path().join("replica-path");
+            let mut mmapped_data = setup_replica(data, &replica_path);
+            let (tau, _) = DrgPoRep::replicate(
+                &pp,
+                &replica_id,
+                (mmapped_data.as_mut()).into(),
+                None,
+                store_config(cache_dir),
+                replica_path.clone(),
+            )
+            .expect("replication failed");
 
-        DrgPoRep::replicate(
+            (tau, mmapped_data, replica_path)
+        };
+
+        // Replicate the old data, then update it with the changed node.
+        let update_dir = tempfile::tempdir().unwrap();
+        let (old_tau, mut replica, replica_path) = replicate_into(&old_data, &update_dir);
+        let mut replica_copy = replica.to_vec();
+        // The updated trees must not reuse the stores of the old ones.
+        let updated_trees_dir = tempfile::tempdir().unwrap();
+        let (updated_tau, _) = DrgPoRep::replicate_update(
             &pp,
             &replica_id,
-            (mmapped_data.as_mut()).into(),
-            None,
-            config.clone(),
+            &new_data,
+            replica.as_mut(),
+            &[changed_node],
+            store_config(&updated_trees_dir),
             replica_path,
         )
-        .expect("replication failed");
+        .expect("replica update failed");
 
-        let mut copied = vec![0; data.len()];
-        copied.copy_from_slice(&mmapped_data);
-        assert_ne!(data, copied, "replication did not change data");
+        // Replicate the new data from scratch.
+        let full_dir = tempfile::tempdir().unwrap();
+        let (full_tau, full_replica, _) = replicate_into(&new_data, &full_dir);
 
-        let decoded_data = DrgPoRep::<Tree::Hasher, _>::extract_all(
+        assert_ne!(old_tau.comm_r, updated_tau.comm_r);
+        assert_eq!(updated_tau.comm_d, full_tau.comm_d);
+        assert_eq!(updated_tau.comm_r, full_tau.comm_r);
+        assert_eq!(&replica[..], &full_replica[..]);
+
+        // A replica kept in memory is written to its path, which the replica tree reads.
+        let copy_dir = tempfile::tempdir().unwrap();
+        let (copy_tau, copy_aux) = DrgPoRep::replicate_update(
             &pp,
             &replica_id,
-            mmapped_data.as_mut(),
-            Some(config),
+            &new_data,
+            &mut replica_copy,
+            &[changed_node],
+            store_config(&copy_dir),
+            copy_dir.path().join("replica-path"),
         )
-        .unwrap_or_else(|e| {
-            panic!("Failed to extract data from `DrgPoRep`: {}", e);
-        });
-
-        assert_eq!(data, decoded_data.as_slice(), "failed to extract data");
+        .expect("replica update failed");
+        assert_eq!(copy_tau.comm_r, full_tau.comm_r);
+        assert_eq!(&replica_copy[..], &full_replica[..]);
+        for node in 0..nodes {
+            assert_eq!(
+                copy_aux.tree_r.read_at(node).unwrap().into_bytes(),
+                data_at_node(&full_replica, node).unwrap()
+            );
+        }
 
-        cache_dir.close().expect("Failed to remove cache dir");
+        update_dir.close().expect("Failed to remove cache dir");
+        updated_trees_dir
+            .close()
+            .expect("Failed to remove cache dir");
+        full_dir.close().expect("Failed to remove cache dir");
+        copy_dir.close().expect("Failed to remove cache dir");
     }
 
     #[test]
-    fn extract_all_pedersen() {
-        test_extract_all::<BinaryMerkleTree<PedersenHasher>>();
+    fn replicate_update_first_node_pedersen() {
+        test_replicate_update::<BinaryMerkleTree<PedersenHasher>>(0);
     }
 
     #[test]
-    fn extract_all_sha256() {
-        test_extract_all::<BinaryMerkleTree<Sha256Hasher>>();
+    fn replicate_update_early_node_sha256() {
+        test_replicate_update::<BinaryMerkleTree<Sha256Hasher>>(3);
     }
 
     #[test]
-    fn extract_all_blake2s() {
-        test_extract_all::<BinaryMerkleTree<Blake2sHasher>>();
+    fn replicate_update_last_node_blake2s() {
+        test_replicate_update::<BinaryMerkleTree<Blake2sHasher>>(63);
     }
 
-    fn test_extract<Tree: MerkleTreeTrait>() {
+    #[test]
+    fn porep_encode_decode_roundtrip() {
         let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
 
-        let replica_id: <Tree::Hasher as Hasher>::Domain =
-            <Tree::Hasher as Hasher>::Domain::random(rng);
-        let nodes = 4;
-        let data = vec![2u8; 32 * nodes];
+        for _ in 0..100 {
+            let key = <PedersenHasher as Hasher>::Domain::random(rng);
+            let node = <PedersenHasher as Hasher>::Domain::random(rng);
 
-        // MT for original data is always named tree-d, and it will be
-        // referenced later in the process as such.
-        let cache_dir = tempfile::tempdir().unwrap();
-        let config = StoreConfig::new(
-            cache_dir.path(),
-            CacheKey::CommDTree.to_string(),
-            default_rows_to_discard(nodes, BINARY_ARITY),
-        );
+            let encoded = DrgPoRep::<PedersenHasher, BucketGraph<_>>::encode(&key, &node);
+            let decoded = DrgPoRep::<PedersenHasher, BucketGraph<_>>::decode(&key, &encoded);
 
-        // Generate a replica path.
-        let replica_path = cache_dir.path().join("replica-path");
-        let mut mmapped_data = setup_replica(&data, &replica_path);
+            assert_ne!(encoded, node);
+            assert_eq!(decoded, node);
+        }
+    }
+
+    type OffsetGraph = BucketGraph<PedersenHasher>;
+    type OffsetDomain = <PedersenHasher as Hasher>::Domain;
+
+    /// A scheme overriding the node encoding, which replicates and extracts through the same
+    /// helpers as `DrgPoRep`. Proving is not overridden.
+    struct OffsetPoRep<'a>(PhantomData<&'a ()>);
+
+    impl<'a> ProofScheme<'a> for OffsetPoRep<'a> {
+        type PublicParams = PublicParams<PedersenHasher, OffsetGraph>;
+        type SetupParams = SetupParams;
+        type PublicInputs = PublicInputs<OffsetDomain>;
+        type PrivateInputs = PrivateInputs<'a, PedersenHasher>;
+        type Proof = Proof<PedersenHasher>;
+        type Requirements = NoRequirements;
+
+        fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+            DrgPoRep::<PedersenHasher, OffsetGraph>::setup(sp)
+        }
+
+        fn prove<'b>(
+            _pub_params: &'b Self::PublicParams,
+            _pub_inputs: &'b Self::PublicInputs,
+            _priv_inputs: &'b Self::PrivateInputs,
+        ) -> Result<Self::Proof> {
+            unimplemented!()
+        }
+
+        fn verify(
+            _pub_params: &Self::PublicParams,
+            _pub_inputs: &Self::PublicInputs,
+            _proof: &Self::Proof,
+        ) -> Result<bool> {
+            unimplemented!()
+        }
+    }
+
+    impl<'a> PoRep<'a, PedersenHasher, PedersenHasher> for OffsetPoRep<'a> {
+        type Tau = ();
+        type ProverAux = ();
+
+        fn replicate(
+            pp: &'a Self::PublicParams,
+            replica_id: &OffsetDomain,
+            mut data: Data<'a>,
+            _data_tree: Option<BinaryMerkleTree<PedersenHasher>>,
+            _config: StoreConfig,
+            _replica_path: PathBuf,
+        ) -> Result<(Self::Tau, Self::ProverAux)> {
+            encode_nodes_with_progress(
+                &pp.graph,
+                replica_id,
+                data.as_mut(),
+                Self::encode,
+                |_, _| {},
+            )?;
+
+            Ok(((), ()))
+        }
+
+        fn extract_all(
+            pp: &'a Self::PublicParams,
+            replica_id: &OffsetDomain,
+            replica: &[u8],
+            _config: Option<StoreConfig>,
+        ) -> Result<Vec<u8>> {
+            decode_with(&pp.graph, replica_id, replica, None, Self::decode)
+        }
+
+        fn extract(
+            pp: &'a Self::PublicParams,
+            replica_id: &OffsetDomain,
+            replica: &[u8],
+            node: usize,
+            _config: Option<StoreConfig>,
+        ) -> Result<Vec<u8>> {
+            let decoded =
+                decode_block_with(&pp.graph, replica_id, replica, None, node, Self::decode)?;
+            Ok(decoded.into_bytes())
+        }
+
+        fn encode(key: &OffsetDomain, node: &OffsetDomain) -> OffsetDomain {
+            let mut encoded: Fr = (*node).into();
+            encoded.sub_assign(&(*key).into());
+            encoded.into()
+        }
+
+        fn decode(key: &OffsetDomain, node: &OffsetDomain) -> OffsetDomain {
+            let mut decoded: Fr = (*node).into();
+            decoded.add_assign(&(*key).into());
+            decoded.into()
+        }
+    }
+
+    #[test]
+    fn overridden_encoding_is_used_by_extract() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let nodes = 16;
+        let replica_id = OffsetDomain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
 
         let sp = SetupParams {
             drg: DrgParams {
-                nodes: data.len() / 32,
+                nodes,
                 degree: BASE_DEGREE,
                 expansion_degree: 0,
                 porep_id: [32; 32],
@@ -734,52 +4097,44 @@ mod tests {
             private: false,
             challenges_count: 1,
         };
+        let pp = OffsetPoRep::setup(&sp).expect("setup failed");
 
-        let pp =
-            DrgPoRep::<Tree::Hasher, BucketGraph<Tree::Hasher>>::setup(&sp).expect("setup failed");
-
-        DrgPoRep::replicate(
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let mut replica = data.clone();
+        OffsetPoRep::replicate(
             &pp,
             &replica_id,
-            (mmapped_data.as_mut()).into(),
+            (&mut replica[..]).into(),
             None,
-            config.clone(),
-            replica_path,
+            config,
+            cache_dir.path().join("replica-path"),
         )
         .expect("replication failed");
 
-        let mut copied = vec![0; data.len()];
-        copied.copy_from_slice(&mmapped_data);
-        assert_ne!(data, copied, "replication did not change data");
-
-        for i in 0..nodes {
-            let decoded_data =
-                DrgPoRep::extract(&pp, &replica_id, &mmapped_data, i, Some(config.clone()))
-                    .expect("failed to extract node data from PoRep");
-
-            let original_data = data_at_node(&data, i).unwrap();
-
+        let extracted =
+            OffsetPoRep::extract_all(&pp, &replica_id, &replica, None).expect("failed to extract");
+        assert_eq!(extracted, data);
+        for node in 0..nodes {
+            let extracted_node = OffsetPoRep::extract(&pp, &replica_id, &replica, node, None)
+                .expect("failed to extract node");
             assert_eq!(
-                original_data,
-                decoded_data.as_slice(),
-                "failed to extract data"
+                &extracted_node[..],
+                data_at_node(&extracted, node).unwrap(),
+                "extract and extract_all disagree on node {}",
+                node
             );
         }
-    }
-
-    #[test]
-    fn extract_pedersen() {
-        test_extract::<BinaryMerkleTree<PedersenHasher>>();
-    }
-
-    #[test]
-    fn extract_sha256() {
-        test_extract::<BinaryMerkleTree<Sha256Hasher>>();
-    }
 
-    #[test]
-    fn extract_blake2s() {
-        test_extract::<BinaryMerkleTree<Blake2sHasher>>();
+        // The replica is not the one of the default encoding.
+        let default_extracted =
+            DrgPoRep::<PedersenHasher, OffsetGraph>::extract_all(&pp, &replica_id, &replica, None)
+                .expect("failed to extract");
+        assert_ne!(default_extracted, data);
     }
 
     fn prove_verify_aux<Tree: MerkleTreeTrait>(
@@ -1006,4 +4361,222 @@ mod tests {
         // Challenge a node (3) that doesn't have all the same parents.
         prove_verify_wrong_parents(8, 5);
     }
+
+    fn test_setup_params(challenges_count: usize) -> SetupParams {
+        SetupParams {
+            drg: DrgParams {
+                nodes: 16,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count,
+        }
+    }
+
+    /// A bucket graph in which node 2 also depends on node 3.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct ForwardEdgeGraph(BucketGraph<PedersenHasher>);
+
+    impl ParameterSetMetadata for ForwardEdgeGraph {
+        fn identifier(&self) -> String {
+            format!("ForwardEdgeGraph({})", self.0.identifier())
+        }
+
+        fn sector_size(&self) -> u64 {
+            self.0.sector_size()
+        }
+    }
+
+    impl Graph<PedersenHasher> for ForwardEdgeGraph {
+        type Key = <PedersenHasher as Hasher>::Domain;
+
+        fn parents(&self, node: usize, parents: &mut [u32]) -> Result<()> {
+            self.0.parents(node, parents)?;
+            if node == 2 {
+                parents[0] = 3;
+            }
+            Ok(())
+        }
+
+        fn size(&self) -> usize {
+            self.0.size()
+        }
+
+        fn degree(&self) -> usize {
+            self.0.degree()
+        }
+
+        fn new(
+            nodes: usize,
+            base_degree: usize,
+            expansion_degree: usize,
+            porep_id: [u8; 32],
+        ) -> Result<Self> {
+            BucketGraph::new(nodes, base_degree, expansion_degree, porep_id).map(ForwardEdgeGraph)
+        }
+
+        fn seed(&self) -> [u8; 28] {
+            self.0.seed()
+        }
+
+        fn create_key(
+            &self,
+            id: &<PedersenHasher as Hasher>::Domain,
+            node: usize,
+            parents: &[u32],
+            parents_data: &[u8],
+            exp_parents_data: Option<&[u8]>,
+        ) -> Result<Self::Key> {
+            self.0
+                .create_key(id, node, parents, parents_data, exp_parents_data)
+        }
+    }
+
+    #[test]
+    fn setup_rejects_cyclic_graph() {
+        let sp = test_setup_params(1);
+        let graph = ForwardEdgeGraph::new(16, BASE_DEGREE, 0, [32; 32]).unwrap();
+        assert!(!graph.is_acyclic());
+
+        if cfg!(debug_assertions) {
+            assert!(DrgPoRep::<PedersenHasher, ForwardEdgeGraph>::setup(&sp).is_err());
+        }
+        assert!(DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).is_ok());
+    }
+
+    #[test]
+    fn sector_config_1gib() {
+        let config = SectorConfig::new(1 << 30, NODE_SIZE, BASE_DEGREE).expect("invalid config");
+        assert_eq!(config.nodes, 1 << 25);
+        assert_eq!(config.tree_depth, 25);
+
+        let sp = SetupParams::from_sector_config(&config, [32; 32], false, 2);
+        assert_eq!(sp.drg.nodes, config.nodes);
+        assert_eq!(sp.drg.degree, BASE_DEGREE);
+        assert_eq!(sp.challenges_count, 2);
+    }
+
+    #[test]
+    fn setup_params_builder() {
+        let built = SetupParams::builder()
+            .nodes(64)
+            .node_size(NODE_SIZE)
+            .degree(BASE_DEGREE)
+            .porep_id([32; 32])
+            .challenges_count(2)
+            .build()
+            .expect("invalid setup params");
+        let sp = SetupParams {
+            drg: DrgParams {
+                nodes: 64,
+                degree: BASE_DEGREE,
+                expansion_degree: 0,
+                porep_id: [32; 32],
+            },
+            private: false,
+            challenges_count: 2,
+        };
+        assert_eq!(
+            serde_json::to_string(&built).unwrap(),
+            serde_json::to_string(&sp).unwrap()
+        );
+
+        let defaults = SetupParams::builder().nodes(16).build().unwrap();
+        assert_eq!(defaults.drg.degree, BASE_DEGREE);
+        assert_eq!(defaults.challenges_count, 1);
+        assert!(!defaults.private);
+
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&built).expect("setup failed");
+        assert_eq!(pp.graph.size(), 64);
+
+        let invalid = [
+            SetupParams::builder(),
+            SetupParams::builder().nodes(12),
+            SetupParams::builder().nodes(1),
+            SetupParams::builder().nodes(16).node_size(64),
+            SetupParams::builder().nodes(16).degree(16),
+            SetupParams::builder().nodes(16).degree(0),
+            SetupParams::builder().nodes(16).challenges_count(0),
+        ];
+        for builder in invalid.iter() {
+            assert!(builder.clone().build().is_err(), "built {:?}", builder);
+        }
+    }
+
+    #[test]
+    fn sector_config_rejects_invalid_sizes() {
+        // 3 nodes
+        assert!(SectorConfig::new(3 * NODE_SIZE, NODE_SIZE, BASE_DEGREE).is_err());
+        // Partial node
+        assert!(SectorConfig::new(1024 + 1, NODE_SIZE, BASE_DEGREE).is_err());
+        assert!(SectorConfig::new(1024, 0, BASE_DEGREE).is_err());
+    }
+
+    #[test]
+    fn drg_params_for_security() {
+        let mut last = 0;
+        for log_nodes in 2..=30 {
+            let nodes = 1 << log_nodes;
+            let degree = DrgParams::for_security(nodes, 128).degree;
+
+            assert!(degree >= last, "degree shrank for {} nodes", nodes);
+            assert!(degree < nodes, "degree {} for {} nodes", degree, nodes);
+            last = degree;
+        }
+        assert_eq!(DrgParams::for_security(1 << 30, 128).degree, 30);
+
+        // Higher security levels need more parents.
+        assert!(
+            DrgParams::for_security(1 << 20, 256).degree
+                > DrgParams::for_security(1 << 20, 128).degree
+        );
+        assert_eq!(DrgParams::for_security(3, 256).degree, 2);
+
+        // The recommended parameters set up a working graph.
+        let sp = SetupParams {
+            drg: DrgParams::for_security(64, 128),
+            private: false,
+            challenges_count: 1,
+        };
+        assert!(DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp).is_ok());
+    }
+
+    #[test]
+    fn test_params_serialization_roundtrip() {
+        let sp = test_setup_params(2);
+        let sp_json = serde_json::to_string(&sp).expect("failed to serialize setup params");
+        let sp_back: SetupParams =
+            serde_json::from_str(&sp_json).expect("failed to deserialize setup params");
+        assert_eq!(sp_json, serde_json::to_string(&sp_back).unwrap());
+
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&sp_back).expect("setup failed");
+        let pp_json = serde_json::to_string(&pp).expect("failed to serialize public params");
+        let pp_back: PublicParams<PedersenHasher, BucketGraph<PedersenHasher>> =
+            serde_json::from_str(&pp_json).expect("failed to deserialize public params");
+
+        assert_eq!(pp.graph, pp_back.graph);
+        assert_eq!(pp.private, pp_back.private);
+        assert_eq!(pp.challenges_count, pp_back.challenges_count);
+        assert_eq!(pp.hash(), pp_back.hash());
+    }
+
+    #[test]
+    fn test_params_hash_stability() {
+        let pp = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&test_setup_params(2))
+            .expect("setup failed");
+
+        // Verifiers compare against digests they already hold, so this must not change.
+        assert_eq!(
+            hex::encode(pp.hash()),
+            "6444dd1950c8bab69acc98e1bca903678012a7996cbdc664cefdd36dddead3fa"
+        );
+
+        let other = DrgPoRep::<PedersenHasher, BucketGraph<_>>::setup(&test_setup_params(3))
+            .expect("setup failed");
+        assert_ne!(pp.hash(), other.hash());
+        // The parameter cache keys stay the same.
+        assert_eq!(pp.identifier(), other.identifier());
+    }
 }
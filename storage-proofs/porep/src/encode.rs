@@ -1,19 +1,66 @@
-use ff::Field;
 use paired::bls12_381::Fr;
-use storage_proofs_core::hasher::Domain;
+use storage_proofs_core::{crypto::sloth, hasher::Domain};
+
+// The non-circuit encoding must stay in lockstep with `storage_proofs_core::gadgets::encode`,
+// which is what the circuits use to check it.
 
 pub fn encode<T: Domain>(key: T, value: T) -> T {
-    let mut result: Fr = value.into();
+    let value: Fr = value.into();
     let key: Fr = key.into();
 
-    result.add_assign(&key);
-    result.into()
+    sloth::encode(&key, &value).into()
 }
 
 pub fn decode<T: Domain>(key: T, value: T) -> T {
-    let mut result: Fr = value.into();
+    let value: Fr = value.into();
     let key: Fr = key.into();
 
-    result.sub_assign(&key);
-    result.into()
+    sloth::decode(&key, &value).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::gadgets::num::AllocatedNum;
+    use bellperson::util_cs::test_cs::TestConstraintSystem;
+    use bellperson::ConstraintSystem;
+    use ff::Field;
+    use paired::bls12_381::Bls12;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{gadgets, hasher::PedersenDomain};
+
+    #[test]
+    fn encode_decode_match_circuit() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        for i in 0..10 {
+            let key = Fr::random(rng);
+            let value = Fr::random(rng);
+
+            let encoded = encode::<PedersenDomain>(key.into(), value.into());
+            let decoded = decode::<PedersenDomain>(key.into(), encoded);
+            let encoded: Fr = encoded.into();
+            let decoded: Fr = decoded.into();
+            assert_eq!(value, decoded);
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let key_num = AllocatedNum::alloc(cs.namespace(|| "key"), || Ok(key)).unwrap();
+            let value_num = AllocatedNum::alloc(cs.namespace(|| "value"), || Ok(value)).unwrap();
+
+            let encoded_num =
+                gadgets::encode::encode(cs.namespace(|| "encode"), &key_num, &value_num).unwrap();
+            let decoded_num =
+                gadgets::encode::decode(cs.namespace(|| "decode"), &key_num, &encoded_num).unwrap();
+
+            assert!(
+                cs.is_satisfied(),
+                "constraints not satisfied in round {}",
+                i
+            );
+            assert_eq!(encoded, encoded_num.get_value().unwrap());
+            assert_eq!(value, decoded_num.get_value().unwrap());
+        }
+    }
 }
@@ -37,6 +37,19 @@ pub trait PoRep<'a, H: Hasher, G: Hasher>: ProofScheme<'a> {
         node: usize,
         config: Option<StoreConfig>,
     ) -> Result<Vec<u8>>;
+
+    /// Encodes a single `node` under `key`. This is the inverse of `decode`.
+    ///
+    /// `replicate` encodes every node with it and `extract`/`extract_all` decode with `decode`,
+    /// so schemes overriding the pair must keep their circuits in sync with it.
+    fn encode(key: &H::Domain, node: &H::Domain) -> H::Domain {
+        encode::encode(*key, *node)
+    }
+
+    /// Decodes a single `node` that was encoded under `key`.
+    fn decode(key: &H::Domain, node: &H::Domain) -> H::Domain {
+        encode::decode(*key, *node)
+    }
 }
 
 #[cfg(test)]
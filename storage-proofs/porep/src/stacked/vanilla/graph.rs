@@ -436,6 +436,10 @@ mod tests {
 
     use std::collections::HashSet;
 
+    use storage_proofs_core::hasher::PedersenHasher;
+
+    use crate::drg::DrgParams;
+
     // Test that 3 (or more) rounds of the Feistel cipher can be used
     // as a pseudorandom permutation, that is, each input will be mapped
     // to a unique output (and though not test here, since the cipher
@@ -474,4 +478,44 @@ mod tests {
         // have skipped as duplicates).
         assert_eq!(shuffled.len(), (n * d) as usize);
     }
+
+    #[test]
+    fn test_parents_split_base_and_expansion() {
+        let params = DrgParams {
+            nodes: 1024,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [32; 32],
+        };
+        let graph = StackedBucketGraph::<PedersenHasher>::new_stacked(
+            params.nodes,
+            params.degree,
+            params.expansion_degree,
+            params.porep_id,
+        )
+        .unwrap();
+        assert_eq!(graph.degree(), params.total_degree());
+
+        let mut parents = vec![0; graph.degree()];
+        let mut base_parents = vec![0; params.degree];
+        let mut exp_parents = vec![0; params.expansion_degree];
+        for node in 0..params.nodes {
+            graph.parents(node, &mut parents).unwrap();
+            graph.base_parents(node, &mut base_parents).unwrap();
+            graph.expanded_parents(node, &mut exp_parents).unwrap();
+
+            assert_eq!(&parents[..params.degree], &base_parents[..]);
+            assert_eq!(&parents[params.degree..], &exp_parents[..]);
+
+            // Base parents come before the node in its own layer, only the first node
+            // references itself.
+            for parent in &base_parents {
+                assert!((*parent as usize) < node || (node == 0 && *parent == 0));
+            }
+            // Expansion parents may be any node of the previous layer.
+            for parent in &exp_parents {
+                assert!((*parent as usize) < params.nodes);
+            }
+        }
+    }
 }
@@ -12,7 +12,7 @@ use storage_proofs_core::{
     drgraph::Graph,
     error::Result,
     fr32::bytes_into_fr_repr_safe,
-    hasher::{Domain, Hasher},
+    hasher::{Domain, HashFunction, Hasher},
     merkle::*,
     parameter_cache::ParameterSetMetadata,
     util::data_at_node,
@@ -237,6 +237,13 @@ impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         layer_challenges: &LayerChallenges,
     ) -> bool {
+        // A proof for a different number of layers must not verify.
+        check_eq!(self.labeling_proofs.len(), layer_challenges.layers());
+        check_eq!(
+            self.replica_column_proofs.c_x.column.rows().len(),
+            layer_challenges.layers()
+        );
+
         // Verify Labels Layer 1..layers
         for layer in 1..=layer_challenges.layers() {
             trace!("verify labeling (layer: {})", layer,);
@@ -323,10 +330,21 @@ pub struct Tau<D: Domain, E: Domain> {
 /// Stored along side the sector on disk.
 #[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PersistentAux<D> {
+    /// Root of the tree over the columns of the labels of all layers.
     pub comm_c: D,
+    /// Root of the tree over the final replica.
     pub comm_r_last: D,
 }
 
+impl<D: Domain> PersistentAux<D> {
+    /// The replica commitment these combine into, `comm_r = H(comm_c || comm_r_last)` with
+    /// the `hash2` of the tree hasher. This is what `Tau::comm_r` holds, the verifier gets
+    /// `comm_c` and `comm_r_last` from the proofs and checks them against it.
+    pub fn comm_r<H: Hasher<Domain = D>>(&self) -> D {
+        H::Function::hash2(&self.comm_c, &self.comm_r_last)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TemporaryAux<Tree: MerkleTreeTrait, G: Hasher> {
     /// The encoded nodes for 1..layers.
@@ -1,6 +1,7 @@
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc, RwLock};
 
@@ -18,7 +19,7 @@ use storage_proofs_core::{
     data::Data,
     drgraph::Graph,
     error::Result,
-    hasher::{Domain, HashFunction, Hasher, PoseidonArity},
+    hasher::{Domain, Hasher, PoseidonArity},
     measurements::{
         measure_op,
         Operation::{CommD, EncodeWindowTimeAll, GenerateTreeC, GenerateTreeRLast},
@@ -70,6 +71,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         layer_challenges: &LayerChallenges,
         layers: usize,
         _total_layers: usize,
+        partitions: Range<usize>,
         partition_count: usize,
     ) -> Result<Vec<Vec<Proof<Tree, G>>>> {
         assert!(layers > 0);
@@ -105,7 +107,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             parents.iter().map(|parent| t_aux.column(*parent)).collect()
         };
 
-        (0..partition_count)
+        partitions
             .map(|k| {
                 trace!("proving partition {}/{}", k + 1, partition_count);
 
@@ -1100,19 +1102,17 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
         data.drop_data();
 
-        // comm_r = H(comm_c || comm_r_last)
-        let comm_r: <Tree::Hasher as Hasher>::Domain =
-            <Tree::Hasher as Hasher>::Function::hash2(&tree_c_root, &tree_r_last_root);
+        let p_aux = PersistentAux {
+            comm_c: tree_c_root,
+            comm_r_last: tree_r_last_root,
+        };
 
         Ok((
             Tau {
                 comm_d: tree_d_root,
-                comm_r,
-            },
-            PersistentAux {
-                comm_c: tree_c_root,
-                comm_r_last: tree_r_last_root,
+                comm_r: p_aux.comm_r::<Tree::Hasher>(),
             },
+            p_aux,
             TemporaryAux {
                 labels: label_configs,
                 tree_d_config,
@@ -1317,16 +1317,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let tree_r_last_root = tree_r_last.root();
         drop(tree_r_last);
 
-        // comm_r = H(comm_c || comm_r_last)
-        let comm_r: <Tree::Hasher as Hasher>::Domain =
-            <Tree::Hasher as Hasher>::Function::hash2(&tree_c_root, &tree_r_last_root);
-
         let p_aux = PersistentAux {
             comm_c: tree_c_root,
             comm_r_last: tree_r_last_root,
         };
 
-        Ok((comm_r, p_aux))
+        Ok((p_aux.comm_r::<Tree::Hasher>(), p_aux))
     }
 }
 
@@ -1348,7 +1344,7 @@ mod tests {
         test_helper::setup_replica,
     };
 
-    use crate::stacked::{PrivateInputs, SetupParams, EXP_DEGREE};
+    use crate::stacked::{PrivateInputs, SetupParams, EXP_DEGREE, OCT_ARITY};
     use crate::PoRep;
 
     const DEFAULT_STACKED_LAYERS: usize = 11;
@@ -1492,6 +1488,96 @@ mod tests {
         cache_dir.close().expect("Failed to remove cache dir");
     }
 
+    #[test]
+    fn comm_r_combines_comm_c_and_comm_r_last() {
+        type Tree = DiskTree<PedersenHasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let replica_id = <PedersenHasher as Hasher>::Domain::random(rng);
+        let nodes = 64;
+        let layers = 2;
+
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| <PedersenHasher as Hasher>::Domain::random(rng).into_bytes())
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [32; 32],
+            layer_challenges: LayerChallenges::new(layers, 5),
+        };
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+        let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, Blake2sHasher>::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path,
+        )
+        .expect("replication failed");
+
+        assert_eq!(tau.comm_r, p_aux.comm_r::<PedersenHasher>());
+        let tampered_r_last = PersistentAux {
+            comm_r_last: p_aux.comm_c,
+            ..p_aux.clone()
+        };
+        assert_ne!(tau.comm_r, tampered_r_last.comm_r::<PedersenHasher>());
+
+        // Rebuilds tree_c from the labels on disk, away from the replication cache.
+        let comm_c = || {
+            let tree_c_dir = tempfile::tempdir().unwrap();
+            let mut tree_c_config = StoreConfig::new(
+                tree_c_dir.path(),
+                CacheKey::CommCTree.to_string(),
+                default_rows_to_discard(nodes, OCT_ARITY),
+            );
+            tree_c_config.size = Some(get_merkle_tree_len(nodes, OCT_ARITY).unwrap());
+            let labels = LabelsCache::<Tree>::new(&t_aux.labels).expect("failed to open labels");
+            let tree_c = StackedDrg::<Tree, Blake2sHasher>::generate_tree_c::<U2, typenum::U8>(
+                layers,
+                nodes,
+                1,
+                split_config(tree_c_config, 1).unwrap(),
+                &labels,
+            )
+            .expect("failed to build tree_c");
+
+            tree_c.root()
+        };
+        assert_eq!(comm_c(), p_aux.comm_c);
+
+        // Changing a single label of any layer changes comm_c.
+        for layer in 0..layers {
+            let label_config = &t_aux.labels.labels[layer];
+            let label_path = StoreConfig::data_path(&label_config.path, &label_config.id);
+            let mut label_data = std::fs::read(&label_path).unwrap();
+            let original = label_data.clone();
+            label_data[..NODE_SIZE]
+                .copy_from_slice(&<PedersenHasher as Hasher>::Domain::random(rng).into_bytes());
+            std::fs::write(&label_path, &label_data).unwrap();
+
+            assert_ne!(comm_c(), p_aux.comm_c, "tampering layer {}", layer + 1);
+
+            std::fs::write(&label_path, &original).unwrap();
+        }
+        assert_eq!(comm_c(), p_aux.comm_c);
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
     fn prove_verify_fixed(n: usize) {
         let challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
 
@@ -1703,6 +1789,197 @@ mod tests {
         }
     }
 
+    #[test]
+    fn prove_verify_wrong_layers() {
+        type Tree = DiskTree<PoseidonHasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        let nodes = 64 * get_base_tree_count::<Tree>();
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let replica_id: <PoseidonHasher as Hasher>::Domain =
+            <PoseidonHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let setup_params = |layers| SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [92; 32],
+            layer_challenges: LayerChallenges::new(layers, 2),
+        };
+
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&setup_params(3)).expect("setup failed");
+        let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, Blake2sHasher>::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path.clone(),
+        )
+        .expect("replication failed");
+
+        let pub_inputs =
+            PublicInputs::<<PoseidonHasher as Hasher>::Domain, <Blake2sHasher as Hasher>::Domain> {
+                replica_id,
+                seed: rng.gen(),
+                tau: Some(tau),
+                k: None,
+            };
+
+        let t_aux_orig = t_aux.clone();
+        let t_aux = TemporaryAuxCache::<Tree, Blake2sHasher>::new(&t_aux, replica_path)
+            .expect("failed to restore contents of t_aux");
+        let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+        let proofs = StackedDrg::<Tree, Blake2sHasher>::prove_all_partitions(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            1,
+        )
+        .expect("failed to generate partition proofs");
+
+        assert!(StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+            &pp,
+            &pub_inputs,
+            &proofs
+        )
+        .expect("failed to verify partition proofs"));
+
+        let fewer_layers_pp =
+            StackedDrg::<Tree, Blake2sHasher>::setup(&setup_params(2)).expect("setup failed");
+        assert!(
+            !StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+                &fewer_layers_pp,
+                &pub_inputs,
+                &proofs
+            )
+            .expect("failed to verify partition proofs"),
+            "proof for 3 layers verified against 2 layers"
+        );
+
+        assert!(
+            StackedDrg::<Tree, Blake2sHasher>::setup(&setup_params(0)).is_err(),
+            "setup accepted zero layers"
+        );
+
+        TemporaryAux::<Tree, Blake2sHasher>::clear_temp(t_aux_orig).expect("t_aux delete failed");
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn prove_partitions_separately() {
+        type Tree = DiskTree<PoseidonHasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        let nodes = 64 * get_base_tree_count::<Tree>();
+        let partitions = 2;
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let replica_id: <PoseidonHasher as Hasher>::Domain =
+            <PoseidonHasher as Hasher>::Domain::random(rng);
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [93; 32],
+            layer_challenges: LayerChallenges::new(2, 2),
+        };
+
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+        let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, Blake2sHasher>::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config,
+            replica_path.clone(),
+        )
+        .expect("replication failed");
+
+        let pub_inputs =
+            PublicInputs::<<PoseidonHasher as Hasher>::Domain, <Blake2sHasher as Hasher>::Domain> {
+                replica_id,
+                seed: rng.gen(),
+                tau: Some(tau),
+                k: None,
+            };
+
+        let t_aux_orig = t_aux.clone();
+        let t_aux = TemporaryAuxCache::<Tree, Blake2sHasher>::new(&t_aux, replica_path)
+            .expect("failed to restore contents of t_aux");
+        let priv_inputs = PrivateInputs { p_aux, t_aux };
+
+        let partition_proofs = (0..partitions)
+            .map(|k| {
+                StackedDrg::<Tree, Blake2sHasher>::prove_partition(
+                    &pp,
+                    &pub_inputs,
+                    &priv_inputs,
+                    k,
+                    partitions,
+                )
+                .expect("failed to prove partition")
+            })
+            .collect::<Vec<_>>();
+
+        assert!(StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(
+            &pp,
+            &pub_inputs,
+            &partition_proofs
+        )
+        .expect("failed to verify partition proofs"));
+
+        // Each partition is bound to its own challenges, so the order matters.
+        let swapped = vec![partition_proofs[1].clone(), partition_proofs[0].clone()];
+        assert!(
+            !StackedDrg::<Tree, Blake2sHasher>::verify_all_partitions(&pp, &pub_inputs, &swapped)
+                .expect("failed to verify partition proofs"),
+            "swapped partition proofs verified"
+        );
+
+        assert!(
+            StackedDrg::<Tree, Blake2sHasher>::prove_partition(
+                &pp,
+                &pub_inputs,
+                &priv_inputs,
+                partitions,
+                partitions
+            )
+            .is_err(),
+            "proved a partition out of range"
+        );
+
+        TemporaryAux::<Tree, Blake2sHasher>::clear_temp(t_aux_orig).expect("t_aux delete failed");
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
     #[test]
     // We are seeing a bug, in which setup never terminates for some sector sizes.
     // This test is to debug that and should remain as a regression teset.
@@ -2,17 +2,13 @@ use anyhow::ensure;
 use log::trace;
 use rayon::prelude::*;
 use storage_proofs_core::{
-    drgraph::Graph,
-    error::Result,
-    hasher::{HashFunction, Hasher},
-    merkle::MerkleTreeTrait,
-    proof::ProofScheme,
+    drgraph::Graph, error::Result, hasher::Hasher, merkle::MerkleTreeTrait, proof::ProofScheme,
 };
 
 use super::{
     challenges::ChallengeRequirements,
     graph::StackedBucketGraph,
-    params::{PrivateInputs, Proof, PublicInputs, PublicParams, SetupParams},
+    params::{PersistentAux, PrivateInputs, Proof, PublicInputs, PublicParams, SetupParams},
     proof::StackedDrg,
 };
 
@@ -27,6 +23,11 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
     type Requirements = ChallengeRequirements;
 
     fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        ensure!(
+            sp.layer_challenges.layers() >= 1,
+            "there must be at least one layer"
+        );
+
         let graph = StackedBucketGraph::<Tree::Hasher>::new_stacked(
             sp.nodes,
             sp.degree,
@@ -75,10 +76,43 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
             &pub_params.layer_challenges,
             pub_params.layer_challenges.layers(),
             pub_params.layer_challenges.layers(),
+            0..partition_count,
             partition_count,
         )
     }
 
+    /// Proves only partition `k` out of `partition_count`, so that partitions can be proven
+    /// independently (e.g. on different machines). The resulting proofs, collected in partition
+    /// order, verify with `verify_all_partitions`.
+    fn prove_partition<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+        k: usize,
+        partition_count: usize,
+    ) -> Result<Self::Proof> {
+        ensure!(
+            k < partition_count,
+            "partition {} out of range for {} partitions",
+            k,
+            partition_count
+        );
+
+        let mut proofs = Self::prove_layers(
+            &pub_params.graph,
+            pub_inputs,
+            &priv_inputs.p_aux,
+            &priv_inputs.t_aux,
+            &pub_params.layer_challenges,
+            pub_params.layer_challenges.layers(),
+            pub_params.layer_challenges.layers(),
+            k..k + 1,
+            partition_count,
+        )?;
+
+        Ok(proofs.remove(0))
+    }
+
     fn verify_all_partitions(
         pub_params: &Self::PublicParams,
         pub_inputs: &Self::PublicInputs,
@@ -103,11 +137,11 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
             );
 
             trace!("verify comm_r");
-            let actual_comm_r: <Tree::Hasher as Hasher>::Domain = {
-                let comm_c = proofs[0].comm_c();
-                let comm_r_last = proofs[0].comm_r_last();
-                <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last)
-            };
+            let actual_comm_r = PersistentAux {
+                comm_c: proofs[0].comm_c(),
+                comm_r_last: proofs[0].comm_r_last(),
+            }
+            .comm_r::<Tree::Hasher>();
 
             if expected_comm_r != &actual_comm_r {
                 return false;
@@ -230,6 +230,35 @@ pub fn generate_leaf_challenge<T: Domain>(
     Ok(challenged_range_index)
 }
 
+/// Samples `challenges_per_sector` node challenges for each of `sector_count` sectors, all derived
+/// from the single `seed`. Returns `(sector_index, node_index)` pairs, grouped by sector.
+pub fn sample_challenges<T: Domain>(
+    seed: T,
+    sector_count: usize,
+    challenges_per_sector: usize,
+    nodes_per_sector: usize,
+) -> Result<Vec<(usize, usize)>> {
+    ensure!(nodes_per_sector > 0, "sectors must have at least one node");
+
+    let mut challenges = Vec::with_capacity(sector_count * challenges_per_sector);
+    for sector_index in 0..sector_count {
+        for challenge_index in 0..challenges_per_sector {
+            let mut hasher = Sha256::new();
+            hasher.input(AsRef::<[u8]>::as_ref(&seed));
+            hasher.input(&(sector_index as u64).to_le_bytes()[..]);
+            hasher.input(&(challenge_index as u64).to_le_bytes()[..]);
+            let hash = hasher.result();
+
+            let node_challenge = LittleEndian::read_u64(&hash.as_ref()[..8]);
+            let node_index = (node_challenge % nodes_per_sector as u64) as usize;
+
+            challenges.push((sector_index, node_index));
+        }
+    }
+
+    Ok(challenges)
+}
+
 impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree> {
     type PublicParams = PublicParams;
     type SetupParams = SetupParams;
@@ -485,6 +514,49 @@ mod tests {
         merkle::{generate_tree, get_base_tree_count, LCTree, MerkleTreeTrait},
     };
 
+    #[test]
+    fn sample_challenges_reproducible_and_in_range() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let seed = <PedersenHasher as Hasher>::Domain::random(rng);
+        let (sector_count, challenges_per_sector, nodes_per_sector) = (5, 10, 64);
+
+        let challenges =
+            sample_challenges(seed, sector_count, challenges_per_sector, nodes_per_sector)
+                .expect("failed to sample challenges");
+        assert_eq!(challenges.len(), sector_count * challenges_per_sector);
+
+        for sector_index in 0..sector_count {
+            let sector_challenges = challenges
+                .iter()
+                .filter(|(sector, _)| *sector == sector_index)
+                .collect::<Vec<_>>();
+            assert_eq!(sector_challenges.len(), challenges_per_sector);
+            assert!(sector_challenges
+                .iter()
+                .all(|(_, node)| *node < nodes_per_sector));
+        }
+
+        let again = sample_challenges(seed, sector_count, challenges_per_sector, nodes_per_sector)
+            .expect("failed to sample challenges");
+        assert_eq!(challenges, again, "sampling is not reproducible");
+
+        let other_seed = <PedersenHasher as Hasher>::Domain::random(rng);
+        let other = sample_challenges(
+            other_seed,
+            sector_count,
+            challenges_per_sector,
+            nodes_per_sector,
+        )
+        .expect("failed to sample challenges");
+        assert_ne!(
+            challenges, other,
+            "different seeds gave the same challenges"
+        );
+
+        assert!(sample_challenges(seed, sector_count, challenges_per_sector, 0).is_err());
+    }
+
     fn test_fallback_post<Tree: MerkleTreeTrait>(
         total_sector_count: usize,
         sector_count: usize,